@@ -0,0 +1,73 @@
+// Sprite-heavy workloads, for comparing changes to how the screen is kept:
+//
+//     cargo bench --bench draw
+//
+// Prints the time per instruction of a program that does little but draw,
+// and per frame of a frontend that runs it and reads the display.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use chip8_core::{Emu, Variant};
+
+const INSTRUCTIONS: u32 = 2_000_000;
+const FRAMES: u32 = 20_000;
+const INSTRUCTIONS_PER_FRAME: u32 = 1000;
+
+// Draws 15-row sprites over the whole screen, never byte aligned for long:
+//
+//         LD V0, 0
+//         LD V1, 0
+//     loop:
+//         LD I, 0x000
+//         DRW V0, V1, 15
+//         ADD V0, 3
+//         ADD V1, 5
+//         JP loop
+const LORES: [u8; 14] = [
+    0x60, 0x00, 0x61, 0x00, 0xA0, 0x00, 0xD0, 0x1F, 0x70, 0x03, 0x71, 0x05, 0x12, 0x04,
+];
+
+// The same in high resolution, looping at 0x206
+const HIRES: [u8; 16] = [
+    0x00, 0xFF, 0x60, 0x00, 0x61, 0x00, 0xA0, 0x00, 0xD0, 0x1F, 0x70, 0x03, 0x71, 0x05, 0x12, 0x06,
+];
+
+fn main() {
+    for (name, program) in [("lores", &LORES[..]), ("hires", &HIRES[..])] {
+        let per_instruction = draw(program) / INSTRUCTIONS;
+        let per_frame = frames(program) / FRAMES;
+        println!(
+            "{}: {:?} per instruction, {:?} per frame of {} instructions and a display read",
+            name, per_instruction, per_frame, INSTRUCTIONS_PER_FRAME
+        );
+    }
+}
+
+fn machine(program: &[u8]) -> Emu {
+    let mut emu = Emu::with_variant(Variant::SuperChipModern);
+    let mut quirks = emu.quirks();
+    quirks.display_wait = false;
+    emu.set_quirks(quirks);
+    emu.load(program).unwrap();
+    emu
+}
+
+fn draw(program: &[u8]) -> Duration {
+    let mut emu = machine(program);
+    let start = Instant::now();
+    for _ in 0..INSTRUCTIONS {
+        black_box(emu.tick().unwrap());
+    }
+    start.elapsed()
+}
+
+fn frames(program: &[u8]) -> Duration {
+    let mut emu = machine(program);
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        emu.run_frame(INSTRUCTIONS_PER_FRAME).unwrap();
+        black_box(emu.get_display().filter(|&lit| lit).count());
+    }
+    start.elapsed()
+}
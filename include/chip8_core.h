@@ -0,0 +1,64 @@
+/* C API for chip8_core, built with the `ffi` feature. See src/ffi.rs. */
+
+#ifndef CHIP8_CORE_H
+#define CHIP8_CORE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define CHIP8_ERROR (-1)
+
+enum {
+    CHIP8_VARIANT_COSMAC_VIP = 0,
+    CHIP8_VARIANT_CHIP48 = 1,
+    CHIP8_VARIANT_SCHIP_LEGACY = 2,
+    CHIP8_VARIANT_SCHIP_MODERN = 3,
+    CHIP8_VARIANT_XO_CHIP = 4,
+};
+
+enum {
+    CHIP8_RUNNING = 0,
+    CHIP8_WAITING_FOR_VBLANK = 1,
+    CHIP8_HALTED = 2,
+    CHIP8_SPINNING = 3,
+};
+
+typedef struct Chip8 Chip8;
+
+Chip8 *chip8_create(void);
+/* NULL for an unknown variant */
+Chip8 *chip8_create_variant(int32_t variant);
+void chip8_destroy(Chip8 *chip8);
+void chip8_reset(Chip8 *chip8);
+
+/* Loads at 0x200, false if the ROM is empty or doesn't fit in memory */
+bool chip8_load_rom(Chip8 *chip8, const uint8_t *data, size_t len);
+
+/* Runs one instruction, returns a CHIP8_RUNNING... state or CHIP8_ERROR */
+int32_t chip8_tick(Chip8 *chip8);
+/* Runs up to n instructions, stopping early on halts and waits. Returns how
+ * many ran or CHIP8_ERROR. */
+int64_t chip8_tick_many(Chip8 *chip8, uint32_t n);
+/* Call at 60Hz */
+void chip8_tick_timers(Chip8 *chip8);
+
+/* Keys 0x0-0xF, others are ignored */
+void chip8_keypress(Chip8 *chip8, uint8_t key, bool pressed);
+
+/* width * height pixels, row by row, valid until the next call to this */
+const bool *chip8_get_display(Chip8 *chip8);
+size_t chip8_display_width(const Chip8 *chip8);
+size_t chip8_display_height(const Chip8 *chip8);
+
+bool chip8_is_sound_active(const Chip8 *chip8);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
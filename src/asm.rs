@@ -0,0 +1,582 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::Instruction;
+
+pub mod octo;
+
+const ORIGIN: u16 = 0x200;
+
+// Assembles the mnemonics printed by the disassembler into a ROM image loaded at 0x200.
+//
+//     ; comments start with a semicolon
+//     start:  LD V0, 0x0A
+//             CALL draw
+//     loop:   JP loop
+//     draw:   LD I, sprite
+//             DRW V0, V0, 5
+//             RET
+//     sprite: DB 0xF0, 0x90, 0x90, 0x90, 0xF0
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    let statements = parse(source)?;
+
+    // First pass: lay out the program to find label addresses
+    let mut symbols = BTreeMap::new();
+    let mut addr = ORIGIN as usize;
+    for statement in &statements {
+        for label in &statement.labels {
+            if symbols
+                .insert(label.text.to_string(), addr as u16)
+                .is_some()
+            {
+                return Err(label.error(AsmErrorKind::DuplicateLabel(label.text.to_string())));
+            }
+        }
+
+        addr += statement.size();
+        if addr > crate::MAX_RAM_SIZE {
+            return Err(statement.mnemonic.error(AsmErrorKind::ProgramTooLarge));
+        }
+    }
+
+    // Second pass: encode with every label known
+    let mut bytes = Vec::new();
+    for statement in &statements {
+        statement.emit(&symbols, &mut bytes)?;
+    }
+
+    Ok(Program { bytes, symbols })
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Program {
+    pub bytes: Vec<u8>,
+    pub symbols: BTreeMap<String, u16>, // label name -> address
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,   // 1-based
+    pub column: usize, // 1-based
+    pub kind: AsmErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AsmErrorKind {
+    UnknownMnemonic(String),
+    InvalidOperands,
+    InvalidNumber(String),
+    ValueOutOfRange(u32),
+    UndefinedLabel(String),
+    DuplicateLabel(String),
+    ProgramTooLarge,
+    UnexpectedToken(String),
+    UnexpectedEnd,
+    UnbalancedBlock,
+    Unsupported(String),
+    RecursiveMacro(String),
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+
+        match &self.kind {
+            AsmErrorKind::UnknownMnemonic(name) => write!(f, "unknown mnemonic '{}'", name),
+            AsmErrorKind::InvalidOperands => write!(f, "invalid operands"),
+            AsmErrorKind::InvalidNumber(text) => write!(f, "invalid number '{}'", text),
+            AsmErrorKind::ValueOutOfRange(value) => write!(f, "value {:#X} out of range", value),
+            AsmErrorKind::UndefinedLabel(name) => write!(f, "undefined label '{}'", name),
+            AsmErrorKind::DuplicateLabel(name) => write!(f, "duplicate label '{}'", name),
+            AsmErrorKind::ProgramTooLarge => write!(f, "program doesn't fit in memory"),
+            AsmErrorKind::UnexpectedToken(text) => write!(f, "unexpected '{}'", text),
+            AsmErrorKind::UnexpectedEnd => write!(f, "unexpected end of source"),
+            AsmErrorKind::UnbalancedBlock => write!(f, "unbalanced block"),
+            AsmErrorKind::Unsupported(text) => write!(f, "'{}' isn't supported", text),
+            AsmErrorKind::RecursiveMacro(name) => write!(f, "macro '{}' expands itself", name),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AsmError {}
+
+// A piece of source text with its position, for error reporting
+#[derive(Clone, Copy, Debug)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Token<'a> {
+    // `text` must be a slice of `line`
+    fn within(line: &'a str, line_number: usize, text: &'a str) -> Token<'a> {
+        let leading_space = text.len() - text.trim_start().len();
+
+        Token {
+            text: text.trim(),
+            line: line_number,
+            column: text.as_ptr() as usize - line.as_ptr() as usize + leading_space + 1,
+        }
+    }
+
+    fn error(&self, kind: AsmErrorKind) -> AsmError {
+        AsmError {
+            line: self.line,
+            column: self.column,
+            kind,
+        }
+    }
+}
+
+struct Statement<'a> {
+    labels: Vec<Token<'a>>,
+    mnemonic: Token<'a>,
+    operands: Vec<Token<'a>>,
+}
+
+fn parse(source: &str) -> Result<Vec<Statement<'_>>, AsmError> {
+    let mut statements = Vec::new();
+    let mut labels = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let token = |text| Token::within(line, index + 1, text);
+
+        let mut rest = line.split(';').next().unwrap_or("");
+
+        // Any number of `label:` prefixes
+        while let Some((label, after)) = rest.split_once(':') {
+            let label = token(label);
+            if !is_identifier(label.text) {
+                return Err(label.error(AsmErrorKind::InvalidOperands));
+            }
+            labels.push(label);
+            rest = after;
+        }
+
+        let rest = rest.trim_start();
+        if rest.trim().is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = match rest.find(char::is_whitespace) {
+            Some(split) => (&rest[..split], &rest[split..]),
+            None => (rest, ""),
+        };
+        let operands = if operands.trim().is_empty() {
+            Vec::new()
+        } else {
+            operands.split(',').map(token).collect()
+        };
+
+        statements.push(Statement {
+            labels: core::mem::take(&mut labels),
+            mnemonic: token(mnemonic),
+            operands,
+        });
+    }
+
+    // Labels at the very end point past the last statement
+    if !labels.is_empty() {
+        let end = Token {
+            text: "",
+            line: labels[0].line,
+            column: labels[0].column,
+        };
+        statements.push(Statement {
+            labels,
+            mnemonic: end,
+            operands: Vec::new(),
+        });
+    }
+
+    Ok(statements)
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operand {
+    Reg(u8),
+    RegRange(u8, u8), // Vx - Vy
+    Value(u32),
+    I,
+    IndirectI, // [I]
+    LongValue(u32),
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+}
+
+impl<'a> Statement<'a> {
+    fn mnemonic(&self) -> String {
+        self.mnemonic.text.to_ascii_uppercase()
+    }
+
+    // Size in bytes, computable before labels are resolved
+    fn size(&self) -> usize {
+        match self.mnemonic().as_str() {
+            "" => 0,
+            "DB" => self.operands.len(),
+            "DW" => self.operands.len() * 2,
+            "LD" if self.operands.len() == 2 && starts_with_long(self.operands[1].text) => 4,
+            _ => 2,
+        }
+    }
+
+    fn emit(&self, symbols: &BTreeMap<String, u16>, bytes: &mut Vec<u8>) -> Result<(), AsmError> {
+        let mnemonic = self.mnemonic();
+
+        match mnemonic.as_str() {
+            "" => return Ok(()),
+            "DB" => {
+                for operand in &self.operands {
+                    let value = resolve_value(operand, symbols)?;
+                    bytes.push(check_range(operand, value, 0xFF)? as u8);
+                }
+                return Ok(());
+            }
+            "DW" => {
+                for operand in &self.operands {
+                    let value = resolve_value(operand, symbols)?;
+                    let value = check_range(operand, value, 0xFFFF)? as u16;
+                    bytes.extend_from_slice(&value.to_be_bytes());
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let mut operands = Vec::with_capacity(self.operands.len());
+        for token in &self.operands {
+            operands.push(parse_operand(token, symbols)?);
+        }
+
+        let instruction = self.instruction(&mnemonic, &operands)?;
+        let mut encoded = [0; 4];
+        let size = instruction.encode_into(&mut encoded);
+        bytes.extend_from_slice(&encoded[..size]);
+
+        Ok(())
+    }
+
+    fn instruction(&self, mnemonic: &str, operands: &[Operand]) -> Result<Instruction, AsmError> {
+        use Operand::*;
+
+        let invalid = || self.mnemonic.error(AsmErrorKind::InvalidOperands);
+        // Checks a numeric operand against its field width
+        let value = |index: usize, max: u32| -> Result<u16, AsmError> {
+            match operands[index] {
+                Value(value) => Ok(check_range(&self.operands[index], value, max)? as u16),
+                _ => Err(invalid()),
+            }
+        };
+
+        let instruction = match (mnemonic, operands) {
+            ("NOP", []) => Instruction::Nop,
+            ("CLS", []) => Instruction::Cls,
+            ("RET", []) => Instruction::Ret,
+            ("SCD", [Value(_)]) => Instruction::ScrollDown(value(0, 0xF)? as u8),
+            ("SCU", [Value(_)]) => Instruction::ScrollUp(value(0, 0xF)? as u8),
+            ("SCR", []) => Instruction::ScrollRight,
+            ("SCL", []) => Instruction::ScrollLeft,
+            ("EXIT", []) => Instruction::Exit,
+            ("LOW", []) => Instruction::Lores,
+            ("HIGH", []) => Instruction::Hires,
+            ("JP", [Value(_)]) => Instruction::Jump(value(0, 0xFFF)?),
+            ("JP", [Reg(x), Value(_)]) => {
+                // BNNN only names V0, or VX for the XNN form where X is the top digit
+                let nnn = value(1, 0xFFF)?;
+                if *x != 0 && *x as u16 != nnn >> 8 {
+                    return Err(invalid());
+                }
+                Instruction::JumpOffset {
+                    x: (nnn >> 8) as u8,
+                    nnn,
+                }
+            }
+            ("CALL", [Value(_)]) => Instruction::Call(value(0, 0xFFF)?),
+            ("SE", [Reg(x), Reg(y)]) => Instruction::SkipEqReg { x: *x, y: *y },
+            ("SE", [Reg(x), Value(_)]) => Instruction::SkipEqImm {
+                x: *x,
+                nn: value(1, 0xFF)? as u8,
+            },
+            ("SNE", [Reg(x), Reg(y)]) => Instruction::SkipNeReg { x: *x, y: *y },
+            ("SNE", [Reg(x), Value(_)]) => Instruction::SkipNeImm {
+                x: *x,
+                nn: value(1, 0xFF)? as u8,
+            },
+            ("SAVE", [RegRange(x, y)]) => Instruction::SaveRange { x: *x, y: *y },
+            ("LOAD", [RegRange(x, y)]) => Instruction::LoadRange { x: *x, y: *y },
+            ("LD", [Reg(x), Value(_)]) => Instruction::SetImm {
+                x: *x,
+                nn: value(1, 0xFF)? as u8,
+            },
+            ("LD", [Reg(x), Reg(y)]) => Instruction::Set { x: *x, y: *y },
+            ("LD", [I, Value(_)]) => Instruction::SetIndex(value(1, 0xFFF)?),
+            ("LD", [I, LongValue(nnnn)]) => {
+                Instruction::SetIndexLong(check_range(&self.operands[1], *nnnn, 0xFFFF)? as u16)
+            }
+            ("LD", [Reg(x), Dt]) => Instruction::GetDelay { x: *x },
+            ("LD", [Reg(x), K]) => Instruction::WaitKey { x: *x },
+            ("LD", [Dt, Reg(x)]) => Instruction::SetDelay { x: *x },
+            ("LD", [St, Reg(x)]) => Instruction::SetSound { x: *x },
+            ("LD", [F, Reg(x)]) => Instruction::Font { x: *x },
+            ("LD", [Hf, Reg(x)]) => Instruction::BigFont { x: *x },
+            ("LD", [B, Reg(x)]) => Instruction::Bcd { x: *x },
+            ("LD", [IndirectI, Reg(x)]) => Instruction::Store { x: *x },
+            ("LD", [Reg(x), IndirectI]) => Instruction::Load { x: *x },
+            ("LD", [R, Reg(x)]) => Instruction::SaveFlags { x: *x },
+            ("LD", [Reg(x), R]) => Instruction::LoadFlags { x: *x },
+            ("ADD", [Reg(x), Value(_)]) => Instruction::AddImm {
+                x: *x,
+                nn: value(1, 0xFF)? as u8,
+            },
+            ("ADD", [Reg(x), Reg(y)]) => Instruction::Add { x: *x, y: *y },
+            ("ADD", [I, Reg(x)]) => Instruction::AddIndex { x: *x },
+            ("OR", [Reg(x), Reg(y)]) => Instruction::Or { x: *x, y: *y },
+            ("AND", [Reg(x), Reg(y)]) => Instruction::And { x: *x, y: *y },
+            ("XOR", [Reg(x), Reg(y)]) => Instruction::Xor { x: *x, y: *y },
+            ("SUB", [Reg(x), Reg(y)]) => Instruction::Sub { x: *x, y: *y },
+            ("SUBN", [Reg(x), Reg(y)]) => Instruction::SubReverse { x: *x, y: *y },
+            ("SHR", [Reg(x)]) => Instruction::ShiftRight { x: *x, y: *x },
+            ("SHR", [Reg(x), Reg(y)]) => Instruction::ShiftRight { x: *x, y: *y },
+            ("SHL", [Reg(x)]) => Instruction::ShiftLeft { x: *x, y: *x },
+            ("SHL", [Reg(x), Reg(y)]) => Instruction::ShiftLeft { x: *x, y: *y },
+            ("RND", [Reg(x), Value(_)]) => Instruction::Random {
+                x: *x,
+                nn: value(1, 0xFF)? as u8,
+            },
+            ("DRW", [Reg(x), Reg(y), Value(_)]) => Instruction::Draw {
+                x: *x,
+                y: *y,
+                n: value(2, 0xF)? as u8,
+            },
+            ("SKP", [Reg(x)]) => Instruction::SkipKeyPressed { x: *x },
+            ("SKNP", [Reg(x)]) => Instruction::SkipKeyNotPressed { x: *x },
+            ("PLANE", [Value(_)]) => Instruction::Plane(value(0, 0x3)? as u8),
+            ("AUDIO", []) => Instruction::LoadAudio,
+            ("PITCH", [Reg(x)]) => Instruction::Pitch { x: *x },
+            (
+                "NOP" | "CLS" | "RET" | "SCD" | "SCU" | "SCR" | "SCL" | "EXIT" | "LOW" | "HIGH"
+                | "JP" | "CALL" | "SE" | "SNE" | "SAVE" | "LOAD" | "LD" | "ADD" | "OR" | "AND"
+                | "XOR" | "SUB" | "SUBN" | "SHR" | "SHL" | "RND" | "DRW" | "SKP" | "SKNP" | "PLANE"
+                | "AUDIO" | "PITCH",
+                _,
+            ) => return Err(invalid()),
+            _ => {
+                let name = self.mnemonic.text.to_string();
+                return Err(self.mnemonic.error(AsmErrorKind::UnknownMnemonic(name)));
+            }
+        };
+
+        Ok(instruction)
+    }
+}
+
+fn starts_with_long(text: &str) -> bool {
+    let mut words = text.split_whitespace();
+    matches!(words.next(), Some(word) if word.eq_ignore_ascii_case("LONG"))
+}
+
+fn parse_operand(token: &Token, symbols: &BTreeMap<String, u16>) -> Result<Operand, AsmError> {
+    let text = token.text;
+
+    let keyword = match text.to_ascii_uppercase().as_str() {
+        "I" => Some(Operand::I),
+        "[I]" => Some(Operand::IndirectI),
+        "DT" => Some(Operand::Dt),
+        "ST" => Some(Operand::St),
+        "K" => Some(Operand::K),
+        "F" => Some(Operand::F),
+        "HF" => Some(Operand::Hf),
+        "B" => Some(Operand::B),
+        "R" => Some(Operand::R),
+        _ => None,
+    };
+    if let Some(keyword) = keyword {
+        return Ok(keyword);
+    }
+
+    if let Some(reg) = parse_register(text) {
+        return Ok(Operand::Reg(reg));
+    }
+
+    if let Some((x, y)) = text.split_once('-') {
+        return match (parse_register(x.trim()), parse_register(y.trim())) {
+            (Some(x), Some(y)) => Ok(Operand::RegRange(x, y)),
+            _ => Err(token.error(AsmErrorKind::InvalidOperands)),
+        };
+    }
+
+    if starts_with_long(text) {
+        let value = Token {
+            text: text[4..].trim(),
+            ..*token
+        };
+        return Ok(Operand::LongValue(resolve_value(&value, symbols)?));
+    }
+
+    Ok(Operand::Value(resolve_value(token, symbols)?))
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix('V').or_else(|| text.strip_prefix('v'))?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+// A number (decimal, 0x hex, 0b binary) or a label
+fn resolve_value(token: &Token, symbols: &BTreeMap<String, u16>) -> Result<u32, AsmError> {
+    let text = token.text;
+
+    if is_identifier(text) {
+        return match symbols.get(text) {
+            Some(&addr) => Ok(addr as u32),
+            None => Err(token.error(AsmErrorKind::UndefinedLabel(text.to_string()))),
+        };
+    }
+
+    let lower = text.to_ascii_lowercase();
+    let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16)
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        u32::from_str_radix(binary, 2)
+    } else {
+        lower.parse()
+    };
+
+    parsed.map_err(|_| token.error(AsmErrorKind::InvalidNumber(text.to_string())))
+}
+
+fn check_range(token: &Token, value: u32, max: u32) -> Result<u32, AsmError> {
+    if value > max {
+        Err(token.error(AsmErrorKind::ValueOutOfRange(value)))
+    } else {
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    fn error(source: &str) -> AsmError {
+        assemble(source).unwrap_err()
+    }
+
+    #[test]
+    fn every_mnemonic_survives_a_round_trip() {
+        let opcodes = [
+            0x0000, 0x00E0, 0x00EE, 0x00C5, 0x00DA, 0x00FB, 0x00FC, 0x00FD, 0x00FE, 0x00FF, 0x1234,
+            0x2345, 0x3A12, 0x4B34, 0x5120, 0x5122, 0x5123, 0x6C56, 0x7D78, 0x8120, 0x8121, 0x8122,
+            0x8123, 0x8124, 0x8125, 0x8126, 0x8127, 0x812E, 0x9120, 0xA456, 0xB678, 0xC1FF, 0xD125,
+            0xE19E, 0xE1A1, 0xF201, 0xF002, 0xF107, 0xF10A, 0xF115, 0xF118, 0xF11E, 0xF129, 0xF130,
+            0xF133, 0xF13A, 0xF155, 0xF165, 0xF175, 0xF185,
+        ];
+
+        for op in opcodes {
+            let instruction = Instruction::decode(op).unwrap();
+            let source = format!("{}", instruction);
+            let program = assemble(&source).unwrap();
+            assert_eq!(program.bytes, op.to_be_bytes(), "{}", source);
+        }
+
+        let long = Instruction::decode_long(0xF000, 0xBEEF).unwrap();
+        let program = assemble(&format!("{}", long)).unwrap();
+        assert_eq!(program.bytes, [0xF0, 0x00, 0xBE, 0xEF]);
+    }
+
+    #[test]
+    fn labels_resolve_forwards_and_backwards() {
+        let program = assemble(
+            "start: CALL sub
+                    JP start
+             sub:   LD I, data
+             a: b:  RET
+             data:  DB 1
+             end:",
+        )
+        .unwrap();
+
+        assert_eq!(
+            program.bytes,
+            [0x22, 0x04, 0x12, 0x00, 0xA2, 0x08, 0x00, 0xEE, 0x01]
+        );
+        assert_eq!(program.symbols["a"], 0x206);
+        assert_eq!(program.symbols["b"], 0x206);
+        assert_eq!(program.symbols["end"], 0x209);
+    }
+
+    #[test]
+    fn data_directives_emit_bytes_and_words() {
+        let program = assemble("DB 0xF0, 144, 0b1010\nDW 0x1234, label\nlabel:").unwrap();
+        assert_eq!(program.bytes, [0xF0, 0x90, 0x0A, 0x12, 0x34, 0x02, 0x07]);
+    }
+
+    #[test]
+    fn jump_offsets_only_name_v0_or_the_top_digit() {
+        assert_eq!(assemble("JP V0, 0x300").unwrap().bytes, [0xB3, 0x00]);
+        assert_eq!(assemble("JP V3, 0x300").unwrap().bytes, [0xB3, 0x00]);
+        assert_eq!(error("JP V5, 0x300").kind, AsmErrorKind::InvalidOperands);
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        let cases = [
+            (
+                "FOO V0",
+                1,
+                1,
+                AsmErrorKind::UnknownMnemonic("FOO".to_string()),
+            ),
+            ("  LD V0", 1, 3, AsmErrorKind::InvalidOperands),
+            (
+                "LD V0, 0x1G",
+                1,
+                8,
+                AsmErrorKind::InvalidNumber("0x1G".to_string()),
+            ),
+            ("\nLD V0, 256", 2, 8, AsmErrorKind::ValueOutOfRange(256)),
+            (
+                "JP nowhere",
+                1,
+                4,
+                AsmErrorKind::UndefinedLabel("nowhere".to_string()),
+            ),
+            (
+                "a: CLS\na: CLS",
+                2,
+                1,
+                AsmErrorKind::DuplicateLabel("a".to_string()),
+            ),
+            ("DRW V0, V1, 16", 1, 13, AsmErrorKind::ValueOutOfRange(16)),
+        ];
+
+        for (source, line, column, kind) in cases {
+            let error = error(source);
+            assert_eq!((error.line, error.column), (line, column), "{}", source);
+            assert_eq!(error.kind, kind, "{}", source);
+        }
+    }
+
+    #[test]
+    fn programs_must_fit_in_memory() {
+        let source = format!("DW {}", ["0"; crate::MAX_RAM_SIZE / 2].join(", "));
+        assert_eq!(error(&source).kind, AsmErrorKind::ProgramTooLarge);
+    }
+}
@@ -0,0 +1,979 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use super::{is_identifier, AsmError, AsmErrorKind, Program, Token, ORIGIN};
+use crate::Instruction;
+
+// Assembles Octo source into XO-CHIP bytecode loaded at 0x200.
+//
+// Supported: `: label`, `:alias`, `:const`, `:unpack`, `:org`, `:byte`, `:call`,
+// `:next`, `:macro`, register and index assignments, `if ... then`,
+// `if ... begin ... else ... end`, `loop ... while ... again` and the SCHIP/XO-CHIP
+// statements. `:calc`, `:stringmode` and the `<`/`>` comparisons (which expand to
+// several instructions clobbering VF) are not supported.
+//
+// Execution starts at the `main` label; a jump to it is placed at 0x200.
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    let mut compiler = Compiler {
+        tokens: tokenize(source),
+        pos: 0,
+        bytes: Vec::new(),
+        here: ORIGIN as usize + 2,
+        symbols: BTreeMap::new(),
+        consts: BTreeMap::new(),
+        aliases: BTreeMap::new(),
+        fixups: Vec::new(),
+        flow: Vec::new(),
+        next_label: None,
+        macros: BTreeMap::new(),
+        expanding: Vec::new(),
+    };
+
+    while compiler.pos < compiler.tokens.len() {
+        compiler.statement()?;
+    }
+
+    if let Some(flow) = compiler.flow.pop() {
+        return Err(flow.token().error(AsmErrorKind::UnbalancedBlock));
+    }
+
+    compiler.finish()
+}
+
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or("");
+        let mut rest = code;
+
+        while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+            let word = &rest[start..];
+            let end = word.find(char::is_whitespace).unwrap_or(word.len());
+            tokens.push(Token::within(line, index + 1, &word[..end]));
+            rest = &word[end..];
+        }
+    }
+
+    tokens
+}
+
+#[derive(Clone, Copy, Debug)]
+enum FixupKind {
+    Addr12,       // low 12 bits of the opcode at the address
+    Addr16,       // the word at the address
+    UnpackHi(u8), // low nibble of the byte after the address, under the given high nibble
+    UnpackLo,     // the byte after the address
+}
+
+struct Fixup<'a> {
+    addr: usize,
+    label: Token<'a>,
+    kind: FixupKind,
+}
+
+enum Flow<'a> {
+    // Address of the jump skipping the block, patched at `else`/`end`
+    If {
+        token: Token<'a>,
+        jump: usize,
+    },
+    Else {
+        token: Token<'a>,
+        jump: usize,
+    },
+    // Start of the loop and the jumps out of it from `while`
+    Loop {
+        token: Token<'a>,
+        start: usize,
+        exits: Vec<usize>,
+    },
+}
+
+// `:macro name params { body }`, expanded by substituting the arguments
+// for the parameters token by token
+struct Macro<'a> {
+    params: Vec<&'a str>,
+    body: Vec<Token<'a>>,
+}
+
+impl<'a> Flow<'a> {
+    fn token(&self) -> Token<'a> {
+        match self {
+            Flow::If { token, .. } | Flow::Else { token, .. } | Flow::Loop { token, .. } => *token,
+        }
+    }
+}
+
+// A skip condition, as written after `if`/`while`
+#[derive(Clone, Copy)]
+enum Condition {
+    EqImm(u8, u8),
+    NeImm(u8, u8),
+    EqReg(u8, u8),
+    NeReg(u8, u8),
+    Key(u8),
+    NotKey(u8),
+}
+
+impl Condition {
+    // Skips the next instruction when the condition is false, so it runs only when true
+    fn run_if_true(self) -> Instruction {
+        match self {
+            Condition::EqImm(x, nn) => Instruction::SkipNeImm { x, nn },
+            Condition::NeImm(x, nn) => Instruction::SkipEqImm { x, nn },
+            Condition::EqReg(x, y) => Instruction::SkipNeReg { x, y },
+            Condition::NeReg(x, y) => Instruction::SkipEqReg { x, y },
+            Condition::Key(x) => Instruction::SkipKeyNotPressed { x },
+            Condition::NotKey(x) => Instruction::SkipKeyPressed { x },
+        }
+    }
+
+    // Skips the next instruction when the condition is true
+    fn run_if_false(self) -> Instruction {
+        match self {
+            Condition::EqImm(x, nn) => Instruction::SkipEqImm { x, nn },
+            Condition::NeImm(x, nn) => Instruction::SkipNeImm { x, nn },
+            Condition::EqReg(x, y) => Instruction::SkipEqReg { x, y },
+            Condition::NeReg(x, y) => Instruction::SkipNeReg { x, y },
+            Condition::Key(x) => Instruction::SkipKeyPressed { x },
+            Condition::NotKey(x) => Instruction::SkipKeyNotPressed { x },
+        }
+    }
+}
+
+struct Compiler<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    bytes: Vec<u8>, // starting at ORIGIN
+    here: usize,    // address of the next emitted byte
+    symbols: BTreeMap<String, u16>,
+    consts: BTreeMap<String, u32>,
+    aliases: BTreeMap<String, u8>,
+    fixups: Vec<Fixup<'a>>,
+    flow: Vec<Flow<'a>>,
+    next_label: Option<Token<'a>>, // `:next`, labels the operand of the next instruction
+    macros: BTreeMap<&'a str, Macro<'a>>,
+    expanding: Vec<(&'a str, usize)>, // macros being expanded and where their tokens end
+}
+
+impl<'a> Compiler<'a> {
+    fn next_token(&mut self) -> Result<Token<'a>, AsmError> {
+        match self.tokens.get(self.pos) {
+            Some(&token) => {
+                self.pos += 1;
+                Ok(token)
+            }
+            None => {
+                let last = self.tokens.last().copied().unwrap_or(Token {
+                    text: "",
+                    line: 1,
+                    column: 1,
+                });
+                Err(last.error(AsmErrorKind::UnexpectedEnd))
+            }
+        }
+    }
+
+    fn peek_text(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(|token| token.text)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token<'a>, AsmError> {
+        let token = self.next_token()?;
+        if token.text == text {
+            Ok(token)
+        } else {
+            Err(unexpected(token))
+        }
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), AsmError> {
+        let offset = self.here - ORIGIN as usize;
+        if self.here >= crate::MAX_RAM_SIZE {
+            let token = self.tokens[self.pos.saturating_sub(1)];
+            return Err(token.error(AsmErrorKind::ProgramTooLarge));
+        }
+
+        if offset >= self.bytes.len() {
+            self.bytes.resize(offset + 1, 0);
+        }
+        self.bytes[offset] = byte;
+        self.here += 1;
+
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> Result<(), AsmError> {
+        let mut encoded = [0; 4];
+        let size = instruction.encode_into(&mut encoded);
+
+        if let Some(label) = self.next_label.take() {
+            // Octo points `:next` labels at the instruction's second byte
+            self.define(label, self.here + 1)?;
+        }
+
+        for &byte in &encoded[..size] {
+            self.emit_byte(byte)?;
+        }
+
+        Ok(())
+    }
+
+    fn patch_word(&mut self, addr: usize, mask: u16, value: u16) {
+        let offset = addr - ORIGIN as usize;
+        let word = u16::from_be_bytes([self.bytes[offset], self.bytes[offset + 1]]);
+        let word = (word & !mask) | (value & mask);
+        self.bytes[offset..offset + 2].copy_from_slice(&word.to_be_bytes());
+    }
+
+    fn define(&mut self, label: Token<'a>, addr: usize) -> Result<(), AsmError> {
+        if !is_identifier(label.text) {
+            return Err(unexpected(label));
+        }
+        if self
+            .symbols
+            .insert(label.text.to_string(), addr as u16)
+            .is_some()
+        {
+            return Err(label.error(AsmErrorKind::DuplicateLabel(label.text.to_string())));
+        }
+        Ok(())
+    }
+
+    fn register(&self, token: Token<'a>) -> Option<u8> {
+        if let Some(&reg) = self.aliases.get(token.text) {
+            return Some(reg);
+        }
+
+        let digit = token.text.strip_prefix(['v', 'V'])?;
+        if digit.len() != 1 {
+            return None;
+        }
+        u8::from_str_radix(digit, 16).ok()
+    }
+
+    fn expect_register(&mut self) -> Result<u8, AsmError> {
+        let token = self.next_token()?;
+        self.register(token).ok_or_else(|| unexpected(token))
+    }
+
+    // A number or constant, or a label that's already been defined
+    fn value(&self, token: Token<'a>) -> Option<i64> {
+        if let Some(&value) = self.consts.get(token.text) {
+            return Some(value as i64);
+        }
+        if let Some(&addr) = self.symbols.get(token.text) {
+            return Some(addr as i64);
+        }
+
+        let (negative, text) = match token.text.strip_prefix('-') {
+            Some(text) => (true, text),
+            None => (false, token.text),
+        };
+        let value = if let Some(hex) = text.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16).ok()?
+        } else if let Some(binary) = text.strip_prefix("0b") {
+            i64::from_str_radix(binary, 2).ok()?
+        } else {
+            text.parse().ok()?
+        };
+
+        Some(if negative { -value } else { value })
+    }
+
+    // Byte operands may be negative, -1 is 0xFF
+    fn expect_byte(&mut self) -> Result<u8, AsmError> {
+        let token = self.next_token()?;
+        match self.value(token) {
+            Some(value) if (-128..=255).contains(&value) => Ok(value as u8),
+            Some(value) => Err(token.error(AsmErrorKind::ValueOutOfRange(value as u32))),
+            None => Err(invalid_number(token)),
+        }
+    }
+
+    fn expect_nibble(&mut self) -> Result<u8, AsmError> {
+        let token = self.next_token()?;
+        match self.value(token) {
+            Some(value) if (0..=0xF).contains(&value) => Ok(value as u8),
+            Some(value) => Err(token.error(AsmErrorKind::ValueOutOfRange(value as u32))),
+            None => Err(invalid_number(token)),
+        }
+    }
+
+    // Emits an instruction whose address operand may be a forward reference
+    fn emit_with_address(
+        &mut self,
+        instruction: Instruction,
+        target: Token<'a>,
+        kind: FixupKind,
+    ) -> Result<(), AsmError> {
+        let max = match kind {
+            FixupKind::Addr16 => 0xFFFF,
+            _ => 0xFFF,
+        };
+        let addr = self.here;
+
+        match self.value(target) {
+            Some(value) if (0..=max).contains(&value) => {}
+            Some(value) => return Err(target.error(AsmErrorKind::ValueOutOfRange(value as u32))),
+            None if is_identifier(target.text) => self.fixups.push(Fixup {
+                addr,
+                label: target,
+                kind,
+            }),
+            None => return Err(invalid_number(target)),
+        }
+
+        let value = self.value(target).unwrap_or(0) as u16;
+        let instruction = match instruction {
+            Instruction::Jump(_) => Instruction::Jump(value),
+            Instruction::Call(_) => Instruction::Call(value),
+            Instruction::SetIndex(_) => Instruction::SetIndex(value),
+            Instruction::JumpOffset { .. } => Instruction::JumpOffset {
+                x: (value >> 8) as u8,
+                nnn: value,
+            },
+            Instruction::SetIndexLong(_) => Instruction::SetIndexLong(value),
+            other => other,
+        };
+
+        self.emit(instruction)
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let token = self.next_token()?;
+
+        match token.text {
+            ":" => {
+                let label = self.next_token()?;
+                self.define(label, self.here)
+            }
+            ":alias" => {
+                let name = self.next_token()?;
+                let reg = self.expect_register()?;
+                self.aliases.insert(name.text.to_string(), reg);
+                Ok(())
+            }
+            ":const" => {
+                let name = self.next_token()?;
+                let value_token = self.next_token()?;
+                let value = self
+                    .value(value_token)
+                    .ok_or_else(|| invalid_number(value_token))?;
+                self.consts.insert(name.text.to_string(), value as u32);
+                Ok(())
+            }
+            ":unpack" => self.unpack(),
+            ":org" => {
+                let addr_token = self.next_token()?;
+                match self.value(addr_token) {
+                    Some(addr) if (ORIGIN as i64..crate::MAX_RAM_SIZE as i64).contains(&addr) => {
+                        self.here = addr as usize;
+                        Ok(())
+                    }
+                    Some(addr) => Err(addr_token.error(AsmErrorKind::ValueOutOfRange(addr as u32))),
+                    None => Err(invalid_number(addr_token)),
+                }
+            }
+            ":byte" => {
+                let byte = self.expect_byte()?;
+                self.emit_byte(byte)
+            }
+            ":call" => {
+                let target = self.next_token()?;
+                self.emit_with_address(Instruction::Call(0), target, FixupKind::Addr12)
+            }
+            ":next" => {
+                self.next_label = Some(self.next_token()?);
+                Ok(())
+            }
+            ":macro" => self.define_macro(),
+            "clear" => self.emit(Instruction::Cls),
+            "return" | ";" => self.emit(Instruction::Ret),
+            "exit" => self.emit(Instruction::Exit),
+            "lores" => self.emit(Instruction::Lores),
+            "hires" => self.emit(Instruction::Hires),
+            "scroll-down" => {
+                let n = self.expect_nibble()?;
+                self.emit(Instruction::ScrollDown(n))
+            }
+            "scroll-up" => {
+                let n = self.expect_nibble()?;
+                self.emit(Instruction::ScrollUp(n))
+            }
+            "scroll-left" => self.emit(Instruction::ScrollLeft),
+            "scroll-right" => self.emit(Instruction::ScrollRight),
+            "jump" => {
+                let target = self.next_token()?;
+                self.emit_with_address(Instruction::Jump(0), target, FixupKind::Addr12)
+            }
+            "jump0" => {
+                let target = self.next_token()?;
+                let offset = Instruction::JumpOffset { x: 0, nnn: 0 };
+                self.emit_with_address(offset, target, FixupKind::Addr12)
+            }
+            "sprite" => {
+                let x = self.expect_register()?;
+                let y = self.expect_register()?;
+                let n = self.expect_nibble()?;
+                self.emit(Instruction::Draw { x, y, n })
+            }
+            "bcd" => {
+                let x = self.expect_register()?;
+                self.emit(Instruction::Bcd { x })
+            }
+            "save" | "load" => {
+                let x = self.expect_register()?;
+                let is_save = token.text == "save";
+
+                if self.peek_text() == Some("-") {
+                    self.pos += 1;
+                    let y = self.expect_register()?;
+                    return self.emit(if is_save {
+                        Instruction::SaveRange { x, y }
+                    } else {
+                        Instruction::LoadRange { x, y }
+                    });
+                }
+
+                self.emit(if is_save {
+                    Instruction::Store { x }
+                } else {
+                    Instruction::Load { x }
+                })
+            }
+            "saveflags" => {
+                let x = self.expect_register()?;
+                self.emit(Instruction::SaveFlags { x })
+            }
+            "loadflags" => {
+                let x = self.expect_register()?;
+                self.emit(Instruction::LoadFlags { x })
+            }
+            "plane" => {
+                let n = self.expect_nibble()?;
+                if n > 3 {
+                    return Err(token.error(AsmErrorKind::ValueOutOfRange(n as u32)));
+                }
+                self.emit(Instruction::Plane(n))
+            }
+            "audio" => self.emit(Instruction::LoadAudio),
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.expect_register()?;
+                self.emit(match token.text {
+                    "delay" => Instruction::SetDelay { x },
+                    "buzzer" => Instruction::SetSound { x },
+                    _ => Instruction::Pitch { x },
+                })
+            }
+            "i" => self.index_statement(),
+            "if" => self.if_statement(token),
+            "else" => match self.flow.pop() {
+                Some(Flow::If { jump, .. }) => {
+                    let else_jump = self.here;
+                    self.emit(Instruction::Jump(0))?;
+                    self.patch_word(jump, 0x0FFF, self.here as u16);
+                    self.flow.push(Flow::Else {
+                        token,
+                        jump: else_jump,
+                    });
+                    Ok(())
+                }
+                _ => Err(token.error(AsmErrorKind::UnbalancedBlock)),
+            },
+            "end" => match self.flow.pop() {
+                Some(Flow::If { jump, .. } | Flow::Else { jump, .. }) => {
+                    self.patch_word(jump, 0x0FFF, self.here as u16);
+                    Ok(())
+                }
+                _ => Err(token.error(AsmErrorKind::UnbalancedBlock)),
+            },
+            "loop" => {
+                self.flow.push(Flow::Loop {
+                    token,
+                    start: self.here,
+                    exits: Vec::new(),
+                });
+                Ok(())
+            }
+            "while" => {
+                let condition = self.condition()?;
+                let exit = self.here + 2;
+                // Falls through to the jump out once the condition is false
+                self.emit(condition.run_if_false())?;
+                self.emit(Instruction::Jump(0))?;
+
+                match self
+                    .flow
+                    .iter_mut()
+                    .rev()
+                    .find(|flow| matches!(flow, Flow::Loop { .. }))
+                {
+                    Some(Flow::Loop { exits, .. }) => {
+                        exits.push(exit);
+                        Ok(())
+                    }
+                    _ => Err(token.error(AsmErrorKind::UnbalancedBlock)),
+                }
+            }
+            "again" => match self.flow.pop() {
+                Some(Flow::Loop { start, exits, .. }) => {
+                    self.emit(Instruction::Jump(start as u16))?;
+                    for exit in exits {
+                        self.patch_word(exit, 0x0FFF, self.here as u16);
+                    }
+                    Ok(())
+                }
+                _ => Err(token.error(AsmErrorKind::UnbalancedBlock)),
+            },
+            _ if self.macros.contains_key(token.text) => self.expand(token),
+            _ if self.register(token).is_some() => self.register_statement(token),
+            _ if self.value(token).is_some() && !self.symbols.contains_key(token.text) => {
+                // Bare numbers are data
+                self.pos -= 1;
+                let byte = self.expect_byte()?;
+                self.emit_byte(byte)
+            }
+            _ if is_identifier(token.text) => {
+                // A bare label is a subroutine call
+                self.emit_with_address(Instruction::Call(0), token, FixupKind::Addr12)
+            }
+            _ => Err(unexpected(token)),
+        }
+    }
+
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.next_token()?;
+        if !is_identifier(name.text) {
+            return Err(unexpected(name));
+        }
+
+        let mut params = Vec::new();
+        loop {
+            let param = self.next_token()?;
+            if param.text == "{" {
+                break;
+            }
+            if !is_identifier(param.text) {
+                return Err(unexpected(param));
+            }
+            params.push(param.text);
+        }
+
+        let mut body = Vec::new();
+        loop {
+            let token = self.next_token()?;
+            if token.text == "}" {
+                break;
+            }
+            body.push(token);
+        }
+
+        self.macros.insert(name.text, Macro { params, body });
+        Ok(())
+    }
+
+    // Replaces a macro call with its body, which is then compiled as usual
+    fn expand(&mut self, name: Token<'a>) -> Result<(), AsmError> {
+        // Drop finished expansions, `self.pos` is just past the name
+        self.expanding.retain(|&(_, end)| end >= self.pos);
+        if self.expanding.iter().any(|&(open, _)| open == name.text) {
+            let text = name.text.to_string();
+            return Err(name.error(AsmErrorKind::RecursiveMacro(text)));
+        }
+
+        let count = self.macros[name.text].params.len();
+        let mut args = Vec::with_capacity(count);
+        for _ in 0..count {
+            args.push(self.next_token()?.text);
+        }
+
+        let definition = &self.macros[name.text];
+        let mut body = definition.body.clone();
+        for token in &mut body {
+            if let Some(index) = definition.params.iter().position(|&p| p == token.text) {
+                token.text = args[index];
+            }
+        }
+
+        // Everything still being expanded now ends that much later
+        let len = body.len();
+        for (_, end) in &mut self.expanding {
+            *end += len;
+        }
+        self.expanding.push((name.text, self.pos + len));
+        self.tokens.splice(self.pos..self.pos, body);
+
+        Ok(())
+    }
+
+    // `:unpack N label` sets V0 = N << 4 | label >> 8 and V1 = label & 0xFF,
+    // `:unpack long label` sets V0 = label >> 8 and V1 = label & 0xFF
+    fn unpack(&mut self) -> Result<(), AsmError> {
+        let prefix = self.next_token()?;
+        let high_nibble = if prefix.text == "long" {
+            None
+        } else {
+            let nibble = self.value(prefix).ok_or_else(|| invalid_number(prefix))?;
+            Some((nibble & 0xF) as u8)
+        };
+        let label = self.next_token()?;
+
+        let (hi_addr, lo_addr) = (self.here, self.here + 2);
+        let value = self.value(label);
+        let (hi, lo) = match value {
+            Some(value) => {
+                let value = value as u16;
+                let hi = match high_nibble {
+                    Some(nibble) => (nibble << 4) | ((value >> 8) as u8 & 0xF),
+                    None => (value >> 8) as u8,
+                };
+                (hi, value as u8)
+            }
+            None if is_identifier(label.text) => {
+                let kind = match high_nibble {
+                    Some(nibble) => FixupKind::UnpackHi(nibble),
+                    None => FixupKind::Addr16,
+                };
+                self.fixups.push(Fixup {
+                    addr: hi_addr,
+                    label,
+                    kind,
+                });
+                self.fixups.push(Fixup {
+                    addr: lo_addr,
+                    label,
+                    kind: FixupKind::UnpackLo,
+                });
+                (high_nibble.unwrap_or(0) << 4, 0)
+            }
+            None => return Err(invalid_number(label)),
+        };
+
+        self.emit(Instruction::SetImm { x: 0, nn: hi })?;
+        self.emit(Instruction::SetImm { x: 1, nn: lo })
+    }
+
+    fn index_statement(&mut self) -> Result<(), AsmError> {
+        let op = self.next_token()?;
+
+        match op.text {
+            ":=" => {
+                let value = self.next_token()?;
+                match value.text {
+                    "long" => {
+                        let target = self.next_token()?;
+                        let long = Instruction::SetIndexLong(0);
+                        self.emit_with_address(long, target, FixupKind::Addr16)
+                    }
+                    "hex" => {
+                        let x = self.expect_register()?;
+                        self.emit(Instruction::Font { x })
+                    }
+                    "bighex" => {
+                        let x = self.expect_register()?;
+                        self.emit(Instruction::BigFont { x })
+                    }
+                    _ => self.emit_with_address(Instruction::SetIndex(0), value, FixupKind::Addr12),
+                }
+            }
+            "+=" => {
+                let x = self.expect_register()?;
+                self.emit(Instruction::AddIndex { x })
+            }
+            _ => Err(unexpected(op)),
+        }
+    }
+
+    fn register_statement(&mut self, target: Token<'a>) -> Result<(), AsmError> {
+        let x = self.register(target).ok_or_else(|| unexpected(target))?;
+        let op = self.next_token()?;
+        let source = self.next_token()?;
+        let y = self.register(source);
+
+        let instruction = match (op.text, y) {
+            (":=", Some(y)) => Instruction::Set { x, y },
+            (":=", None) => match source.text {
+                "delay" => Instruction::GetDelay { x },
+                "key" => Instruction::WaitKey { x },
+                "random" => {
+                    let nn = self.expect_byte()?;
+                    Instruction::Random { x, nn }
+                }
+                _ => {
+                    self.pos -= 1;
+                    let nn = self.expect_byte()?;
+                    Instruction::SetImm { x, nn }
+                }
+            },
+            ("+=", Some(y)) => Instruction::Add { x, y },
+            ("+=", None) => {
+                self.pos -= 1;
+                let nn = self.expect_byte()?;
+                Instruction::AddImm { x, nn }
+            }
+            ("-=", None) => {
+                // Octo compiles `vx -= n` to an add of the two's complement
+                self.pos -= 1;
+                let nn = self.expect_byte()?;
+                Instruction::AddImm {
+                    x,
+                    nn: nn.wrapping_neg(),
+                }
+            }
+            ("-=", Some(y)) => Instruction::Sub { x, y },
+            ("=-", Some(y)) => Instruction::SubReverse { x, y },
+            ("|=", Some(y)) => Instruction::Or { x, y },
+            ("&=", Some(y)) => Instruction::And { x, y },
+            ("^=", Some(y)) => Instruction::Xor { x, y },
+            (">>=", Some(y)) => Instruction::ShiftRight { x, y },
+            ("<<=", Some(y)) => Instruction::ShiftLeft { x, y },
+            _ => return Err(unexpected(op)),
+        };
+
+        self.emit(instruction)
+    }
+
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let x = self.expect_register()?;
+        let op = self.next_token()?;
+
+        match op.text {
+            "key" => return Ok(Condition::Key(x)),
+            "-key" => return Ok(Condition::NotKey(x)),
+            "==" | "!=" => {}
+            "<" | ">" | "<=" | ">=" => {
+                let text = op.text.to_string();
+                return Err(op.error(AsmErrorKind::Unsupported(text)));
+            }
+            _ => return Err(unexpected(op)),
+        }
+
+        let operand = self.next_token()?;
+        let condition = match (op.text, self.register(operand)) {
+            ("==", Some(y)) => Condition::EqReg(x, y),
+            ("!=", Some(y)) => Condition::NeReg(x, y),
+            (_, None) => {
+                self.pos -= 1;
+                let nn = self.expect_byte()?;
+                if op.text == "==" {
+                    Condition::EqImm(x, nn)
+                } else {
+                    Condition::NeImm(x, nn)
+                }
+            }
+            _ => return Err(unexpected(op)),
+        };
+
+        Ok(condition)
+    }
+
+    fn if_statement(&mut self, token: Token<'a>) -> Result<(), AsmError> {
+        let condition = self.condition()?;
+        let keyword = self.next_token()?;
+
+        match keyword.text {
+            // The following statement runs only when the condition holds
+            "then" => self.emit(condition.run_if_true()),
+            "begin" => {
+                self.emit(condition.run_if_false())?;
+                let jump = self.here;
+                self.emit(Instruction::Jump(0))?;
+                self.flow.push(Flow::If { token, jump });
+                Ok(())
+            }
+            _ => Err(unexpected(keyword)),
+        }
+    }
+
+    fn finish(mut self) -> Result<Program, AsmError> {
+        let main = match self.symbols.get("main") {
+            Some(&main) => main,
+            None => {
+                let token = self.tokens.first().copied().unwrap_or(Token {
+                    text: "",
+                    line: 1,
+                    column: 1,
+                });
+                return Err(token.error(AsmErrorKind::UndefinedLabel("main".to_string())));
+            }
+        };
+
+        if self.bytes.len() < 2 {
+            self.bytes.resize(2, 0);
+        }
+        let mut jump = [0; 2];
+        Instruction::Jump(main).encode_into(&mut jump);
+        self.bytes[..2].copy_from_slice(&jump);
+
+        for fixup in core::mem::take(&mut self.fixups) {
+            let value = match self.symbols.get(fixup.label.text) {
+                Some(&value) => value,
+                None => {
+                    let name = fixup.label.text.to_string();
+                    return Err(fixup.label.error(AsmErrorKind::UndefinedLabel(name)));
+                }
+            };
+
+            match fixup.kind {
+                FixupKind::Addr12 if value > 0xFFF => {
+                    return Err(fixup
+                        .label
+                        .error(AsmErrorKind::ValueOutOfRange(value as u32)));
+                }
+                FixupKind::Addr12 => self.patch_word(fixup.addr, 0x0FFF, value),
+                FixupKind::Addr16 if Instruction::is_long(self.word_at(fixup.addr)) => {
+                    self.patch_word(fixup.addr + 2, 0xFFFF, value)
+                }
+                FixupKind::Addr16 => self.patch_word(fixup.addr, 0x00FF, value >> 8),
+                FixupKind::UnpackHi(nibble) => {
+                    let hi = ((nibble as u16) << 4) | ((value >> 8) & 0xF);
+                    self.patch_word(fixup.addr, 0x00FF, hi)
+                }
+                FixupKind::UnpackLo => self.patch_word(fixup.addr, 0x00FF, value & 0xFF),
+            }
+        }
+
+        Ok(Program {
+            bytes: self.bytes,
+            symbols: self.symbols,
+        })
+    }
+
+    fn word_at(&self, addr: usize) -> u16 {
+        let offset = addr - ORIGIN as usize;
+        u16::from_be_bytes([self.bytes[offset], self.bytes[offset + 1]])
+    }
+}
+
+fn unexpected(token: Token) -> AsmError {
+    token.error(AsmErrorKind::UnexpectedToken(token.text.to_string()))
+}
+
+fn invalid_number(token: Token) -> AsmError {
+    token.error(AsmErrorKind::InvalidNumber(token.text.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Emu;
+
+    fn run(source: &str) -> Emu {
+        let program = assemble(source).unwrap();
+        let mut emu = Emu::new();
+        emu.load(&program.bytes).unwrap();
+        emu.tick_many(200).unwrap();
+        emu
+    }
+
+    #[test]
+    fn while_leaves_the_loop_once_its_condition_fails() {
+        let emu = run("
+            : main
+                v0 := 0
+                loop
+                    while v0 != 5
+                    v0 += 1
+                again
+                v1 := 1
+            : halt
+                jump halt
+        ");
+        assert_eq!(emu.v_reg[0], 5);
+        assert_eq!(emu.v_reg[1], 1);
+    }
+
+    #[test]
+    fn if_then_runs_one_statement_when_true() {
+        let emu = run("
+            : main
+                v0 := 3
+                if v0 == 3 then v1 := 1
+                if v0 != 3 then v2 := 1
+                if v0 == v1 then v3 := 1
+            : halt
+                jump halt
+        ");
+        assert_eq!(emu.v_reg[1..4], [1, 0, 0]);
+    }
+
+    #[test]
+    fn if_begin_else_end_runs_one_branch() {
+        let emu = run("
+            : main
+                v0 := 3
+                if v0 == 3 begin
+                    v1 := 1
+                else
+                    v1 := 2
+                end
+                if v0 != 3 begin
+                    v2 := 1
+                else
+                    v2 := 2
+                end
+            : halt
+                jump halt
+        ");
+        assert_eq!(emu.v_reg[1..3], [1, 2]);
+    }
+
+    #[test]
+    fn consts_and_aliases_stand_for_values_and_registers() {
+        let emu = run("
+            :const LIVES 3
+            :alias lives v4
+            : main
+                lives := LIVES
+                lives += 1
+            : halt
+                jump halt
+        ");
+        assert_eq!(emu.v_reg[4], 4);
+    }
+
+    #[test]
+    fn macros_expand_with_their_arguments() {
+        let emu = run("
+            :macro set_pair a b value {
+                a := value
+                b := value
+            }
+            :macro bump r { r += 1 }
+            :macro both a b { bump a bump b }
+            : main
+                set_pair v1 v2 7
+                both v1 v2
+                bump v2
+            : halt
+                jump halt
+        ");
+        assert_eq!(emu.v_reg[1..3], [8, 9]);
+    }
+
+    #[test]
+    fn recursive_macros_are_an_error() {
+        let error = assemble(":macro forever { v0 += 1 forever }\n: main forever").unwrap_err();
+        assert_eq!(
+            error.kind,
+            AsmErrorKind::RecursiveMacro("forever".to_string())
+        );
+    }
+
+    #[test]
+    fn unbalanced_blocks_are_an_error() {
+        for source in [
+            ": main again",
+            ": main loop",
+            ": main end",
+            ": main if v0 == 1 begin",
+        ] {
+            let error = assemble(source).unwrap_err();
+            assert_eq!(error.kind, AsmErrorKind::UnbalancedBlock, "{}", source);
+        }
+    }
+}
@@ -0,0 +1,146 @@
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use core::fmt;
+
+use crate::*;
+
+const DEFAULT_FREQUENCY: f32 = 440.0;
+
+// An audio backend the machine tells when the buzzer turns on and off,
+// see Emu::set_audio_sink(). Called from tick_timers(), so at most once a
+// frame per edge.
+pub trait AudioSink {
+    fn sound_started(&mut self) {}
+    fn sound_stopped(&mut self) {}
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Triangle,
+    Sine,
+}
+
+impl Waveform {
+    // One period over `phase` 0.0..1.0, between -1.0 and 1.0
+    pub(crate) fn sample(self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square if phase < 0.5 => 1.0,
+            Waveform::Square => -1.0,
+            Waveform::Triangle => 4.0 * (phase - 0.5).abs() - 1.0,
+            Waveform::Sine => sine(phase),
+        }
+    }
+}
+
+// The tone render_audio() plays for programs without an XO-CHIP audio
+// pattern. The volume applies to patterns as well.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Buzzer {
+    pub frequency: f32, // Hz
+    pub waveform: Waveform,
+    pub volume: f32, // 0.0 to 1.0
+}
+
+impl Default for Buzzer {
+    fn default() -> Self {
+        Self {
+            frequency: DEFAULT_FREQUENCY,
+            waveform: Waveform::Square,
+            volume: 1.0,
+        }
+    }
+}
+
+// sin(2π * phase), core has no sin(). A parabola per half period, corrected
+// towards the sine, is within 0.1% of it.
+fn sine(phase: f32) -> f32 {
+    let x = if phase < 0.5 { phase } else { phase - 1.0 }; // -0.5..0.5
+    let y = 8.0 * x * (1.0 - 2.0 * x.abs());
+    0.225 * (y * y.abs() - y) + y
+}
+
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    pub fn buzzer(&self) -> Buzzer {
+        self.buzzer
+    }
+
+    // Frequencies that aren't positive and volumes that aren't numbers get
+    // the defaults, volumes outside 0.0 to 1.0 are clamped
+    pub fn set_buzzer(&mut self, buzzer: Buzzer) {
+        let frequency = match buzzer.frequency > 0.0 && buzzer.frequency.is_finite() {
+            true => buzzer.frequency,
+            false => DEFAULT_FREQUENCY,
+        };
+        let volume = match buzzer.volume.is_nan() {
+            true => 1.0,
+            false => buzzer.volume.clamp(0.0, 1.0),
+        };
+        self.buzzer = Buzzer {
+            frequency,
+            waveform: buzzer.waveform,
+            volume,
+        };
+    }
+}
+
+// Holds a sink outside of the machine state: clones, like the ones rewind
+// keeps, don't get one and comparisons ignore it
+#[cfg(feature = "alloc")]
+pub(crate) struct SinkSlot<T: ?Sized>(pub(crate) Option<Box<T>>);
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> Default for SinkSlot<T> {
+    fn default() -> Self {
+        SinkSlot(None)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> Clone for SinkSlot<T> {
+    fn clone(&self) -> Self {
+        SinkSlot(None)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> PartialEq for SinkSlot<T> {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> fmt::Debug for SinkSlot<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "SinkSlot(Some(..))"),
+            None => write!(f, "SinkSlot(None)"),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    // Replaces any previous sink. A sink set while the sound is on hears
+    // about it at the next tick_timers().
+    pub fn set_audio_sink(&mut self, sink: impl AudioSink + Send + 'static) {
+        self.audio_sink = SinkSlot(Some(Box::new(sink)));
+        self.sounding = false;
+    }
+
+    pub fn take_audio_sink(&mut self) -> Option<Box<dyn AudioSink + Send>> {
+        self.audio_sink.0.take()
+    }
+
+    pub(crate) fn notify_audio_sink(&mut self, edge: SoundEdge) {
+        if let Some(sink) = &mut self.audio_sink.0 {
+            match edge {
+                SoundEdge::Started => sink.sound_started(),
+                SoundEdge::Stopped => sink.sound_stopped(),
+            }
+        }
+    }
+}
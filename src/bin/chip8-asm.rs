@@ -0,0 +1,106 @@
+// Assembler:
+//
+//     chip8-asm [--octo] [--symbols FILE] SOURCE [-o ROM]
+//
+// Sources use the disassembler's mnemonics, see asm::assemble(), or Octo
+// with --octo or a `.8o` extension. The ROM goes next to the source with a
+// `.ch8` extension unless -o says otherwise. --symbols writes the labels
+// in the format the other tools read with their --symbols option.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use chip8_core::asm::{self, octo, AsmError};
+use chip8_core::symbols::SymbolTable;
+
+#[derive(Default)]
+struct Options {
+    octo: bool,
+    output: Option<PathBuf>,
+    symbols: Option<PathBuf>,
+    source: Option<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("chip8-asm: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<(), String> {
+    let options = parse_options()?;
+    let source_path = options
+        .source
+        .as_ref()
+        .ok_or("usage: chip8-asm [--octo] [--symbols FILE] SOURCE [-o ROM]")?;
+    let source = std::fs::read_to_string(source_path)
+        .map_err(|error| format!("{}: {}", source_path.display(), error))?;
+
+    let octo = options.octo || source_path.extension().is_some_and(|ext| ext == "8o");
+    let result = match octo {
+        true => octo::assemble(&source),
+        false => asm::assemble(&source),
+    };
+    let program = result.map_err(|error| describe_error(source_path, &source, &error))?;
+
+    let output = match &options.output {
+        Some(path) => path.clone(),
+        None => source_path.with_extension("ch8"),
+    };
+    std::fs::write(&output, &program.bytes)
+        .map_err(|error| format!("{}: {}", output.display(), error))?;
+
+    if let Some(path) = &options.symbols {
+        let symbols = SymbolTable::from(&program.symbols);
+        std::fs::write(path, symbols.to_string())
+            .map_err(|error| format!("{}: {}", path.display(), error))?;
+    }
+
+    Ok(())
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--octo" => options.octo = true,
+            "-o" => options.output = Some(args.next().ok_or("-o needs a file")?.into()),
+            "--symbols" => {
+                options.symbols = Some(args.next().ok_or("--symbols needs a file")?.into())
+            }
+            _ if options.source.is_none() && !arg.starts_with('-') => {
+                options.source = Some(arg.into())
+            }
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    Ok(options)
+}
+
+// The error followed by the offending line with a caret under the column:
+//
+//     game.s: line 3, column 9: unknown mnemonic 'LDD'
+//         loop:   LDD V0, 1
+//                 ^
+fn describe_error(path: &Path, source: &str, error: &AsmError) -> String {
+    let mut message = format!("{}: {}", path.display(), error);
+
+    if let Some(line) = source.lines().nth(error.line.saturating_sub(1)) {
+        // Keep tabs so the caret lines up with what the terminal shows
+        let indent: String = line
+            .chars()
+            .take(error.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        message.push_str(&format!("\n    {}\n    {}^", line, indent));
+    }
+
+    message
+}
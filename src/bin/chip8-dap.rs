@@ -0,0 +1,694 @@
+// Debug Adapter Protocol server, so editors like VS Code can debug CHIP-8
+// programs running on this core. Speaks DAP over stdin/stdout:
+//
+//     "type": "chip8", "request": "launch",
+//     "program": "game.ch8", "variant": "schip-modern",
+//     "symbols": "game.sym", "stopOnEntry": true
+//
+// The ROM is shown as a disassembly listing (a `source` request) whose
+// lines take source breakpoints, with an optional symbol file for labels.
+// Registers, timers and the call stack appear as variables, and the memory
+// and disassembly views read the emulator's RAM.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chip8_core::disasm::{self, ListingLine};
+use chip8_core::json::Json;
+use chip8_core::symbols::SymbolTable;
+use chip8_core::{Condition, Emu, RunState, Stopped, Variant};
+
+const THREAD_ID: i64 = 1;
+const LISTING_REFERENCE: i64 = 1;
+const REGISTERS_REFERENCE: i64 = 1;
+const TIMERS_REFERENCE: i64 = 2;
+const STACK_REFERENCE: i64 = 3;
+
+const LOAD_ADDR: u16 = 0x200;
+
+// Gives up on a step over or out that doesn't return within this many cycles
+const MAX_STEP_CYCLES: u32 = 1_000_000;
+
+// How long the main loop sleeps between running slices of emulated time
+const RUN_INTERVAL: Duration = Duration::from_millis(4);
+
+fn main() {
+    let messages = spawn_reader();
+    let mut session = Session::new();
+    let mut stdout = io::stdout().lock();
+    let mut seq = 0;
+
+    while !session.done {
+        let message = if session.running {
+            match messages.try_recv() {
+                Ok(message) => Some(message),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match messages.recv() {
+                Ok(message) => Some(message),
+                Err(_) => break,
+            }
+        };
+
+        match message {
+            Some(request) => session.handle(&request),
+            None => {
+                session.run();
+                thread::sleep(RUN_INTERVAL);
+            }
+        }
+
+        for mut message in session.outgoing.drain(..) {
+            seq += 1;
+            if let Json::Object(fields) = &mut message {
+                fields.insert(0, ("seq".to_string(), seq.into()));
+            }
+            if write_message(&mut stdout, &message).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+// Parses messages on a thread of its own so the emulator can keep running
+// while waiting for input. Stops at the end of input or on a framing error.
+fn spawn_reader() -> Receiver<Json> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut stdin = BufReader::new(io::stdin().lock());
+        while let Ok(Some(body)) = read_message(&mut stdin) {
+            match Json::parse(&body) {
+                Ok(message) => {
+                    if sender.send(message).is_err() {
+                        break;
+                    }
+                }
+                Err(error) => eprintln!("chip8-dap: {}", error),
+            }
+        }
+    });
+
+    receiver
+}
+
+// A `Content-Length` framed body, None at the end of input
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+
+    let length = length.ok_or(io::ErrorKind::InvalidData)?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|_| io::ErrorKind::InvalidData.into())
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+struct Session {
+    emu: Emu,
+    symbols: SymbolTable,
+    listing: String,
+    line_addrs: Vec<u16>, // address of each listing line
+    rom_end: u16,
+    line_base: i64, // number of the first line, 0 or 1 as the client asked
+    source_breakpoints: Vec<(u16, Option<Condition>)>,
+    instruction_breakpoints: Vec<(u16, Option<Condition>)>,
+    stop_on_entry: bool,
+    running: bool,
+    last_run: Instant,
+    outgoing: Vec<Json>,
+    done: bool,
+}
+
+impl Session {
+    fn new() -> Self {
+        Self {
+            emu: Emu::new(),
+            symbols: SymbolTable::new(),
+            listing: String::new(),
+            line_addrs: Vec::new(),
+            rom_end: LOAD_ADDR,
+            line_base: 1,
+            source_breakpoints: Vec::new(),
+            instruction_breakpoints: Vec::new(),
+            stop_on_entry: false,
+            running: false,
+            last_run: Instant::now(),
+            outgoing: Vec::new(),
+            done: false,
+        }
+    }
+
+    // Sequence numbers are added when the message is written
+    fn send(&mut self, kind: &str, mut fields: Vec<(&str, Json)>) {
+        fields.insert(0, ("type", kind.into()));
+        self.outgoing.push(Json::object(fields));
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        self.send("event", vec![("event", event.into()), ("body", body)]);
+    }
+
+    // Responds to `request`, events raised while handling it follow the response
+    fn handle(&mut self, request: &Json) {
+        if request.get("type").as_str() != Some("request") {
+            return;
+        }
+        let command = request.get("command").as_str().unwrap_or("");
+        let events = self.outgoing.len();
+
+        let result = self.dispatch(command, request.get("arguments"));
+
+        let mut fields = vec![
+            ("request_seq", request.get("seq").clone()),
+            ("success", result.is_ok().into()),
+            ("command", command.into()),
+        ];
+        match result {
+            Ok(body) => fields.push(("body", body)),
+            Err(message) => fields.push(("message", message.into())),
+        }
+        let events: Vec<_> = self.outgoing.drain(events..).collect();
+        self.send("response", fields);
+        self.outgoing.extend(events);
+    }
+
+    fn dispatch(&mut self, command: &str, args: &Json) -> Result<Json, String> {
+        match command {
+            "initialize" => Ok(self.initialize(args)),
+            "launch" => self.launch(args),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    self.stop("entry", None);
+                } else {
+                    self.resume();
+                }
+                Ok(Json::Null)
+            }
+            "threads" => {
+                let thread = Json::object([("id", THREAD_ID.into()), ("name", "CHIP-8".into())]);
+                Ok(Json::object([("threads", vec![thread].into())]))
+            }
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(self.scopes()),
+            "variables" => Ok(self.variables(args)),
+            "source" => Ok(Json::object([
+                ("content", self.listing.as_str().into()),
+                ("mimeType", "text/x-chip8-asm".into()),
+            ])),
+            "continue" => {
+                self.resume();
+                Ok(Json::object([("allThreadsContinued", true.into())]))
+            }
+            "pause" => {
+                self.stop("pause", None);
+                Ok(Json::Null)
+            }
+            "next" | "stepIn" | "stepOut" => {
+                self.step(command);
+                Ok(Json::Null)
+            }
+            "readMemory" => self.read_memory(args),
+            "disassemble" => self.disassemble(args),
+            "disconnect" => {
+                self.done = true;
+                Ok(Json::Null)
+            }
+            "terminate" => {
+                self.running = false;
+                self.event("terminated", Json::object([]));
+                Ok(Json::Null)
+            }
+            _ => Err(format!("unsupported request '{}'", command)),
+        }
+    }
+
+    fn initialize(&mut self, args: &Json) -> Json {
+        if args.get("linesStartAt1").as_bool() == Some(false) {
+            self.line_base = 0;
+        }
+
+        Json::object([
+            ("supportsConfigurationDoneRequest", true.into()),
+            ("supportsConditionalBreakpoints", true.into()),
+            ("supportsInstructionBreakpoints", true.into()),
+            ("supportsDisassembleRequest", true.into()),
+            ("supportsReadMemoryRequest", true.into()),
+            ("supportsTerminateRequest", true.into()),
+        ])
+    }
+
+    fn launch(&mut self, args: &Json) -> Result<Json, String> {
+        let program = args
+            .get("program")
+            .as_str()
+            .ok_or("missing 'program' path")?;
+        let rom = std::fs::read(program).map_err(|error| format!("{}: {}", program, error))?;
+
+        if let Some(name) = args.get("variant").as_str() {
+            let variant: Variant = name.parse().map_err(|error| format!("{}", error))?;
+            self.emu.set_variant(variant);
+        }
+        self.emu
+            .load(&rom)
+            .map_err(|error| format!("{}: {}", program, error))?;
+
+        if let Some(path) = args.get("symbols").as_str() {
+            let text =
+                std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+            self.symbols =
+                SymbolTable::parse(&text).map_err(|error| format!("{}: {}", path, error))?;
+        }
+
+        self.stop_on_entry = args.get("stopOnEntry").as_bool().unwrap_or(false);
+        self.build_listing(&rom);
+
+        self.event("initialized", Json::Null);
+        Ok(Json::Null)
+    }
+
+    // The static analysis listing of the ROM, label lines belong to the
+    // address they name
+    fn build_listing(&mut self, rom: &[u8]) {
+        let analysis = disasm::analyze(rom, LOAD_ADDR);
+        self.rom_end = LOAD_ADDR + rom.len() as u16;
+
+        for line in analysis.listing() {
+            let addr = match line {
+                ListingLine::Code(line) => line.addr,
+                ListingLine::Data { addr, .. } => addr,
+            };
+            let text = line.with_symbols(&self.symbols).to_string();
+            for text in text.lines() {
+                self.listing.push_str(text);
+                self.listing.push('\n');
+                self.line_addrs.push(addr);
+            }
+        }
+    }
+
+    // Line of the listing showing `addr`, the line of the instruction for
+    // operand bytes, None outside the ROM
+    fn line_of(&self, addr: u16) -> Option<i64> {
+        if !(LOAD_ADDR..self.rom_end).contains(&addr) {
+            return None;
+        }
+        let index = self.line_addrs.partition_point(|&line| line <= addr);
+        Some(index as i64 - 1 + self.line_base)
+    }
+
+    fn set_breakpoints(&mut self, args: &Json) -> Result<Json, String> {
+        let reference = args.get("source").get("sourceReference").as_i64();
+        let mut results = Vec::new();
+        self.source_breakpoints.clear();
+
+        for breakpoint in args.get("breakpoints").as_array() {
+            let line = breakpoint.get("line").as_i64().unwrap_or(0);
+            let addr = match reference {
+                Some(LISTING_REFERENCE) => usize::try_from(line - self.line_base)
+                    .ok()
+                    .and_then(|index| self.line_addrs.get(index).copied()),
+                _ => None,
+            };
+
+            let result = match (addr, parse_condition(breakpoint)) {
+                (Some(addr), Ok(condition)) => {
+                    self.source_breakpoints.push((addr, condition));
+                    Json::object([("verified", true.into()), ("line", line.into())])
+                }
+                (Some(_), Err(message)) => unverified(message),
+                (None, _) if reference == Some(LISTING_REFERENCE) => {
+                    unverified("no instruction on this line".into())
+                }
+                (None, _) => unverified("breakpoints can only be set in the disassembly".into()),
+            };
+            results.push(result);
+        }
+
+        self.sync_breakpoints();
+        Ok(Json::object([("breakpoints", results.into())]))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Json) -> Result<Json, String> {
+        let mut results = Vec::new();
+        self.instruction_breakpoints.clear();
+
+        for breakpoint in args.get("breakpoints").as_array() {
+            let reference = breakpoint.get("instructionReference").as_str();
+            let offset = breakpoint.get("offset").as_i64().unwrap_or(0);
+            let addr = reference
+                .and_then(parse_address)
+                .and_then(|addr| u16::try_from(addr + offset).ok());
+
+            let result = match (addr, parse_condition(breakpoint)) {
+                (Some(addr), Ok(condition)) => {
+                    self.instruction_breakpoints.push((addr, condition));
+                    Json::object([
+                        ("verified", true.into()),
+                        ("instructionReference", format_address(addr).into()),
+                    ])
+                }
+                (None, _) => unverified("invalid instruction reference".into()),
+                (_, Err(message)) => unverified(message),
+            };
+            results.push(result);
+        }
+
+        self.sync_breakpoints();
+        Ok(Json::object([("breakpoints", results.into())]))
+    }
+
+    // The emulator has one set of breakpoints for both kinds
+    fn sync_breakpoints(&mut self) {
+        self.emu.clear_breakpoints();
+        let breakpoints = self.source_breakpoints.iter();
+        for (addr, condition) in breakpoints.chain(&self.instruction_breakpoints) {
+            match condition {
+                Some(condition) => self
+                    .emu
+                    .add_conditional_breakpoint(*addr, condition.clone()),
+                None => self.emu.add_breakpoint(*addr),
+            };
+        }
+    }
+
+    fn frame(&self, id: i64, addr: u16) -> Json {
+        let name = match self.symbols.name(addr) {
+            Some(name) => name.to_string(),
+            None => format_address(addr),
+        };
+        let line = self.line_of(addr);
+        let mut fields = vec![
+            ("id", id.into()),
+            ("name", name.into()),
+            ("line", line.unwrap_or(0).into()),
+            ("column", self.line_base.into()),
+            ("instructionPointerReference", format_address(addr).into()),
+        ];
+
+        // Outside the ROM there's only the disassembly view
+        if line.is_some() {
+            let source = Json::object([
+                ("name", "disassembly".into()),
+                ("sourceReference", LISTING_REFERENCE.into()),
+            ]);
+            fields.push(("source", source));
+        }
+        Json::object(fields)
+    }
+
+    // The current instruction and then the CALLs that led to it
+    fn stack_trace(&self) -> Json {
+        let pc = self.emu.pc();
+        let call_sites: Vec<_> = self.emu.call_sites().collect();
+        let frames: Vec<_> = std::iter::once(pc)
+            .chain(call_sites.into_iter().rev())
+            .enumerate()
+            .map(|(id, addr)| self.frame(id as i64, addr))
+            .collect();
+
+        Json::object([
+            ("totalFrames", (frames.len() as i64).into()),
+            ("stackFrames", frames.into()),
+        ])
+    }
+
+    fn scopes(&self) -> Json {
+        let scope = |name: &str, reference: i64| {
+            Json::object([
+                ("name", name.into()),
+                ("variablesReference", reference.into()),
+                ("expensive", false.into()),
+            ])
+        };
+
+        Json::object([(
+            "scopes",
+            vec![
+                scope("Registers", REGISTERS_REFERENCE),
+                scope("Timers", TIMERS_REFERENCE),
+                scope("Stack", STACK_REFERENCE),
+            ]
+            .into(),
+        )])
+    }
+
+    fn variables(&self, args: &Json) -> Json {
+        let emu = &self.emu;
+        // Addresses can be opened in the memory view
+        let variable = |name: String, value: String, address: Option<u16>| {
+            let mut fields = vec![
+                ("name", name.into()),
+                ("value", value.into()),
+                ("variablesReference", 0.into()),
+            ];
+            if let Some(addr) = address {
+                fields.push(("memoryReference", format_address(addr).into()));
+            }
+            Json::object(fields)
+        };
+
+        let variables: Vec<_> = match args.get("variablesReference").as_i64() {
+            Some(REGISTERS_REFERENCE) => {
+                let v = emu.v_reg().iter().enumerate().map(|(x, value)| {
+                    variable(
+                        format!("V{:X}", x),
+                        format!("{:#04X} ({})", value, value),
+                        None,
+                    )
+                });
+                v.chain([
+                    variable("I".into(), format_address(emu.i_reg()), Some(emu.i_reg())),
+                    variable("PC".into(), format_address(emu.pc()), Some(emu.pc())),
+                    variable("SP".into(), emu.sp().to_string(), None),
+                ])
+                .collect()
+            }
+            Some(TIMERS_REFERENCE) => vec![
+                variable("DT".into(), emu.dt().to_string(), None),
+                variable("ST".into(), emu.st().to_string(), None),
+            ],
+            Some(STACK_REFERENCE) => emu
+                .call_stack()
+                .iter()
+                .enumerate()
+                .map(|(level, &addr)| {
+                    variable(format!("[{}]", level), format_address(addr), Some(addr))
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Json::object([("variables", variables.into())])
+    }
+
+    fn read_memory(&self, args: &Json) -> Result<Json, String> {
+        let addr = args
+            .get("memoryReference")
+            .as_str()
+            .and_then(parse_address)
+            .ok_or("invalid memory reference")?;
+        let start = addr + args.get("offset").as_i64().unwrap_or(0);
+        let count = args.get("count").as_i64().unwrap_or(0).max(0);
+
+        let ram = self.emu.ram();
+        let readable_start = start.clamp(0, ram.len() as i64);
+        let readable_end = (start + count).clamp(readable_start, ram.len() as i64);
+        let data = &ram[readable_start as usize..readable_end as usize];
+
+        Ok(Json::object([
+            ("address", format_address(start.max(0) as u16).into()),
+            ("data", base64(data).into()),
+            ("unreadableBytes", (count - data.len() as i64).into()),
+        ]))
+    }
+
+    // Decodes RAM from the requested address, counting instructions as
+    // two bytes when going backwards since there's no telling where
+    // earlier ones start
+    fn disassemble(&self, args: &Json) -> Result<Json, String> {
+        let addr = args
+            .get("memoryReference")
+            .as_str()
+            .and_then(parse_address)
+            .ok_or("invalid memory reference")?;
+        let offset = args.get("offset").as_i64().unwrap_or(0);
+        let instruction_offset = args.get("instructionOffset").as_i64().unwrap_or(0);
+        let count = args.get("instructionCount").as_i64().unwrap_or(0).max(0) as usize;
+
+        let ram = self.emu.ram();
+        let mut start = addr + offset + instruction_offset * 2;
+        let mut instructions = Vec::new();
+
+        // Placeholders for addresses before RAM
+        while start < 0 && instructions.len() < count {
+            instructions.push(Json::object([
+                ("address", format!("-{:#05X}", -start).into()),
+                ("instruction", "".into()),
+                ("presentationHint", "invalid".into()),
+            ]));
+            start += 2;
+        }
+
+        let start = (start as usize).min(ram.len());
+        for line in disasm::disassemble(&ram[start..], start as u16) {
+            if instructions.len() == count {
+                break;
+            }
+            let bytes = &ram[line.addr as usize..][..line.size as usize];
+            let bytes: String = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            let text = line.to_string();
+            let mut fields = vec![
+                ("address", format_address(line.addr).into()),
+                ("instructionBytes", bytes.into()),
+                // Without the address the line starts with
+                (
+                    "instruction",
+                    text[text.find(": ").map_or(0, |i| i + 2)..].into(),
+                ),
+            ];
+            if let Some(name) = self.symbols.name(line.addr) {
+                fields.push(("symbol", name.into()));
+            }
+            instructions.push(Json::object(fields));
+        }
+
+        Ok(Json::object([("instructions", instructions.into())]))
+    }
+
+    fn resume(&mut self) {
+        self.running = true;
+        self.last_run = Instant::now();
+    }
+
+    // Reports a stop to the client, or the end of the program once it halted
+    fn stop(&mut self, reason: &str, text: Option<String>) {
+        self.running = false;
+
+        if self.emu.run_state() == RunState::Halted {
+            self.event("exited", Json::object([("exitCode", 0.into())]));
+            self.event("terminated", Json::object([]));
+            return;
+        }
+
+        let mut fields = vec![
+            ("reason", reason.into()),
+            ("threadId", THREAD_ID.into()),
+            ("allThreadsStopped", true.into()),
+        ];
+        if let Some(text) = text {
+            fields.push(("text", text.into()));
+        }
+        self.event("stopped", Json::object(fields));
+    }
+
+    // Time doesn't pass while stepping, so a DXYN waiting for vblank is
+    // released right away
+    fn step(&mut self, command: &str) {
+        self.emu.vblank();
+
+        let result = match command {
+            "next" => self.emu.step_over(MAX_STEP_CYCLES),
+            "stepOut" => self.emu.step_out(MAX_STEP_CYCLES),
+            _ => self.emu.tick().map(|outcome| outcome.stopped.is_none()),
+        };
+
+        match result {
+            Ok(false) if self.emu.breakpoints().any(|addr| addr == self.emu.pc()) => {
+                self.stop("breakpoint", None)
+            }
+            Ok(_) => self.stop("step", None),
+            Err(error) => self.stop("exception", Some(error.to_string())),
+        }
+    }
+
+    // Catches up on the wall-clock time since the last call
+    fn run(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.last_run;
+        self.last_run = now;
+
+        match self.emu.advance(elapsed) {
+            Ok(Some(Stopped::Breakpoint(_))) => self.stop("breakpoint", None),
+            Ok(Some(Stopped::Watchpoint(_))) => self.stop("data breakpoint", None),
+            Ok(None) if self.emu.run_state() == RunState::Halted => self.stop("exit", None),
+            Ok(None) => {}
+            Err(error) => self.stop("exception", Some(error.to_string())),
+        }
+    }
+}
+
+fn unverified(message: String) -> Json {
+    Json::object([("verified", false.into()), ("message", message.into())])
+}
+
+fn parse_condition(breakpoint: &Json) -> Result<Option<Condition>, String> {
+    match breakpoint.get("condition").as_str() {
+        Some(text) if !text.trim().is_empty() => Condition::parse(text)
+            .map(Some)
+            .map_err(|error| error.to_string()),
+        _ => Ok(None),
+    }
+}
+
+// Memory and instruction references are hex addresses like `0x200`
+fn parse_address(reference: &str) -> Option<i64> {
+    let hex = reference
+        .strip_prefix("0x")
+        .or_else(|| reference.strip_prefix("0X"))?;
+    i64::from_str_radix(hex, 16).ok()
+}
+
+fn format_address(addr: u16) -> String {
+    format!("{:#05X}", addr)
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
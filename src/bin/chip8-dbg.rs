@@ -0,0 +1,400 @@
+// Interactive command-line debugger:
+//
+//     chip8-dbg [--variant NAME] [--symbols FILE] ROM
+//
+// Type `help` at the prompt for the commands. Addresses are hex with a 0x
+// prefix, decimal, or labels from the symbol file.
+
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+use std::process::ExitCode;
+
+use chip8_core::disasm;
+use chip8_core::symbols::SymbolTable;
+use chip8_core::{Condition, Emu, EmuError, RunState, Stopped, Variant, WatchKind};
+
+// Gives up on a step over or out that doesn't return within this many cycles
+const MAX_STEP_CYCLES: u32 = 1_000_000;
+
+// `continue` runs at most this many frames, a minute of emulated time
+const MAX_CONTINUE_FRAMES: u32 = 3600;
+
+const DEFAULT_MEM_LEN: usize = 64;
+const DEFAULT_DISAS_COUNT: usize = 10;
+
+const HELP: &str = "\
+break ADDR [CONDITION]   stop before ADDR runs, optionally only if CONDITION holds
+delete ADDR              remove the breakpoint at ADDR
+watch ADDR[..END] [r|w]  stop after RAM at ADDR is read or written
+unwatch ADDR[..END]      remove a watchpoint
+list                     show breakpoints and watchpoints
+step [N]                 run N instructions (s)
+next                     run one instruction, stepping over calls (n)
+finish                   run until the current subroutine returns
+continue [FRAMES]        run until a stop, a halt or FRAMES frames (c)
+regs                     show registers and timers (r)
+stack                    show the call stack (bt)
+mem ADDR [LEN]           hex dump of RAM (x)
+disas [ADDR] [COUNT]     disassemble, from PC by default (d)
+reset                    restart the program
+quit                     exit (q)";
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("chip8-dbg: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<(), String> {
+    let mut args = std::env::args().skip(1);
+    let mut variant = None;
+    let mut symbols = SymbolTable::new();
+    let mut rom_path = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--variant" => {
+                let name = args.next().ok_or("--variant needs a name")?;
+                variant = Some(name.parse::<Variant>().map_err(|error| error.to_string())?);
+            }
+            "--symbols" => {
+                let path = args.next().ok_or("--symbols needs a file")?;
+                let text = std::fs::read_to_string(&path)
+                    .map_err(|error| format!("{}: {}", path, error))?;
+                symbols =
+                    SymbolTable::parse(&text).map_err(|error| format!("{}: {}", path, error))?;
+            }
+            _ if rom_path.is_none() && !arg.starts_with('-') => rom_path = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    let rom_path = rom_path.ok_or("usage: chip8-dbg [--variant NAME] [--symbols FILE] ROM")?;
+    let rom = std::fs::read(&rom_path).map_err(|error| format!("{}: {}", rom_path, error))?;
+
+    let mut debugger = Debugger {
+        emu: Emu::new(),
+        variant,
+        symbols,
+        rom,
+    };
+    debugger.reset()?;
+    debugger.show_location();
+
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut previous = String::new();
+
+    loop {
+        print!("(chip8) ");
+        io::stdout().flush().map_err(|error| error.to_string())?;
+
+        let mut line = String::new();
+        if input
+            .read_line(&mut line)
+            .map_err(|error| error.to_string())?
+            == 0
+        {
+            return Ok(());
+        }
+
+        // An empty line repeats the previous command, handy for stepping
+        let line = match line.trim() {
+            "" => previous.clone(),
+            line => line.to_string(),
+        };
+        previous.clone_from(&line);
+
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            continue;
+        };
+        let args: Vec<&str> = words.collect();
+
+        match debugger.command(command, &args) {
+            Ok(true) => {}
+            Ok(false) => return Ok(()),
+            Err(message) => println!("error: {}", message),
+        }
+    }
+}
+
+struct Debugger {
+    emu: Emu,
+    variant: Option<Variant>,
+    symbols: SymbolTable,
+    rom: Vec<u8>,
+}
+
+impl Debugger {
+    // Keeps breakpoints and watchpoints, like restarting a program in gdb
+    fn reset(&mut self) -> Result<(), String> {
+        self.emu.reset();
+        if let Some(variant) = self.variant {
+            self.emu.set_variant(variant);
+        }
+        self.emu.load(&self.rom).map_err(|error| error.to_string())
+    }
+
+    // False once the user quits
+    fn command(&mut self, command: &str, args: &[&str]) -> Result<bool, String> {
+        match command {
+            "break" | "b" => {
+                let addr = self.address(args.first().ok_or("break needs an address")?)?;
+                let condition = args[1..].join(" ");
+                if condition.is_empty() {
+                    self.emu.add_breakpoint(addr);
+                } else {
+                    let condition =
+                        Condition::parse(&condition).map_err(|error| error.to_string())?;
+                    self.emu.add_conditional_breakpoint(addr, condition);
+                }
+                println!("breakpoint at {}", self.describe(addr));
+            }
+            "delete" => {
+                let addr = self.address(args.first().ok_or("delete needs an address")?)?;
+                if !self.emu.remove_breakpoint(addr) {
+                    return Err(format!("no breakpoint at {:#05X}", addr));
+                }
+            }
+            "watch" => {
+                let range = self.range(args.first().ok_or("watch needs an address")?)?;
+                let kind = match args.get(1).copied() {
+                    None | Some("rw") => WatchKind::ReadWrite,
+                    Some("r") => WatchKind::Read,
+                    Some("w") => WatchKind::Write,
+                    Some(other) => return Err(format!("unknown access '{}'", other)),
+                };
+                self.emu.add_watchpoint(range, kind);
+            }
+            "unwatch" => {
+                let range = self.range(args.first().ok_or("unwatch needs an address")?)?;
+                if !self.emu.remove_watchpoint(range) {
+                    return Err("no watchpoint on that range".into());
+                }
+            }
+            "list" => self.list(),
+            "step" | "s" => {
+                let count = match args.first() {
+                    Some(count) => count.parse().map_err(|_| "invalid count")?,
+                    None => 1,
+                };
+                for _ in 0..count {
+                    // Stepping doesn't wait for the next frame
+                    self.emu.vblank();
+                    let outcome = self.emu.tick().map_err(|error| error.to_string())?;
+                    if let Some(stopped) = outcome.stopped {
+                        self.show_stop(stopped);
+                        break;
+                    }
+                }
+                self.show_location();
+            }
+            "next" | "n" => {
+                self.emu.vblank();
+                let done = self.emu.step_over(MAX_STEP_CYCLES);
+                self.finish_step(done)?;
+            }
+            "finish" => {
+                if self.emu.sp() == 0 {
+                    return Err("not in a subroutine".into());
+                }
+                let done = self.emu.step_out(MAX_STEP_CYCLES);
+                self.finish_step(done)?;
+            }
+            "continue" | "c" => {
+                let frames = match args.first() {
+                    Some(frames) => frames.parse().map_err(|_| "invalid frame count")?,
+                    None => MAX_CONTINUE_FRAMES,
+                };
+                self.continue_for(frames)?;
+            }
+            "regs" | "r" => self.regs(),
+            "stack" | "bt" => self.stack(),
+            "mem" | "x" => {
+                let addr = self.address(args.first().ok_or("mem needs an address")?)?;
+                let len = match args.get(1) {
+                    Some(len) => parse_number(len).ok_or("invalid length")?,
+                    None => DEFAULT_MEM_LEN,
+                };
+                let start = addr as usize;
+                print!("{}", self.emu.dump_ram(start..start.saturating_add(len)));
+            }
+            "disas" | "d" => {
+                let addr = match args.first() {
+                    Some(addr) => self.address(addr)?,
+                    None => self.emu.pc(),
+                };
+                let count = match args.get(1) {
+                    Some(count) => parse_number(count).ok_or("invalid count")?,
+                    None => DEFAULT_DISAS_COUNT,
+                };
+                self.disas(addr, count);
+            }
+            "reset" => {
+                self.reset()?;
+                self.show_location();
+            }
+            "help" | "h" | "?" => println!("{}", HELP),
+            "quit" | "q" => return Ok(false),
+            _ => return Err(format!("unknown command '{}', try help", command)),
+        }
+
+        Ok(true)
+    }
+
+    fn address(&self, text: &str) -> Result<u16, String> {
+        self.symbols
+            .address(text)
+            .or_else(|| parse_number(text).and_then(|addr| u16::try_from(addr).ok()))
+            .ok_or_else(|| format!("invalid address '{}'", text))
+    }
+
+    // ADDR for a single byte or ADDR..END
+    fn range(&self, text: &str) -> Result<Range<usize>, String> {
+        match text.split_once("..") {
+            Some((start, end)) => Ok(self.address(start)? as usize..self.address(end)? as usize),
+            None => {
+                let addr = self.address(text)? as usize;
+                Ok(addr..addr + 1)
+            }
+        }
+    }
+
+    // `0x208 <sub>`, or just the address without a label
+    fn describe(&self, addr: u16) -> String {
+        match self.symbols.name(addr) {
+            Some(name) => format!("{:#05X} <{}>", addr, name),
+            None => format!("{:#05X}", addr),
+        }
+    }
+
+    fn list(&self) {
+        for addr in self.emu.breakpoints() {
+            match self.emu.breakpoint_condition(addr) {
+                Some(_) => println!("break {} (conditional)", self.describe(addr)),
+                None => println!("break {}", self.describe(addr)),
+            }
+        }
+        for (range, kind) in self.emu.watchpoints() {
+            println!("watch {:#05X}..{:#05X} {:?}", range.start, range.end, kind);
+        }
+    }
+
+    fn finish_step(&mut self, done: Result<bool, EmuError>) -> Result<(), String> {
+        let done = done.map_err(|error| error.to_string())?;
+        if !done && self.emu.run_state() != RunState::Halted {
+            println!("stopped before the step finished");
+        }
+        self.show_location();
+        Ok(())
+    }
+
+    fn continue_for(&mut self, frames: u32) -> Result<(), String> {
+        let cycles = self.emu.instructions_per_second() / 60;
+
+        for _ in 0..frames {
+            let result = self
+                .emu
+                .run_frame(cycles)
+                .map_err(|error| error.to_string())?;
+            if let Some(stopped) = result.stopped {
+                self.show_stop(stopped);
+                break;
+            }
+            if matches!(result.run_state, RunState::Halted | RunState::Spinning) {
+                break;
+            }
+        }
+
+        self.show_location();
+        Ok(())
+    }
+
+    fn show_stop(&self, stopped: Stopped) {
+        match stopped {
+            Stopped::Breakpoint(addr) => println!("breakpoint at {}", self.describe(addr)),
+            Stopped::Watchpoint(hit) => println!(
+                "{:?} of {:#05X} by {}: {:#04X} -> {:#04X}",
+                hit.access,
+                hit.addr,
+                self.describe(hit.pc),
+                hit.old,
+                hit.new
+            ),
+        }
+    }
+
+    // The next instruction, or why there isn't one
+    fn show_location(&self) {
+        match self.emu.run_state() {
+            RunState::Halted => println!("program exited"),
+            RunState::Spinning => println!("program is stuck in a loop"),
+            RunState::WaitingForVblank => println!("waiting for vblank"),
+            RunState::Running => {}
+        }
+        self.disas(self.emu.pc(), 1);
+    }
+
+    fn disas(&self, addr: u16, count: usize) {
+        let ram = self.emu.ram();
+        let start = (addr as usize).min(ram.len());
+        let lines = disasm::disassemble(&ram[start..], start as u16);
+
+        for line in lines.take(count) {
+            let marker = if line.addr == self.emu.pc() {
+                "=>"
+            } else {
+                "  "
+            };
+            let text = line.with_symbols(&self.symbols).to_string();
+            for text in text.lines() {
+                // Label lines get no marker
+                match text.ends_with(':') {
+                    true => println!("{}", text),
+                    false => println!("{} {}", marker, text),
+                }
+            }
+        }
+    }
+
+    fn regs(&self) {
+        let emu = &self.emu;
+        for (row, regs) in emu.v_reg().chunks(8).enumerate() {
+            let regs: Vec<_> = regs
+                .iter()
+                .enumerate()
+                .map(|(x, value)| format!("V{:X}={:02X}", row * 8 + x, value))
+                .collect();
+            println!("{}", regs.join(" "));
+        }
+        println!(
+            "PC={}  I={:#05X}  SP={}  DT={}  ST={}",
+            self.describe(emu.pc()),
+            emu.i_reg(),
+            emu.sp(),
+            emu.dt(),
+            emu.st()
+        );
+    }
+
+    // Innermost first, each caller shown at its CALL
+    fn stack(&self) {
+        println!("#0  {}", self.describe(self.emu.pc()));
+        let call_sites: Vec<_> = self.emu.call_sites().collect();
+        for (level, addr) in call_sites.into_iter().rev().enumerate() {
+            println!("#{}  {}", level + 1, self.describe(addr));
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
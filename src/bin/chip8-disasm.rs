@@ -0,0 +1,183 @@
+// Disassembler:
+//
+//     chip8-disasm [--linear] [--json] [--symbols FILE] ROM
+//
+// By default control flow is followed from 0x200 to tell code from data,
+// see disasm::analyze(). --linear decodes every two bytes as an instruction
+// instead. --json prints an array of lines for other tools:
+//
+//     {"addr":512,"kind":"code","bytes":"00E0","text":"CLS","label":"main"}
+
+use std::fmt::Write;
+use std::process::ExitCode;
+
+use chip8_core::disasm::{self, Line, ListingLine};
+use chip8_core::symbols::SymbolTable;
+
+const LOAD_ADDR: u16 = 0x200;
+
+// Column of the hex bytes comment in text listings
+const BYTES_COLUMN: usize = 32;
+
+#[derive(Default)]
+struct Options {
+    linear: bool,
+    json: bool,
+    symbols: SymbolTable,
+    rom: Option<String>,
+}
+
+// A listing line in the form both outputs need
+struct Entry<'a> {
+    addr: u16,
+    code: bool,
+    bytes: &'a [u8],
+    text: String, // with symbols, may start with `label:` lines
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("chip8-disasm: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<(), String> {
+    let options = parse_options()?;
+    let rom_path = options
+        .rom
+        .as_ref()
+        .ok_or("usage: chip8-disasm [--linear] [--json] [--symbols FILE] ROM")?;
+    let rom = std::fs::read(rom_path).map_err(|error| format!("{}: {}", rom_path, error))?;
+    let symbols = &options.symbols;
+
+    let entries: Vec<Entry> = if options.linear {
+        disasm::disassemble(&rom, LOAD_ADDR)
+            .map(|line| code_entry(&rom, line, symbols))
+            .collect()
+    } else {
+        let analysis = disasm::analyze(&rom, LOAD_ADDR);
+        analysis
+            .listing()
+            .map(|line| match line {
+                ListingLine::Code(code) => code_entry(&rom, code, symbols),
+                ListingLine::Data { addr, bytes } => Entry {
+                    addr,
+                    code: false,
+                    bytes,
+                    text: line.with_symbols(symbols).to_string(),
+                },
+            })
+            .collect()
+    };
+
+    let output = match options.json {
+        true => json(&entries, symbols),
+        false => text(&entries),
+    };
+    print!("{}", output);
+    Ok(())
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--linear" => options.linear = true,
+            "--json" => options.json = true,
+            "--symbols" => {
+                let path = args.next().ok_or("--symbols needs a file")?;
+                let text = std::fs::read_to_string(&path)
+                    .map_err(|error| format!("{}: {}", path, error))?;
+                options.symbols =
+                    SymbolTable::parse(&text).map_err(|error| format!("{}: {}", path, error))?;
+            }
+            _ if options.rom.is_none() && !arg.starts_with('-') => options.rom = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    Ok(options)
+}
+
+fn code_entry<'a>(rom: &'a [u8], line: Line, symbols: &SymbolTable) -> Entry<'a> {
+    let offset = (line.addr - LOAD_ADDR) as usize;
+    Entry {
+        addr: line.addr,
+        code: line.instruction.is_ok(),
+        bytes: &rom[offset..offset + line.size as usize],
+        text: line.with_symbols(symbols).to_string(),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+// The listing with the bytes of each line in a comment
+fn text(entries: &[Entry]) -> String {
+    let mut out = String::new();
+
+    for entry in entries {
+        let mut lines = entry.text.lines().peekable();
+        while let Some(line) = lines.next() {
+            // The instruction comes after any label lines
+            if lines.peek().is_some() {
+                let _ = writeln!(out, "{}", line);
+            } else {
+                let _ = writeln!(out, "{:<BYTES_COLUMN$}; {}", line, hex(entry.bytes));
+            }
+        }
+    }
+
+    out
+}
+
+fn json(entries: &[Entry], symbols: &SymbolTable) -> String {
+    let mut out = String::from("[\n");
+
+    for (index, entry) in entries.iter().enumerate() {
+        // Label lines and the address prefix are separate fields in JSON
+        let line = entry.text.lines().last().unwrap_or("");
+        let text = line.split_once(": ").map_or(line, |(_, text)| text);
+        let kind = if entry.code { "code" } else { "data" };
+
+        let _ = write!(
+            out,
+            "  {{\"addr\":{},\"kind\":\"{}\",\"bytes\":\"{}\",\"text\":{}",
+            entry.addr,
+            kind,
+            hex(entry.bytes),
+            json_string(text)
+        );
+        if let Some(label) = symbols.name(entry.addr) {
+            let _ = write!(out, ",\"label\":{}", json_string(label));
+        }
+        let separator = if index + 1 == entries.len() { "" } else { "," };
+        let _ = writeln!(out, "}}{}", separator);
+    }
+
+    out.push_str("]\n");
+    out
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,283 @@
+// Headless runner for CI and regression checks:
+//
+//     chip8-run [OPTIONS] ROM
+//
+//     --variant NAME   quirks and memory of a platform, e.g. schip-modern
+//     --frames N       frames to run at most, 600 (10 seconds) by default
+//     --ips N          instructions per second, 700 by default
+//     --ascii          print the final screen, one character per pixel
+//     --blocks         print it with half blocks, two pixels per character
+//     --braille        print it in braille, eight pixels per character
+//     --hash           print a hash of the final screen
+//     --png FILE       save the final screen as a PNG image
+//     --wav FILE       save the sound of the run as a WAV file
+//     --state          print the registers and counters
+//
+// The run ends early when the program exits or gets stuck in a loop, like
+// the endless jump most test ROMs finish with. Exits with 2 if the ROM
+// faults, after dumping what was asked for.
+
+use std::io;
+use std::process::ExitCode;
+
+use chip8_core::hash::crc32;
+use chip8_core::{AudioRecorder, Emu, EmuError, Palette, RunState, TextStyle, Variant};
+
+const DEFAULT_FRAMES: u32 = 600;
+const FRAMES_PER_SECOND: u32 = 60;
+const SAMPLE_RATE: u32 = 44_100;
+
+// Background, plane 0, plane 1, both planes
+const ASCII_PIXELS: [char; 4] = ['.', '#', 'o', '@'];
+
+const FNV_OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01B3;
+
+// Stored deflate blocks hold at most this many bytes
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+#[derive(Default)]
+struct Options {
+    variant: Option<Variant>,
+    frames: Option<u32>,
+    ips: Option<u32>,
+    ascii: bool,
+    text: Option<TextStyle>,
+    hash: bool,
+    png: Option<String>,
+    wav: Option<String>,
+    state: bool,
+    rom: Option<String>,
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(None) => ExitCode::SUCCESS,
+        Ok(Some(error)) => {
+            eprintln!("chip8-run: {}", error);
+            ExitCode::from(2)
+        }
+        Err(message) => {
+            eprintln!("chip8-run: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// The fault that ended the run, if any
+fn run() -> Result<Option<EmuError>, String> {
+    let options = parse_options()?;
+    let rom_path = options
+        .rom
+        .as_ref()
+        .ok_or("usage: chip8-run [OPTIONS] ROM")?;
+
+    let mut emu = Emu::new();
+    if let Some(variant) = options.variant {
+        emu.set_variant(variant);
+    }
+    if let Some(ips) = options.ips {
+        emu.set_instructions_per_second(ips);
+    }
+    emu.load_rom_file(rom_path)
+        .map_err(|error| format!("{}: {}", rom_path, error))?;
+
+    let cycles = emu.instructions_per_second() / FRAMES_PER_SECOND;
+    let mut fault = None;
+    let mut recorder = AudioRecorder::new(SAMPLE_RATE);
+    let mut samples = [0.0; (SAMPLE_RATE / FRAMES_PER_SECOND) as usize];
+    for _ in 0..options.frames.unwrap_or(DEFAULT_FRAMES) {
+        // The sound of the frame about to run, as the timer is during it
+        if options.wav.is_some() {
+            recorder.render_audio(&mut emu, &mut samples);
+        }
+        match emu.run_frame(cycles) {
+            Ok(result) if matches!(result.run_state, RunState::Halted | RunState::Spinning) => {
+                break
+            }
+            Ok(_) => {}
+            Err(error) => {
+                fault = Some(error);
+                break;
+            }
+        }
+    }
+
+    if options.ascii {
+        print_ascii(&emu);
+    }
+    if let Some(style) = options.text {
+        let mut text = String::new();
+        let _ = emu.render_text(&mut text, style);
+        print!("{}", text);
+    }
+    if options.hash {
+        println!("{:016x}", screen_hash(&emu));
+    }
+    if let Some(path) = &options.png {
+        save_png(&emu, path).map_err(|error| format!("{}: {}", path, error))?;
+    }
+    if let Some(path) = &options.wav {
+        recorder
+            .save_wav(path)
+            .map_err(|error| format!("{}: {}", path, error))?;
+    }
+    if options.state {
+        print_state(&emu, fault);
+    }
+
+    Ok(fault)
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        match arg.as_str() {
+            "--variant" => {
+                let variant = value("--variant")?.parse();
+                options.variant = Some(variant.map_err(|error| format!("{}", error))?);
+            }
+            "--frames" => {
+                let frames = value("--frames")?.parse();
+                options.frames = Some(frames.map_err(|_| "invalid frame count")?);
+            }
+            "--ips" => {
+                let ips = value("--ips")?.parse();
+                options.ips = Some(ips.map_err(|_| "invalid instructions per second")?);
+            }
+            "--ascii" => options.ascii = true,
+            "--blocks" => options.text = Some(TextStyle::HalfBlock),
+            "--braille" => options.text = Some(TextStyle::Braille),
+            "--hash" => options.hash = true,
+            "--png" => options.png = Some(value("--png")?),
+            "--wav" => options.wav = Some(value("--wav")?),
+            "--state" => options.state = true,
+            _ if options.rom.is_none() && !arg.starts_with('-') => options.rom = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    Ok(options)
+}
+
+fn print_ascii(emu: &Emu) {
+    let width = emu.resolution().width();
+    let pixels: Vec<char> = emu
+        .get_display_colors()
+        .map(|color| ASCII_PIXELS[color as usize])
+        .collect();
+
+    for row in pixels.chunks(width) {
+        println!("{}", row.iter().collect::<String>());
+    }
+}
+
+// FNV-1a over the resolution and the color of every pixel, so it only
+// changes when the picture does
+fn screen_hash(emu: &Emu) -> u64 {
+    let resolution = emu.resolution();
+    let size = [resolution.width() as u8, resolution.height() as u8];
+
+    size.into_iter()
+        .chain(emu.get_display_colors())
+        .fold(FNV_OFFSET_BASIS, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+        })
+}
+
+fn print_state(emu: &Emu, fault: Option<EmuError>) {
+    match fault {
+        Some(error) => println!("state: faulted, {}", error),
+        None => println!("state: {:?}", emu.run_state()),
+    }
+
+    for (row, regs) in emu.v_reg().chunks(8).enumerate() {
+        let regs: Vec<_> = regs
+            .iter()
+            .enumerate()
+            .map(|(x, value)| format!("V{:X}={:02X}", row * 8 + x, value))
+            .collect();
+        println!("{}", regs.join(" "));
+    }
+    println!(
+        "PC={:#05X} I={:#05X} SP={} DT={} ST={}",
+        emu.pc(),
+        emu.i_reg(),
+        emu.sp(),
+        emu.dt(),
+        emu.st()
+    );
+
+    let stack: Vec<_> = emu
+        .call_stack()
+        .iter()
+        .map(|addr| format!("{:#05X}", addr))
+        .collect();
+    println!("stack: [{}]", stack.join(", "));
+
+    let metrics = emu.metrics();
+    println!(
+        "frames: {} instructions: {} draws: {}",
+        metrics.frames, metrics.instructions, metrics.draws
+    );
+}
+
+// RGBA image in Octo's colors, deflated with stored blocks since screens
+// are tiny
+fn save_png(emu: &Emu, path: &str) -> io::Result<()> {
+    let (width, height) = (emu.resolution().width(), emu.resolution().height());
+    let mut pixels = vec![0; width * height * 4];
+    emu.render_rgba(&mut pixels, &Palette::default());
+
+    // Every row starts with filter type 0, none
+    let mut raw = Vec::with_capacity(pixels.len() + height);
+    for row in pixels.chunks(width * 4) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let blocks = raw.chunks(MAX_STORED_BLOCK);
+    let last = blocks.len() - 1;
+    for (index, block) in blocks.enumerate() {
+        let len = block.len() as u16;
+        zlib.push((index == last) as u8);
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, RGBA, default compression, filtering and no interlacing
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib);
+    write_chunk(&mut png, b"IEND", &[]);
+    std::fs::write(path, png)
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
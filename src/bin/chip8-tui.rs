@@ -0,0 +1,384 @@
+// Terminal frontend:
+//
+//     chip8-tui [--variant NAME] [--ips N] [--symbols FILE] ROM
+//
+// Shows the screen in half blocks next to the registers and the code at PC.
+// The keypad is on the left of a QWERTY keyboard:
+//
+//     1 2 3 4        1 2 3 C
+//     Q W E R   ->   4 5 6 D
+//     A S D F        7 8 9 E
+//     Z X C V        A 0 B F
+//
+// Space pauses, `.` steps one instruction and `n` steps over a call while
+// paused, Ctrl-R restarts the program and Ctrl-C or Ctrl-Q quits.
+//
+// Drawn with ANSI escape sequences and put in raw mode with stty, so it
+// needs nothing beyond std but only runs in a Unix-like terminal. Terminals only report
+// key presses, a key counts as held until it hasn't repeated for a few
+// frames.
+
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::process::{Command, ExitCode, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chip8_core::disasm;
+use chip8_core::symbols::SymbolTable;
+use chip8_core::{Emu, Key, RunState, Stopped, TextStyle, Variant};
+
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// Longer than the delay before a terminal starts repeating a held key
+const KEY_HOLD_FRAMES: u8 = 30;
+
+// Gives up on a step over that doesn't return within this many cycles
+const MAX_STEP_CYCLES: u32 = 1_000_000;
+
+const DISASM_LINES: usize = 12;
+
+// Keypad keys by row, as laid out on the keyboard
+const KEYPAD: [[(u8, Key); 4]; 4] = [
+    [
+        (b'1', Key::Key1),
+        (b'2', Key::Key2),
+        (b'3', Key::Key3),
+        (b'4', Key::KeyC),
+    ],
+    [
+        (b'q', Key::Key4),
+        (b'w', Key::Key5),
+        (b'e', Key::Key6),
+        (b'r', Key::KeyD),
+    ],
+    [
+        (b'a', Key::Key7),
+        (b's', Key::Key8),
+        (b'd', Key::Key9),
+        (b'f', Key::KeyE),
+    ],
+    [
+        (b'z', Key::KeyA),
+        (b'x', Key::Key0),
+        (b'c', Key::KeyB),
+        (b'v', Key::KeyF),
+    ],
+];
+
+const CTRL_C: u8 = 0x03;
+const CTRL_Q: u8 = 0x11;
+const CTRL_R: u8 = 0x12;
+
+const HELP: &str = "space pause  . step  n next  ^R reset  ^C quit";
+
+#[derive(Default)]
+struct Options {
+    variant: Option<Variant>,
+    ips: Option<u32>,
+    symbols: SymbolTable,
+    rom: Option<String>,
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("chip8-tui: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<(), String> {
+    // Windows consoles have neither stty nor the same raw mode
+    if !cfg!(unix) {
+        return Err("needs a Unix-like terminal with stty".to_string());
+    }
+
+    let options = parse_options()?;
+    let rom_path = options
+        .rom
+        .as_ref()
+        .ok_or("usage: chip8-tui [--variant NAME] [--ips N] [--symbols FILE] ROM")?;
+    let rom = std::fs::read(rom_path).map_err(|error| format!("{}: {}", rom_path, error))?;
+
+    let mut frontend = Frontend {
+        emu: Emu::new(),
+        options,
+        rom,
+        held: [0; 16],
+        paused: false,
+        status: String::new(),
+    };
+    frontend.reset()?;
+
+    let _terminal = RawTerminal::enter().map_err(|error| format!("terminal: {}", error))?;
+    let input = spawn_input();
+    let mut next_frame = Instant::now();
+
+    loop {
+        while let Ok(byte) = input.try_recv() {
+            if !frontend.input(byte)? {
+                return Ok(());
+            }
+        }
+        frontend.run_frame();
+        frontend.draw().map_err(|error| error.to_string())?;
+
+        next_frame += FRAME_TIME;
+        match next_frame.checked_duration_since(Instant::now()) {
+            Some(wait) => thread::sleep(wait),
+            // Too slow to keep up, don't try to catch up
+            None => next_frame = Instant::now(),
+        }
+    }
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        match arg.as_str() {
+            "--variant" => {
+                let variant = value("--variant")?.parse();
+                options.variant = Some(variant.map_err(|error| format!("{}", error))?);
+            }
+            "--ips" => {
+                let ips = value("--ips")?.parse();
+                options.ips = Some(ips.map_err(|_| "invalid instructions per second")?);
+            }
+            "--symbols" => {
+                let path = value("--symbols")?;
+                let text = std::fs::read_to_string(&path)
+                    .map_err(|error| format!("{}: {}", path, error))?;
+                options.symbols =
+                    SymbolTable::parse(&text).map_err(|error| format!("{}: {}", path, error))?;
+            }
+            _ if options.rom.is_none() && !arg.starts_with('-') => options.rom = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    Ok(options)
+}
+
+// Raw mode without echo in the alternate screen, restored when dropped
+struct RawTerminal {
+    saved: String, // `stty -g` settings
+}
+
+impl RawTerminal {
+    fn enter() -> io::Result<Self> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        print!("\x1b[?1049h\x1b[?25l\x1b[2J");
+        io::stdout().flush()?;
+        Ok(Self {
+            saved: saved.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+// stty works on the terminal it gets as stdin
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("stty failed, not a terminal?"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// Bytes typed, read on a thread so the frame loop never blocks
+fn spawn_input() -> Receiver<u8> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = [0; 64];
+        while let Ok(len @ 1..) = io::stdin().read(&mut buffer) {
+            for &byte in &buffer[..len] {
+                if sender.send(byte).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    receiver
+}
+
+struct Frontend {
+    emu: Emu,
+    options: Options,
+    rom: Vec<u8>,
+    held: [u8; 16], // frames each key stays down without a repeat
+    paused: bool,
+    status: String, // why the program stopped, if it did
+}
+
+impl Frontend {
+    fn reset(&mut self) -> Result<(), String> {
+        self.emu.reset();
+        if let Some(variant) = self.options.variant {
+            self.emu.set_variant(variant);
+        }
+        if let Some(ips) = self.options.ips {
+            self.emu.set_instructions_per_second(ips);
+        }
+        self.held = [0; 16];
+        self.status.clear();
+        self.emu.load(&self.rom).map_err(|error| error.to_string())
+    }
+
+    // False once the user quits
+    fn input(&mut self, byte: u8) -> Result<bool, String> {
+        match byte.to_ascii_lowercase() {
+            CTRL_C | CTRL_Q => return Ok(false),
+            CTRL_R => self.reset()?,
+            b' ' => self.paused = !self.paused,
+            b'.' if self.paused => {
+                // Stepping doesn't wait for the next frame
+                self.emu.vblank();
+                match self.emu.tick() {
+                    Ok(outcome) => self.show_stop(outcome.stopped),
+                    Err(error) => self.status = error.to_string(),
+                }
+            }
+            b'n' if self.paused => {
+                self.emu.vblank();
+                if let Err(error) = self.emu.step_over(MAX_STEP_CYCLES) {
+                    self.status = error.to_string();
+                }
+            }
+            byte => {
+                let key = KEYPAD.iter().flatten().find(|(char, _)| *char == byte);
+                if let Some(&(_, key)) = key {
+                    if self.held[key.index()] == 0 {
+                        self.emu.keypress(key, true);
+                    }
+                    self.held[key.index()] = KEY_HOLD_FRAMES;
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn run_frame(&mut self) {
+        for (key, frames) in Key::ALL.into_iter().zip(&mut self.held) {
+            if *frames > 0 {
+                *frames -= 1;
+                if *frames == 0 {
+                    self.emu.keypress(key, false);
+                }
+            }
+        }
+
+        if self.paused {
+            return;
+        }
+        let cycles = self.emu.instructions_per_second() / 60;
+        match self.emu.run_frame(cycles) {
+            Ok(result) => self.show_stop(result.stopped),
+            Err(error) => {
+                self.status = error.to_string();
+                self.paused = true;
+            }
+        }
+    }
+
+    fn show_stop(&mut self, stopped: Option<Stopped>) {
+        match stopped {
+            Some(Stopped::Breakpoint(addr)) => self.status = format!("breakpoint at {:#05X}", addr),
+            Some(Stopped::Watchpoint(hit)) => {
+                self.status = format!("{:?} of {:#05X}", hit.access, hit.addr)
+            }
+            None => return,
+        }
+        self.paused = true;
+    }
+
+    // The screen on the left, registers and code on the right, drawn over
+    // the previous frame
+    fn draw(&self) -> io::Result<()> {
+        let mut screen = String::new();
+        let _ = self.emu.render_text(&mut screen, TextStyle::HalfBlock);
+        let width = self.emu.resolution().width();
+        let panel = self.panel();
+
+        let mut out = String::from("\x1b[H");
+        let screen_lines = screen.lines().chain(std::iter::repeat(""));
+        for (left, right) in screen_lines.zip(&panel) {
+            let _ = write!(out, "{:<width$} │ {}\x1b[K\r\n", left, right);
+        }
+        for line in screen.lines().skip(panel.len()) {
+            let _ = write!(out, "{} │\x1b[K\r\n", line);
+        }
+        let _ = write!(out, "{}\x1b[K\r\n{}\x1b[K\x1b[J", HELP, self.status);
+
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(out.as_bytes())?;
+        stdout.flush()
+    }
+
+    fn panel(&self) -> Vec<String> {
+        let emu = &self.emu;
+        let mut lines = Vec::new();
+
+        for (row, regs) in emu.v_reg().chunks(4).enumerate() {
+            let regs: Vec<_> = regs
+                .iter()
+                .enumerate()
+                .map(|(x, value)| format!("V{:X}={:02X}", row * 4 + x, value))
+                .collect();
+            lines.push(regs.join(" "));
+        }
+        lines.push(format!(
+            "PC={:#05X} I={:#05X} SP={}",
+            emu.pc(),
+            emu.i_reg(),
+            emu.sp()
+        ));
+        let sound = if emu.is_sound_active() { "♪" } else { "" };
+        lines.push(format!("DT={:02X} ST={:02X} {}", emu.dt(), emu.st(), sound));
+
+        let state = match (self.paused, emu.run_state()) {
+            (true, _) => "paused",
+            (false, RunState::Running | RunState::WaitingForVblank) => "running",
+            (false, RunState::Halted) => "exited",
+            (false, RunState::Spinning) => "stuck in a loop",
+        };
+        lines.push(state.to_string());
+        lines.push(String::new());
+
+        let ram = emu.ram();
+        let start = (emu.pc() as usize).min(ram.len());
+        let code = disasm::disassemble(&ram[start..], start as u16)
+            .flat_map(|line| {
+                let marker = if line.addr == emu.pc() { "=>" } else { "  " };
+                let text = line.with_symbols(&self.options.symbols).to_string();
+                // Label lines get no marker
+                text.lines()
+                    .map(|text| match text.ends_with(':') {
+                        true => text.to_string(),
+                        false => format!("{} {}", marker, text),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .take(DISASM_LINES);
+        lines.extend(code);
+
+        lines
+    }
+}
@@ -0,0 +1,332 @@
+use core::time::Duration;
+
+use crate::*;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+const TIMER_HZ: u128 = 60;
+const DEFAULT_IPS: u32 = 700;
+const MIN_SPEED: f32 = 0.25;
+
+// Longest step advance() catches up on, so a frontend that stalled (a
+// debugger, a suspended laptop) doesn't freeze while emulating the gap
+const MAX_ADVANCE: Duration = Duration::from_millis(250);
+const MAX_FRAMES: u32 = 15; // MAX_ADVANCE at 60Hz
+
+// The VIP's 1802 runs a machine cycle every 8 clocks of 1.76MHz, 3668 per
+// 60Hz frame. Display DMA steals one for each byte shown, 8 bytes on each
+// of 128 lines, the interpreter gets the rest.
+const VIP_CPU_CYCLES: u32 = 3668 - 8 * 128;
+const VIP_FETCH_CYCLES: u32 = 40;
+const VIP_TYPICAL_CYCLES: u32 = 10;
+
+// How advance() and run_frame() decide how long an instruction takes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timing {
+    Fixed,     // every instruction takes 1 / instructions_per_second()
+    CosmacVip, // the machine cycles each instruction takes the VIP interpreter
+}
+
+// Time owed to instructions and timer ticks, in nanoseconds times their
+// rate, so an event is due once its debt reaches a second's worth
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Clock {
+    timing: Timing,
+    ips: u32,
+    speed: f32,
+    instruction_debt: u128,
+    timer_debt: u128,
+    frame_debt: f32,   // run_frame() calls not yet run at slow speeds
+    frame_time: i64,   // VIP machine cycles left in the current run_frame() frame
+    sample_time: u128, // nanoseconds times the sample rate fill_audio() owes
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self {
+            timing: Timing::Fixed,
+            ips: DEFAULT_IPS,
+            speed: 1.0,
+            instruction_debt: 0,
+            timer_debt: 0,
+            frame_debt: 0.0,
+            frame_time: 0,
+            sample_time: 0,
+        }
+    }
+}
+
+impl Clock {
+    pub(crate) fn clear(&mut self) {
+        self.instruction_debt = 0;
+        self.timer_debt = 0;
+        self.frame_debt = 0.0;
+        self.frame_time = 0;
+        self.sample_time = 0;
+    }
+
+    // Whole frames a run_frame() call should emulate at the current speed
+    pub(crate) fn take_frames(&mut self) -> u32 {
+        if self.speed.is_infinite() {
+            return MAX_FRAMES;
+        }
+
+        self.frame_debt += self.speed;
+        let frames = self.frame_debt as u32;
+        self.frame_debt -= frames as f32;
+        frames.min(MAX_FRAMES)
+    }
+}
+
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    pub fn timing(&self) -> Timing {
+        self.clock.timing
+    }
+
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.clock.timing = timing;
+    }
+
+    pub fn instructions_per_second(&self) -> u32 {
+        self.clock.ips
+    }
+
+    pub fn set_instructions_per_second(&mut self, ips: u32) {
+        self.clock.ips = ips.max(1);
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.clock.speed
+    }
+
+    // Multiplies how fast advance() and run_frame() emulate time, 1.0 is real
+    // time and values below 0.25 are raised to it. f32::INFINITY is turbo:
+    // each call emulates as much as a call is allowed to catch up on.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.clock.speed = if speed.is_nan() {
+            1.0
+        } else {
+            speed.max(MIN_SPEED)
+        };
+    }
+
+    // Emulates `elapsed` of wall-clock time: instructions at the configured
+    // rate, with the 60Hz timer ticks in between where they fall. Time left
+    // over carries into the next call, unless a debugger stop cuts it short.
+    pub fn advance(&mut self, elapsed: Duration) -> Result<Option<Stopped>, EmuError> {
+        // Infinite or huge speeds don't fit a Duration and get the maximum
+        let elapsed = match self.clock.speed {
+            1.0 => elapsed,
+            speed => {
+                Duration::try_from_secs_f32(elapsed.as_secs_f32() * speed).unwrap_or(MAX_ADVANCE)
+            }
+        };
+        let nanos = elapsed.min(MAX_ADVANCE).as_nanos();
+
+        // An instruction costs `cost` once its debt grows by `rate` per nanosecond
+        let rate = match self.clock.timing {
+            Timing::Fixed => self.clock.ips as u128,
+            Timing::CosmacVip => VIP_CPU_CYCLES as u128 * TIMER_HZ,
+        };
+        self.clock.instruction_debt += nanos * rate;
+        self.clock.timer_debt += nanos * TIMER_HZ;
+
+        loop {
+            let cost = match self.clock.timing {
+                Timing::Fixed => NANOS_PER_SEC,
+                Timing::CosmacVip => self.vip_cycles() as u128 * NANOS_PER_SEC,
+            };
+
+            // How long ago each event fell due, scaled by rate * TIMER_HZ to compare them
+            let instruction_due = self.clock.instruction_debt.checked_sub(cost);
+            let timer_due = self.clock.timer_debt.checked_sub(NANOS_PER_SEC);
+
+            match (instruction_due, timer_due) {
+                (Some(instruction), Some(timer)) if instruction * TIMER_HZ >= timer * rate => {}
+                (_, Some(_)) => {
+                    self.clock.timer_debt -= NANOS_PER_SEC;
+                    self.tick_timers();
+                    continue;
+                }
+                (Some(_), None) => {}
+                (None, None) => return Ok(None),
+            }
+
+            self.clock.instruction_debt -= cost;
+            let stopped = self.tick()?.stopped;
+            if stopped.is_some() {
+                self.clock.clear();
+                return Ok(stopped);
+            }
+        }
+    }
+
+    // Emulates the time `samples` take to play at `sample_rate` and fills
+    // them with its sound, for frontends that drive the machine from their
+    // audio callback instead of a timer. Each sample is rendered once the
+    // instructions and timer ticks before it have run, time left over
+    // carries into the next call. After a debugger stop or a fault the rest
+    // of the buffer is silent.
+    pub fn fill_audio(
+        &mut self,
+        samples: &mut [f32],
+        sample_rate: u32,
+    ) -> Result<Option<Stopped>, EmuError> {
+        let rate = sample_rate.max(1) as u128;
+
+        for index in 0..samples.len() {
+            self.clock.sample_time += NANOS_PER_SEC;
+            let nanos = self.clock.sample_time / rate;
+            self.clock.sample_time %= rate;
+
+            let result = self.advance(Duration::from_nanos(nanos as u64));
+            if !matches!(result, Ok(None)) {
+                samples[index..].fill(0.0);
+                return result;
+            }
+            self.render_audio(&mut samples[index..index + 1], sample_rate);
+        }
+
+        Ok(None)
+    }
+
+    // Runs up to `cycles` instructions followed by one timer tick, or a
+    // frame's worth of instructions with Timing::CosmacVip. The frame ends
+    // early when a DXYN waits for vblank or FX0A waits for a key, since
+    // neither can make progress before the next frame. Other speeds than 1.0
+    // run several frames per call or skip calls, see set_speed().
+    pub fn run_frame(&mut self, cycles: u32) -> Result<FrameResult, EmuError> {
+        let mut result = FrameResult {
+            run_state: self.run_state,
+            frames: self.clock.take_frames(),
+            instructions: 0,
+            screen_changed: false,
+            sound_active: self.is_sound_active(),
+            stopped: None,
+        };
+
+        'frames: for _ in 0..result.frames {
+            let mut instructions = 0;
+            self.clock.frame_time += VIP_CPU_CYCLES as i64;
+
+            loop {
+                let cost = match self.clock.timing {
+                    Timing::Fixed if instructions < cycles => 0,
+                    Timing::CosmacVip if self.clock.frame_time > 0 => self.vip_cycles(),
+                    _ => break,
+                };
+                self.clock.frame_time -= cost as i64;
+
+                let outcome = self.tick()?;
+                if outcome.opcode.is_some() {
+                    instructions += 1;
+                    result.instructions += 1;
+                    result.screen_changed |= outcome.screen_changed;
+                }
+
+                if outcome.stopped.is_some() {
+                    result.stopped = outcome.stopped;
+                    break 'frames;
+                }
+                if outcome.opcode.is_none()
+                    || outcome.waiting_for_key
+                    || outcome.run_state == RunState::WaitingForVblank
+                {
+                    break;
+                }
+            }
+
+            // Time left after blocking is spent idling, only overruns carry over
+            self.clock.frame_time = self.clock.frame_time.min(0);
+            self.tick_timers();
+        }
+
+        result.run_state = self.run_state;
+        result.sound_active = self.is_sound_active();
+        Ok(result)
+    }
+
+    // Machine cycles the VIP interpreter spends on the instruction at PC,
+    // including the fetch and dispatch every instruction goes through. Skips
+    // cost more when they skip. DXYN pays for every sprite byte it XORs in,
+    // two per row when the sprite straddles a byte of display memory, and
+    // for every bit it shifts a row right to line it up. Instructions the
+    // VIP doesn't have get a typical cost.
+    fn vip_cycles(&self) -> u32 {
+        let instruction = match self.peek(self.pc).map(Instruction::decode) {
+            Some(Ok(instruction)) => instruction,
+            _ => return VIP_FETCH_CYCLES + VIP_TYPICAL_CYCLES,
+        };
+        let v = |x: u8| self.v_reg[x as usize];
+        let skip = |skipped: bool| if skipped { 4 } else { 0 };
+
+        VIP_FETCH_CYCLES
+            + match instruction {
+                // Clears the 256 bytes of display memory a byte per loop
+                Instruction::Cls => 24 + 6 * 256,
+                Instruction::Ret => 10,
+                Instruction::Jump(_) => 12,
+                Instruction::Call(_) => 26,
+                Instruction::JumpOffset { .. } => 22,
+                Instruction::SkipEqImm { x, nn } => 10 + skip(v(x) == nn),
+                Instruction::SkipNeImm { x, nn } => 10 + skip(v(x) != nn),
+                Instruction::SkipEqReg { x, y } => 14 + skip(v(x) == v(y)),
+                Instruction::SkipNeReg { x, y } => 14 + skip(v(x) != v(y)),
+                Instruction::SkipKeyPressed { x } => 14 + skip(self.keys[v(x) as usize & 0xF]),
+                Instruction::SkipKeyNotPressed { x } => 14 + skip(!self.keys[v(x) as usize & 0xF]),
+                Instruction::SetImm { .. } => 6,
+                Instruction::AddImm { .. } => 10,
+                // Run through a two instruction routine built in RAM
+                Instruction::Set { .. }
+                | Instruction::Or { .. }
+                | Instruction::And { .. }
+                | Instruction::Xor { .. }
+                | Instruction::Add { .. }
+                | Instruction::Sub { .. }
+                | Instruction::ShiftRight { .. }
+                | Instruction::SubReverse { .. }
+                | Instruction::ShiftLeft { .. } => 44,
+                Instruction::SetIndex(_) => 12,
+                Instruction::Random { .. } => 36,
+                Instruction::Draw { x, n, .. } => {
+                    let (width, height) = self.sprite_dimensions(n);
+                    let shift = v(x) as u32 % 8;
+                    let bytes = (width / 8) as u32 + (shift != 0) as u32;
+                    52 + height as u32 * (16 + 14 * bytes + 12 * shift)
+                }
+                Instruction::AddIndex { .. } => 14,
+                Instruction::Font { .. } => 14,
+                // The digits are found by repeated subtraction
+                Instruction::Bcd { x } => {
+                    let value = v(x) as u32;
+                    80 + 16 * (value / 100 + value / 10 % 10 + value % 10)
+                }
+                Instruction::Store { x } | Instruction::Load { x } => 14 + 8 * (x as u32 + 1),
+                _ => VIP_TYPICAL_CYCLES,
+            }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cycles of DXYN with VX = `x` and N = `rows`
+    fn draw_cycles(x: u8, rows: u8) -> u32 {
+        let mut emu = Emu::new();
+        emu.load(&[0xD0, 0x10 | rows]).unwrap();
+        emu.v_reg[0] = x;
+        emu.vip_cycles()
+    }
+
+    #[test]
+    fn draw_cycles_count_rows_bytes_and_shifts() {
+        let row = draw_cycles(0, 2) - draw_cycles(0, 1);
+        assert_eq!(draw_cycles(0, 5), draw_cycles(0, 1) + 4 * row);
+
+        // A second byte and one shift per row
+        let shifted = draw_cycles(1, 1) - draw_cycles(0, 1);
+        assert_eq!(draw_cycles(1, 3) - draw_cycles(0, 3), 3 * shifted);
+        assert_eq!(draw_cycles(7, 1) - draw_cycles(1, 1), 6 * (shifted - 14));
+        assert_eq!(draw_cycles(8, 1), draw_cycles(0, 1));
+    }
+}
@@ -0,0 +1,268 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::*;
+
+mod condition;
+
+pub use condition::{Condition, ConditionError};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    fn matches(self, access: Access) -> bool {
+        match self {
+            WatchKind::Read => access == Access::Read,
+            WatchKind::Write => access == Access::Write,
+            WatchKind::ReadWrite => true,
+        }
+    }
+}
+
+// Breakpoints, watchpoints and the state needed to resume from them
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Debugger {
+    breakpoints: BTreeMap<u16, Option<Condition>>,
+    resume_at: Option<u16>, // breakpoint that stopped the last tick, the next one runs it
+    watchpoints: Vec<(Range<usize>, WatchKind)>,
+}
+
+impl Debugger {
+    pub(crate) fn clear_resume(&mut self) {
+        self.resume_at = None;
+    }
+}
+
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    // Stops tick() and the functions built on it before the instruction at
+    // `addr` runs. Ticking again runs it. False if already set.
+    pub fn add_breakpoint(&mut self, addr: u16) -> bool {
+        self.debugger.breakpoints.insert(addr, None).is_none()
+    }
+
+    // Like add_breakpoint(), but only stops when `condition` holds. Replaces
+    // the condition of an existing breakpoint.
+    pub fn add_conditional_breakpoint(&mut self, addr: u16, condition: Condition) -> bool {
+        self.debugger
+            .breakpoints
+            .insert(addr, Some(condition))
+            .is_none()
+    }
+
+    pub fn breakpoint_condition(&self, addr: u16) -> Option<&Condition> {
+        self.debugger.breakpoints.get(&addr)?.as_ref()
+    }
+
+    // False if there was no breakpoint at `addr`
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.debugger.breakpoints.remove(&addr).is_some()
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.debugger.breakpoints.clear();
+    }
+
+    // In address order
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.debugger.breakpoints.keys().copied()
+    }
+
+    // Stops tick() and the functions built on it after an instruction reads
+    // or writes RAM in `range`. Instruction fetches don't count.
+    pub fn add_watchpoint(&mut self, range: Range<usize>, kind: WatchKind) {
+        self.debugger.watchpoints.push((range, kind));
+    }
+
+    // Removes the watchpoints on exactly `range`, false if there were none
+    pub fn remove_watchpoint(&mut self, range: Range<usize>) -> bool {
+        let watchpoints = &mut self.debugger.watchpoints;
+        let len = watchpoints.len();
+        watchpoints.retain(|(watched, _)| *watched != range);
+        watchpoints.len() != len
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.debugger.watchpoints.clear();
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = (Range<usize>, WatchKind)> + '_ {
+        self.debugger.watchpoints.iter().cloned()
+    }
+
+    // Runs one instruction, or a whole subroutine if it's a CALL. False if a
+    // debugger stop, a halt or `max_cycles` ticks came first.
+    pub fn step_over(&mut self, max_cycles: u32) -> Result<bool, EmuError> {
+        let sp = self.sp;
+        let is_call = matches!(
+            self.peek(self.pc).map(Instruction::decode),
+            Some(Ok(Instruction::Call(_)))
+        );
+
+        let outcome = self.tick()?;
+        if outcome.opcode.is_none() || outcome.stopped.is_some() {
+            return Ok(false);
+        }
+        if !is_call {
+            return Ok(true);
+        }
+
+        self.run_until(max_cycles, |emu| emu.sp <= sp)
+    }
+
+    // Runs until the current subroutine returns. False outside a subroutine
+    // or if a debugger stop, a halt or `max_cycles` ticks came first.
+    pub fn step_out(&mut self, max_cycles: u32) -> Result<bool, EmuError> {
+        let sp = self.sp;
+        if sp == 0 {
+            return Ok(false);
+        }
+
+        self.run_until(max_cycles, |emu| emu.sp < sp)
+    }
+
+    // Called before fetching the instruction at PC
+    pub(crate) fn check_breakpoint(&mut self) -> Option<Stopped> {
+        let pc = self.pc;
+        if self.debugger.resume_at.take() == Some(pc) {
+            return None;
+        }
+
+        let condition = self.debugger.breakpoints.get(&pc)?;
+        if !condition
+            .as_ref()
+            .is_none_or(|condition| condition.eval(self))
+        {
+            return None;
+        }
+
+        self.debugger.resume_at = Some(pc);
+        Some(Stopped::Breakpoint(pc))
+    }
+
+    // Called before executing `instruction`, finds the first watched address
+    // it will touch. The caller fills in the new value afterwards.
+    pub(crate) fn check_watchpoints(&self, pc: u16, instruction: Instruction) -> Option<WatchHit> {
+        if self.debugger.watchpoints.is_empty() {
+            return None;
+        }
+
+        let (range, access) = self.ram_access(pc, instruction)?;
+        let addr = range.clone().find(|addr| {
+            self.debugger
+                .watchpoints
+                .iter()
+                .any(|(watched, kind)| watched.contains(addr) && kind.matches(access))
+        })?;
+
+        Some(WatchHit {
+            pc,
+            opcode: instruction.encode(),
+            addr,
+            access,
+            old: self.ram[addr],
+            new: self.ram[addr],
+        })
+    }
+
+    // The RAM `instruction` is about to read or write through I, if any
+    pub(crate) fn ram_access(
+        &self,
+        pc: u16,
+        instruction: Instruction,
+    ) -> Option<(Range<usize>, Access)> {
+        let (len, access) = match instruction {
+            Instruction::Draw { n, .. } => {
+                let (width, height) = self.sprite_dimensions(n);
+                (self.sprite_data_len(width, height), Access::Read)
+            }
+            Instruction::LoadRange { x, y } => (x.abs_diff(y) as usize + 1, Access::Read),
+            Instruction::Load { x } => (x as usize + 1, Access::Read),
+            Instruction::LoadAudio => (AUDIO_PATTERN_SIZE, Access::Read),
+            Instruction::SaveRange { x, y } => (x.abs_diff(y) as usize + 1, Access::Write),
+            Instruction::Bcd { .. } => (3, Access::Write),
+            Instruction::Store { x } => (x as usize + 1, Access::Write),
+            _ => return None,
+        };
+        let range = self.ram_range(pc, self.i_reg as usize, len).ok()?;
+        Some((range, access))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LD V0, 0x7B / LD I, 0x300 / LD B, V0 / LD V1, [I] / DRW V0, V0, 1
+    const PROGRAM: [u8; 10] = [0x60, 0x7B, 0xA3, 0x00, 0xF0, 0x33, 0xF1, 0x65, 0xD0, 0x01];
+
+    fn first_hit(emu: &mut Emu) -> Option<WatchHit> {
+        for _ in 0..PROGRAM.len() / 2 {
+            if let Some(Stopped::Watchpoint(hit)) = emu.tick().unwrap().stopped {
+                return Some(hit);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn writes_report_the_instruction_and_both_values() {
+        let mut emu = Emu::new();
+        emu.load(&PROGRAM).unwrap();
+        emu.add_watchpoint(0x301..0x303, WatchKind::Write);
+
+        let hit = first_hit(&mut emu).unwrap();
+        assert_eq!(
+            hit,
+            WatchHit {
+                pc: 0x204,
+                opcode: 0xF033,
+                addr: 0x301,
+                access: Access::Write,
+                old: 0,
+                new: 2,
+            }
+        );
+        assert_eq!(emu.pc, 0x206);
+    }
+
+    #[test]
+    fn reads_are_told_apart_from_writes_and_fetches() {
+        let mut emu = Emu::new();
+        emu.load(&PROGRAM).unwrap();
+        emu.add_watchpoint(0x200..0x20A, WatchKind::Read);
+        emu.add_watchpoint(0x300..0x301, WatchKind::Read);
+
+        let hit = first_hit(&mut emu).unwrap();
+        assert_eq!((hit.pc, hit.addr, hit.access), (0x206, 0x300, Access::Read));
+        assert_eq!((hit.old, hit.new), (1, 1));
+        assert_eq!(emu.v_reg[..2], [1, 2]);
+
+        // DRW reads its sprite from I too
+        let hit = first_hit(&mut emu).unwrap();
+        assert_eq!((hit.pc, hit.addr), (0x208, 0x300));
+    }
+
+    #[test]
+    fn removed_watchpoints_stop_nothing() {
+        let mut emu = Emu::new();
+        emu.load(&PROGRAM).unwrap();
+        emu.add_watchpoint(0x300..0x303, WatchKind::ReadWrite);
+        emu.add_watchpoint(0x400..0x401, WatchKind::Write);
+        assert!(!emu.remove_watchpoint(0x300..0x302));
+        assert!(emu.remove_watchpoint(0x300..0x303));
+        assert_eq!(
+            emu.watchpoints().collect::<Vec<_>>(),
+            [(0x400..0x401, WatchKind::Write)]
+        );
+
+        assert_eq!(first_hit(&mut emu), None);
+        emu.clear_watchpoints();
+        assert_eq!(emu.watchpoints().count(), 0);
+    }
+}
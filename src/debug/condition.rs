@@ -0,0 +1,398 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::*;
+
+// Deepest nesting of parentheses, brackets, ! and chained operators, deeper
+// conditions would overflow the stack when parsed or evaluated
+const MAX_DEPTH: usize = 64;
+
+// Breakpoint condition over the registers and RAM, e.g.
+//
+//     V3 == 0x1F && I >= 0x300
+//     [I + 2] != 0 || !(DT)
+//
+// Operands are numbers (decimal or 0x hex), V0-VF, I, PC, SP, DT, ST and
+// [addr] for the RAM byte at addr. Operators from loosest to tightest:
+// ||, &&, comparisons (== != < <= > >=), + - & | ^ and the unary !.
+// Anything nonzero is true.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+    expr: Expr,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConditionError {
+    UnexpectedToken(usize), // 1-based column
+    InvalidNumber(usize),
+    UnexpectedEnd,
+    TooDeep(usize), // column where nesting went past the limit
+}
+
+impl fmt::Display for ConditionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConditionError::UnexpectedToken(column) => {
+                write!(f, "unexpected token at column {}", column)
+            }
+            ConditionError::InvalidNumber(column) => {
+                write!(f, "invalid number at column {}", column)
+            }
+            ConditionError::UnexpectedEnd => write!(f, "unexpected end of condition"),
+            ConditionError::TooDeep(column) => {
+                write!(f, "condition nested too deeply at column {}", column)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ConditionError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Register {
+    V(u8),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    BitAnd,
+    BitOr,
+    BitXor,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expr {
+    Number(u32),
+    Register(Register),
+    Memory(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token {
+    Number(u32),
+    Register(Register),
+    Op(Op),
+    Not,
+    Open,
+    Close,
+    OpenBracket,
+    CloseBracket,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Condition, ConditionError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            next: 0,
+            depth: 0,
+        };
+        let expr = parser.or()?;
+
+        match parser.tokens.get(parser.next) {
+            Some(&(_, column)) => Err(ConditionError::UnexpectedToken(column)),
+            None => Ok(Condition { expr }),
+        }
+    }
+
+    pub fn eval<F: FlagStorage, R: RandomSource>(&self, emu: &Emu<F, R>) -> bool {
+        self.expr.eval(emu) != 0
+    }
+}
+
+impl Expr {
+    fn eval<F: FlagStorage, R: RandomSource>(&self, emu: &Emu<F, R>) -> u32 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Register(register) => match *register {
+                Register::V(x) => emu.v_reg[x as usize] as u32,
+                Register::I => emu.i_reg as u32,
+                Register::Pc => emu.pc as u32,
+                Register::Sp => emu.sp as u32,
+                Register::Dt => emu.dt as u32,
+                Register::St => emu.st as u32,
+            },
+            // Addresses past the end of RAM read as 0
+            Expr::Memory(addr) => {
+                let addr = addr.eval(emu) as usize;
+                emu.ram[..emu.ram_size].get(addr).copied().unwrap_or(0) as u32
+            }
+            Expr::Not(expr) => (expr.eval(emu) == 0) as u32,
+            Expr::Binary(op, left, right) => {
+                let left = left.eval(emu);
+                // && and || short-circuit
+                match op {
+                    Op::Or if left != 0 => return 1,
+                    Op::And if left == 0 => return 0,
+                    _ => {}
+                }
+                let right = right.eval(emu);
+
+                match op {
+                    Op::Or | Op::And => (right != 0) as u32,
+                    Op::Eq => (left == right) as u32,
+                    Op::Ne => (left != right) as u32,
+                    Op::Lt => (left < right) as u32,
+                    Op::Le => (left <= right) as u32,
+                    Op::Gt => (left > right) as u32,
+                    Op::Ge => (left >= right) as u32,
+                    Op::Add => left.wrapping_add(right),
+                    Op::Sub => left.wrapping_sub(right),
+                    Op::BitAnd => left & right,
+                    Op::BitOr => left | right,
+                    Op::BitXor => left ^ right,
+                }
+            }
+        }
+    }
+}
+
+// Tokens with their 1-based columns
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ConditionError> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let column = index + 1;
+        let rest = &bytes[index..];
+
+        if rest[0].is_ascii_whitespace() {
+            index += 1;
+            continue;
+        }
+
+        if rest[0].is_ascii_alphanumeric() {
+            let len = rest
+                .iter()
+                .take_while(|byte| byte.is_ascii_alphanumeric())
+                .count();
+            let word = &source[index..index + len];
+            let token = if rest[0].is_ascii_digit() {
+                Token::Number(parse_number(word).ok_or(ConditionError::InvalidNumber(column))?)
+            } else {
+                Token::Register(
+                    parse_register(word).ok_or(ConditionError::UnexpectedToken(column))?,
+                )
+            };
+
+            tokens.push((token, column));
+            index += len;
+            continue;
+        }
+
+        let two = rest.get(..2);
+        let (token, len) = match two {
+            Some(b"||") => (Token::Op(Op::Or), 2),
+            Some(b"&&") => (Token::Op(Op::And), 2),
+            Some(b"==") => (Token::Op(Op::Eq), 2),
+            Some(b"!=") => (Token::Op(Op::Ne), 2),
+            Some(b"<=") => (Token::Op(Op::Le), 2),
+            Some(b">=") => (Token::Op(Op::Ge), 2),
+            _ => match rest[0] {
+                b'<' => (Token::Op(Op::Lt), 1),
+                b'>' => (Token::Op(Op::Gt), 1),
+                b'+' => (Token::Op(Op::Add), 1),
+                b'-' => (Token::Op(Op::Sub), 1),
+                b'&' => (Token::Op(Op::BitAnd), 1),
+                b'|' => (Token::Op(Op::BitOr), 1),
+                b'^' => (Token::Op(Op::BitXor), 1),
+                b'!' => (Token::Not, 1),
+                b'(' => (Token::Open, 1),
+                b')' => (Token::Close, 1),
+                b'[' => (Token::OpenBracket, 1),
+                b']' => (Token::CloseBracket, 1),
+                _ => return Err(ConditionError::UnexpectedToken(column)),
+            },
+        };
+
+        tokens.push((token, column));
+        index += len;
+    }
+
+    Ok(tokens)
+}
+
+fn parse_number(word: &str) -> Option<u32> {
+    match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => word.parse().ok(),
+    }
+}
+
+fn parse_register(word: &str) -> Option<Register> {
+    let mut upper = [0; 2];
+    if word.len() > upper.len() {
+        return None;
+    }
+    for (upper, byte) in upper.iter_mut().zip(word.bytes()) {
+        *upper = byte.to_ascii_uppercase();
+    }
+
+    match &upper[..word.len()] {
+        b"I" => Some(Register::I),
+        b"PC" => Some(Register::Pc),
+        b"SP" => Some(Register::Sp),
+        b"DT" => Some(Register::Dt),
+        b"ST" => Some(Register::St),
+        [b'V', digit] => (*digit as char).to_digit(16).map(|x| Register::V(x as u8)),
+        _ => None,
+    }
+}
+
+// Recursive descent, one function per precedence level
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+    depth: usize, // nesting of the expression being parsed
+}
+
+impl Parser {
+    fn peek(&self) -> Option<(Token, usize)> {
+        self.tokens.get(self.next).copied()
+    }
+
+    fn advance(&mut self) -> Result<(Token, usize), ConditionError> {
+        let token = self
+            .tokens
+            .get(self.next)
+            .copied()
+            .ok_or(ConditionError::UnexpectedEnd)?;
+        self.next += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ConditionError> {
+        match self.advance()? {
+            (token, _) if token == expected => Ok(()),
+            (_, column) => Err(ConditionError::UnexpectedToken(column)),
+        }
+    }
+
+    // One level deeper, up to MAX_DEPTH
+    fn nest(&mut self, column: usize) -> Result<(), ConditionError> {
+        self.depth += 1;
+        match self.depth > MAX_DEPTH {
+            true => Err(ConditionError::TooDeep(column)),
+            false => Ok(()),
+        }
+    }
+
+    // Left-associative chain of the operators in `ops` over `operand`
+    fn binary(
+        &mut self,
+        ops: &[Op],
+        operand: fn(&mut Self) -> Result<Expr, ConditionError>,
+    ) -> Result<Expr, ConditionError> {
+        let depth = self.depth;
+        let mut left = operand(self)?;
+
+        // Each operator nests the chain so far one level deeper
+        while let Some((Token::Op(op), column)) = self.peek() {
+            if !ops.contains(&op) {
+                break;
+            }
+            self.next += 1;
+            self.nest(column)?;
+            let right = operand(self)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+
+        self.depth = depth;
+        Ok(left)
+    }
+
+    fn or(&mut self) -> Result<Expr, ConditionError> {
+        self.binary(&[Op::Or], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, ConditionError> {
+        self.binary(&[Op::And], Self::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ConditionError> {
+        let ops = [Op::Eq, Op::Ne, Op::Lt, Op::Le, Op::Gt, Op::Ge];
+        self.binary(&ops, Self::arithmetic)
+    }
+
+    fn arithmetic(&mut self) -> Result<Expr, ConditionError> {
+        let ops = [Op::Add, Op::Sub, Op::BitAnd, Op::BitOr, Op::BitXor];
+        self.binary(&ops, Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ConditionError> {
+        let depth = self.depth;
+        let expr = match self.advance()? {
+            (Token::Number(value), _) => Expr::Number(value),
+            (Token::Register(register), _) => Expr::Register(register),
+            (Token::Not, column) => {
+                self.nest(column)?;
+                Expr::Not(Box::new(self.unary()?))
+            }
+            (Token::Open, column) => {
+                self.nest(column)?;
+                let expr = self.or()?;
+                self.expect(Token::Close)?;
+                expr
+            }
+            (Token::OpenBracket, column) => {
+                self.nest(column)?;
+                let addr = self.or()?;
+                self.expect(Token::CloseBracket)?;
+                Expr::Memory(Box::new(addr))
+            }
+            (_, column) => return Err(ConditionError::UnexpectedToken(column)),
+        };
+
+        self.depth = depth;
+        Ok(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        for (open, close) in [("(", ")"), ("[", "]"), ("!", "")] {
+            let nested = |depth: usize| {
+                let mut source = open.repeat(depth);
+                source.push('1');
+                source.push_str(&close.repeat(depth));
+                Condition::parse(&source)
+            };
+            assert!(nested(MAX_DEPTH).is_ok());
+            let column = MAX_DEPTH * open.len() + 1;
+            assert_eq!(nested(100_000), Err(ConditionError::TooDeep(column)));
+        }
+
+        let chain = |len: usize| {
+            let mut source = String::from("1");
+            source.push_str(&" + 1".repeat(len));
+            Condition::parse(&source)
+        };
+        assert!(chain(MAX_DEPTH).is_ok());
+        assert!(matches!(chain(100_000), Err(ConditionError::TooDeep(_))));
+    }
+}
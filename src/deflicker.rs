@@ -0,0 +1,112 @@
+use crate::*;
+
+pub const MAX_DEFLICKER_FRAMES: usize = 8;
+
+type Planes = [[u128; HIRES_SCREEN_HEIGHT]; NUM_PLANES];
+
+// Combines the last few frames into a steady picture for games that erase
+// and redraw their sprites every frame, like Pong and Brix. Call `update`
+// once per frame, then draw `colors` or `levels` instead of the display.
+// The machine's own display is left as it is.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Deflicker {
+    frames: [Planes; MAX_DEFLICKER_FRAMES], // ring, rows packed like the display
+    len: usize,                             // frames held, at most `window`
+    next: usize,                            // slot of the next frame
+    window: usize,
+    resolution: Resolution,
+}
+
+impl Deflicker {
+    // Combines `window` frames, 1 to MAX_DEFLICKER_FRAMES
+    pub fn new(window: usize) -> Self {
+        Self {
+            frames: [[[0; HIRES_SCREEN_HEIGHT]; NUM_PLANES]; MAX_DEFLICKER_FRAMES],
+            len: 0,
+            next: 0,
+            window: window.clamp(1, MAX_DEFLICKER_FRAMES),
+            resolution: Resolution::Low,
+        }
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    // Starts over with the next frame
+    pub fn set_window(&mut self, window: usize) {
+        self.window = window.clamp(1, MAX_DEFLICKER_FRAMES);
+        self.clear();
+    }
+
+    pub fn update<F: FlagStorage, R: RandomSource>(&mut self, emu: &Emu<F, R>) {
+        // Frames of another resolution don't line up
+        if emu.resolution() != self.resolution {
+            self.resolution = emu.resolution();
+            self.clear();
+        }
+
+        let height = self.resolution.height();
+        for (plane, rows) in self.frames[self.next].iter_mut().enumerate() {
+            rows[..height].copy_from_slice(emu.get_plane_display_packed(plane));
+        }
+        self.next = (self.next + 1) % self.window;
+        self.len = (self.len + 1).min(self.window);
+    }
+
+    // One 2-bit color index per pixel like Emu::get_display_colors(), bit N
+    // set if plane N was lit in any of the frames
+    pub fn colors(&self) -> impl Iterator<Item = u8> + '_ {
+        let mut lit: Planes = [[0; HIRES_SCREEN_HEIGHT]; NUM_PLANES];
+        for frame in &self.frames[..self.len] {
+            for (lit, rows) in lit.iter_mut().zip(frame) {
+                for (lit, row) in lit.iter_mut().zip(rows) {
+                    *lit |= row;
+                }
+            }
+        }
+
+        self.pixels().map(move |(y, bit)| {
+            let [plane0, plane1] = lit;
+            ((plane0[y] >> bit) & 1) as u8 | ((((plane1[y] >> bit) & 1) as u8) << 1)
+        })
+    }
+
+    // One brightness per pixel, 0 to 255 for the share of the frames it was
+    // lit in, in either plane
+    pub fn levels(&self) -> impl Iterator<Item = u8> + '_ {
+        let frames = &self.frames[..self.len];
+        let len = self.len.max(1) as u32;
+
+        self.pixels().map(move |(y, bit)| {
+            let count = frames
+                .iter()
+                .filter(|[plane0, plane1]| (plane0[y] | plane1[y]) >> bit & 1 != 0)
+                .count() as u32;
+            (count * 0xFF / len) as u8
+        })
+    }
+
+    // colors() as 4 bytes per pixel, see Emu::render_rgba()
+    pub fn render_rgba(&self, buffer: &mut [u8], palette: &Palette) {
+        for (pixel, color) in buffer.chunks_exact_mut(4).zip(self.colors()) {
+            pixel.copy_from_slice(&palette.colors[color as usize]);
+        }
+    }
+
+    // The resolution of the frames held
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.next = 0;
+    }
+
+    // Row and bit in the packed row of each pixel, row by row
+    fn pixels(&self) -> impl Iterator<Item = (usize, usize)> {
+        let (width, height) = (self.resolution.width(), self.resolution.height());
+        (0..height).flat_map(move |y| (0..width).map(move |x| (y, width - 1 - x)))
+    }
+}
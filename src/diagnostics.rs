@@ -0,0 +1,156 @@
+use alloc::boxed::Box;
+use core::fmt;
+
+use crate::*;
+
+// Calls that leave fewer free stack entries than this get reported
+const STACK_HEADROOM: usize = 2;
+
+// The standard and big fonts, at the bottom of RAM
+const FONT_AREA_END: usize = BIG_FONTSET_ADDR + BIG_FONTSET_SIZE;
+
+// Something a program did that runs but is usually a bug, reported to the
+// DiagnosticSink while the program carries on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Diagnostic {
+    StackNearOverflow { pc: u16, depth: usize }, // a CALL left `depth` entries in use
+    FontWrite { pc: u16, addr: usize },          // first font byte an instruction wrote
+    UnknownOpcode { pc: u16, op: u16 },          // skipped in permissive mode
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Diagnostic::StackNearOverflow { pc, depth } => {
+                write!(
+                    f,
+                    "Call at {:#05X} leaves {} of {} stack entries in use",
+                    pc, depth, STACK_SIZE
+                )
+            }
+            Diagnostic::FontWrite { pc, addr } => {
+                write!(f, "Write to the font at {:#05X} by {:#05X}", addr, pc)
+            }
+            Diagnostic::UnknownOpcode { pc, op } => {
+                write!(f, "Skipped unimplemented opcode {:#06X} at {:#05X}", op, pc)
+            }
+        }
+    }
+}
+
+// A frontend's log for diagnostics, see Emu::set_diagnostic_sink().
+// Closures taking a Diagnostic are sinks.
+pub trait DiagnosticSink {
+    fn warn(&mut self, diagnostic: Diagnostic);
+}
+
+impl<T: FnMut(Diagnostic)> DiagnosticSink for T {
+    fn warn(&mut self, diagnostic: Diagnostic) {
+        self(diagnostic)
+    }
+}
+
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    // Replaces any previous sink
+    pub fn set_diagnostic_sink(&mut self, sink: impl DiagnosticSink + Send + 'static) {
+        self.diagnostic_sink = audio::SinkSlot(Some(Box::new(sink)));
+    }
+
+    pub fn take_diagnostic_sink(&mut self) -> Option<Box<dyn DiagnosticSink + Send>> {
+        self.diagnostic_sink.0.take()
+    }
+
+    // Called before executing `instruction`, the first font byte it will write
+    pub(crate) fn check_font_write(&self, pc: u16, instruction: Instruction) -> Option<usize> {
+        self.diagnostic_sink.0.as_ref()?;
+        match self.ram_access(pc, instruction)? {
+            (range, Access::Write) if range.start < FONT_AREA_END => Some(range.start),
+            _ => None,
+        }
+    }
+
+    // Called after executing `instruction`
+    pub(crate) fn diagnose(
+        &mut self,
+        pc: u16,
+        instruction: Instruction,
+        font_write: Option<usize>,
+    ) {
+        let depth = self.sp as usize;
+        if let Instruction::Call(_) = instruction {
+            if depth > STACK_SIZE - STACK_HEADROOM {
+                self.warn(Diagnostic::StackNearOverflow { pc, depth });
+            }
+        }
+        if let Some(addr) = font_write {
+            self.warn(Diagnostic::FontWrite { pc, addr });
+        }
+    }
+
+    pub(crate) fn warn(&mut self, diagnostic: Diagnostic) {
+        if let Some(sink) = &mut self.diagnostic_sink.0 {
+            sink.warn(diagnostic);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::vec::Vec;
+
+    fn diagnose(program: &[u8], cycles: usize) -> Vec<Diagnostic> {
+        let mut emu = Emu::new();
+        emu.set_permissive(true);
+        emu.load(program).unwrap();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        emu.set_diagnostic_sink(move |diagnostic| log.lock().unwrap().push(diagnostic));
+        for _ in 0..cycles {
+            emu.tick().unwrap();
+        }
+        let seen = seen.lock().unwrap();
+        seen.clone()
+    }
+
+    #[test]
+    fn deep_calls_are_reported_before_they_overflow() {
+        // CALL 0x200, forever
+        let seen = diagnose(&[0x22, 0x00], 16);
+        let depths: Vec<_> = seen
+            .iter()
+            .map(|diagnostic| match *diagnostic {
+                Diagnostic::StackNearOverflow { pc: 0x200, depth } => depth,
+                _ => panic!("unexpected {:?}", diagnostic),
+            })
+            .collect();
+        assert_eq!(depths, [15, 16]);
+    }
+
+    #[test]
+    fn font_writes_are_reported() {
+        // LD I, 0x4E; LD [I], V3; LD I, 0x200; LD B, V0
+        let seen = diagnose(&[0xA0, 0x4E, 0xF3, 0x55, 0xA2, 0x00, 0xF0, 0x33], 4);
+        assert_eq!(
+            seen,
+            [Diagnostic::FontWrite {
+                pc: 0x202,
+                addr: 0x4E
+            }]
+        );
+    }
+
+    #[test]
+    fn unknown_opcodes_are_skipped_and_reported() {
+        // An unknown opcode, then LD V0, 1
+        let seen = diagnose(&[0x5A, 0xB1, 0x60, 0x01], 2);
+        assert_eq!(
+            seen,
+            [Diagnostic::UnknownOpcode {
+                pc: 0x200,
+                op: 0x5AB1
+            }]
+        );
+    }
+}
@@ -0,0 +1,462 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::fmt;
+
+#[cfg(feature = "alloc")]
+use crate::symbols::SymbolTable;
+use crate::{DecodeError, Instruction};
+
+// One disassembled instruction, formatted as `0x200: JP 0x228`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub addr: u16,
+    pub op: u16,
+    pub size: u16, // bytes consumed
+    pub instruction: Result<Instruction, DecodeError>,
+}
+
+// Linear disassembly of a byte slice loaded at `base`
+pub struct Disassembler<'a> {
+    bytes: &'a [u8],
+    base: u16,
+    offset: usize,
+}
+
+pub fn disassemble(bytes: &[u8], base: u16) -> Disassembler<'_> {
+    Disassembler {
+        bytes,
+        base,
+        offset: 0,
+    }
+}
+
+impl Iterator for Disassembler<'_> {
+    type Item = Line;
+
+    fn next(&mut self) -> Option<Line> {
+        let remaining = &self.bytes[self.offset.min(self.bytes.len())..];
+        let addr = self.base.wrapping_add(self.offset as u16);
+
+        let line = match *remaining {
+            [] => return None,
+            // A trailing odd byte can't be an instruction
+            [byte] => Line {
+                addr,
+                op: (byte as u16) << 8,
+                size: 1,
+                instruction: Err(DecodeError::Unknown((byte as u16) << 8)),
+            },
+            [high, low, ref rest @ ..] => {
+                let op = u16::from_be_bytes([high, low]);
+                let instruction = match (Instruction::decode(op), rest) {
+                    (Err(DecodeError::MissingOperand(_)), [high, low, ..]) => {
+                        Instruction::decode_long(op, u16::from_be_bytes([*high, *low]))
+                    }
+                    (result, _) => result,
+                };
+                let size = match instruction {
+                    Ok(instruction) => instruction.size(),
+                    Err(_) => 2,
+                };
+
+                Line {
+                    addr,
+                    op,
+                    size,
+                    instruction,
+                }
+            }
+        };
+
+        self.offset += line.size as usize;
+        Some(line)
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#05X}: ", self.addr)?;
+
+        match self.instruction {
+            Ok(instruction) => write!(f, "{}", instruction),
+            Err(_) if self.size == 1 => write!(f, "DB {:#04X}", self.op >> 8),
+            Err(_) => write!(f, "DW {:#06X}", self.op),
+        }
+    }
+}
+
+// A line or listing line displayed with label names in place of known
+// addresses. Labelled addresses get a `name:` line of their own.
+#[cfg(feature = "alloc")]
+pub struct WithSymbols<'s, T> {
+    item: T,
+    symbols: &'s SymbolTable,
+}
+
+#[cfg(feature = "alloc")]
+impl Line {
+    pub fn with_symbols<'s>(&self, symbols: &'s SymbolTable) -> WithSymbols<'s, Line> {
+        WithSymbols {
+            item: *self,
+            symbols,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Display for WithSymbols<'_, Line> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let line = &self.item;
+        write_label(f, line.addr, self.symbols)?;
+
+        match line.instruction {
+            Ok(instruction) => {
+                write!(f, "{:#05X}: ", line.addr)?;
+                write_instruction(f, instruction, self.symbols)
+            }
+            Err(_) => write!(f, "{}", line),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+fn write_label(f: &mut fmt::Formatter, addr: u16, symbols: &SymbolTable) -> fmt::Result {
+    match symbols.name(addr) {
+        Some(name) => writeln!(f, "{}:", name),
+        None => Ok(()),
+    }
+}
+
+#[cfg(feature = "alloc")]
+fn write_instruction(
+    f: &mut fmt::Formatter,
+    instruction: Instruction,
+    symbols: &SymbolTable,
+) -> fmt::Result {
+    let (mnemonic, addr) = match instruction {
+        Instruction::Jump(nnn) => ("JP ", nnn),
+        Instruction::Call(nnn) => ("CALL ", nnn),
+        Instruction::SetIndex(nnn) => ("LD I, ", nnn),
+        Instruction::JumpOffset { nnn, .. } => ("JP V0, ", nnn),
+        Instruction::SetIndexLong(nnnn) => ("LD I, LONG ", nnnn),
+        _ => return write!(f, "{}", instruction),
+    };
+
+    match symbols.name(addr) {
+        Some(name) => write!(f, "{}{}", mnemonic, name),
+        None => write!(f, "{}", instruction),
+    }
+}
+
+// Cowgod-style mnemonics, extended with the SCHIP and XO-CHIP instructions
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Nop => write!(f, "NOP"),
+            Instruction::Cls => write!(f, "CLS"),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::ScrollDown(n) => write!(f, "SCD {}", n),
+            Instruction::ScrollUp(n) => write!(f, "SCU {}", n),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::Lores => write!(f, "LOW"),
+            Instruction::Hires => write!(f, "HIGH"),
+            Instruction::Jump(nnn) => write!(f, "JP {:#05X}", nnn),
+            Instruction::Call(nnn) => write!(f, "CALL {:#05X}", nnn),
+            Instruction::SkipEqImm { x, nn } => write!(f, "SE V{:X}, {:#04X}", x, nn),
+            Instruction::SkipNeImm { x, nn } => write!(f, "SNE V{:X}, {:#04X}", x, nn),
+            Instruction::SkipEqReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::SaveRange { x, y } => write!(f, "SAVE V{:X} - V{:X}", x, y),
+            Instruction::LoadRange { x, y } => write!(f, "LOAD V{:X} - V{:X}", x, y),
+            Instruction::SetImm { x, nn } => write!(f, "LD V{:X}, {:#04X}", x, nn),
+            Instruction::AddImm { x, nn } => write!(f, "ADD V{:X}, {:#04X}", x, nn),
+            Instruction::Set { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::Add { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubReverse { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNeReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::SetIndex(nnn) => write!(f, "LD I, {:#05X}", nnn),
+            Instruction::JumpOffset { nnn, .. } => write!(f, "JP V0, {:#05X}", nnn),
+            Instruction::Random { x, nn } => write!(f, "RND V{:X}, {:#04X}", x, nn),
+            Instruction::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKeyPressed { x } => write!(f, "SKP V{:X}", x),
+            Instruction::SkipKeyNotPressed { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::SetIndexLong(nnnn) => write!(f, "LD I, LONG {:#06X}", nnnn),
+            Instruction::Plane(n) => write!(f, "PLANE {}", n),
+            Instruction::LoadAudio => write!(f, "AUDIO"),
+            Instruction::GetDelay { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelay { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSound { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddIndex { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::Font { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::BigFont { x } => write!(f, "LD HF, V{:X}", x),
+            Instruction::Bcd { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::Pitch { x } => write!(f, "PITCH V{:X}", x),
+            Instruction::Store { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::Load { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::SaveFlags { x } => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mark {
+    Data,
+    Code,        // first byte of a reachable instruction
+    CodeOperand, // remaining bytes of a reachable instruction
+    Unknown,     // reachable bytes that aren't an instruction
+}
+
+// Result of following control flow from the entry point, used to tell
+// code apart from the sprite data interleaved with it
+#[cfg(feature = "alloc")]
+pub struct Analysis<'a> {
+    bytes: &'a [u8],
+    base: u16,
+    marks: Vec<Mark>, // one per byte
+}
+
+// Walks jumps, calls and skips starting at `base`. Computed jumps (BNNN)
+// can't be followed statically, so code only reachable through them is
+// reported as data.
+#[cfg(feature = "alloc")]
+pub fn analyze(bytes: &[u8], base: u16) -> Analysis<'_> {
+    let bytes = &bytes[..bytes.len().min(crate::MAX_RAM_SIZE)];
+    let mut walker = Walker {
+        bytes,
+        base,
+        marks: alloc::vec![Mark::Data; bytes.len()],
+        pending: Vec::new(),
+    };
+
+    walker.visit(base);
+
+    while let Some(addr) = walker.pending.pop() {
+        let instruction = match walker.decode_at(addr) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        let next = addr.wrapping_add(instruction.size());
+
+        match instruction {
+            Instruction::Jump(nnn) => walker.visit(nnn),
+            Instruction::Call(nnn) => {
+                walker.visit(nnn);
+                walker.visit(next);
+            }
+            Instruction::Ret | Instruction::Exit | Instruction::JumpOffset { .. } => {}
+            Instruction::SkipEqImm { .. }
+            | Instruction::SkipNeImm { .. }
+            | Instruction::SkipEqReg { .. }
+            | Instruction::SkipNeReg { .. }
+            | Instruction::SkipKeyPressed { .. }
+            | Instruction::SkipKeyNotPressed { .. } => {
+                let skipped_size = walker.decode_at(next).map_or(2, |skipped| skipped.size());
+                walker.visit(next);
+                walker.visit(next.wrapping_add(skipped_size));
+            }
+            _ => walker.visit(next),
+        }
+    }
+
+    Analysis {
+        bytes,
+        base,
+        marks: walker.marks,
+    }
+}
+
+#[cfg(feature = "alloc")]
+struct Walker<'a> {
+    bytes: &'a [u8],
+    base: u16,
+    marks: Vec<Mark>,
+    // Every instruction is queued at most once since it's marked when queued
+    pending: Vec<u16>,
+}
+
+#[cfg(feature = "alloc")]
+impl Walker<'_> {
+    fn decode_at(&self, addr: u16) -> Option<Instruction> {
+        let offset = addr.wrapping_sub(self.base) as usize;
+        let byte = |i: usize| self.bytes.get(offset + i).copied();
+
+        let op = u16::from_be_bytes([byte(0)?, byte(1)?]);
+        match Instruction::decode(op) {
+            Err(DecodeError::MissingOperand(_)) => {
+                let operand = u16::from_be_bytes([byte(2)?, byte(3)?]);
+                Instruction::decode_long(op, operand).ok()
+            }
+            result => result.ok(),
+        }
+    }
+
+    fn visit(&mut self, addr: u16) {
+        let offset = addr.wrapping_sub(self.base) as usize;
+        if offset >= self.bytes.len() || self.marks[offset] != Mark::Data {
+            return;
+        }
+
+        if let Some(instruction) = self.decode_at(addr) {
+            let size = instruction.size() as usize;
+            self.marks[offset] = Mark::Code;
+            self.marks[offset + 1..offset + size].fill(Mark::CodeOperand);
+            self.pending.push(addr);
+        } else {
+            self.marks[offset] = Mark::Unknown;
+        }
+    }
+}
+
+// Data lines hold at most this many bytes
+#[cfg(feature = "alloc")]
+const DATA_LINE_LEN: usize = 8;
+
+#[cfg(feature = "alloc")]
+impl<'a> Analysis<'a> {
+    pub fn is_code(&self, addr: u16) -> bool {
+        let offset = addr.wrapping_sub(self.base) as usize;
+        offset < self.bytes.len() && matches!(self.marks[offset], Mark::Code | Mark::CodeOperand)
+    }
+
+    // Opcodes control flow reaches that don't decode, a sign of data run as
+    // code or of an instruction set this crate doesn't know
+    pub fn unknown(&self) -> impl Iterator<Item = Line> + '_ {
+        (0..self.bytes.len())
+            .filter(|&offset| self.marks[offset] == Mark::Unknown)
+            .filter_map(|offset| {
+                let addr = self.base.wrapping_add(offset as u16);
+                disassemble(&self.bytes[offset..], addr).next()
+            })
+    }
+
+    pub fn listing(&self) -> Listing<'_, 'a> {
+        Listing {
+            analysis: self,
+            offset: 0,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListingLine<'a> {
+    Code(Line),
+    Data { addr: u16, bytes: &'a [u8] },
+}
+
+// Disassembly of the reachable code, with everything else grouped into data lines
+#[cfg(feature = "alloc")]
+pub struct Listing<'r, 'a> {
+    analysis: &'r Analysis<'a>,
+    offset: usize,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> Iterator for Listing<'_, 'a> {
+    type Item = ListingLine<'a>;
+
+    fn next(&mut self) -> Option<ListingLine<'a>> {
+        let analysis = self.analysis;
+        let bytes = analysis.bytes;
+        if self.offset >= bytes.len() {
+            return None;
+        }
+
+        let addr = analysis.base.wrapping_add(self.offset as u16);
+
+        if analysis.marks[self.offset] == Mark::Code {
+            let line = disassemble(&bytes[self.offset..], addr).next()?;
+            self.offset += line.size as usize;
+            return Some(ListingLine::Code(line));
+        }
+
+        let start = self.offset;
+        while self.offset < bytes.len()
+            && self.offset - start < DATA_LINE_LEN
+            && analysis.marks[self.offset] != Mark::Code
+        {
+            self.offset += 1;
+        }
+
+        Some(ListingLine::Data {
+            addr,
+            bytes: &bytes[start..self.offset],
+        })
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Display for ListingLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ListingLine::Code(line) => write!(f, "{}", line),
+            ListingLine::Data { addr, bytes } => {
+                write!(f, "{:#05X}: DB", addr)?;
+                for (i, byte) in bytes.iter().enumerate() {
+                    let separator = if i == 0 { " " } else { ", " };
+                    write!(f, "{}{:#04X}", separator, byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> ListingLine<'a> {
+    pub fn with_symbols<'s>(&self, symbols: &'s SymbolTable) -> WithSymbols<'s, ListingLine<'a>> {
+        WithSymbols {
+            item: *self,
+            symbols,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Display for WithSymbols<'_, ListingLine<'_>> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.item {
+            ListingLine::Code(line) => write!(f, "{}", line.with_symbols(self.symbols)),
+            ListingLine::Data { addr, .. } => {
+                write_label(f, addr, self.symbols)?;
+                write!(f, "{}", self.item)
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn analysis_skips_data_between_code() {
+        // JP 0x206 / sprite rows / LD I, 0x202 / JP 0x208
+        let rom = [0x12, 0x06, 0xF0, 0x90, 0xF0, 0x90, 0xA2, 0x02, 0x12, 0x08];
+        let analysis = analyze(&rom, 0x200);
+        assert!(analysis.is_code(0x200));
+        assert!(!analysis.is_code(0x202));
+        assert!(analysis.is_code(0x207));
+        assert_eq!(analysis.unknown().count(), 0);
+
+        let listing: Vec<ListingLine> = analysis.listing().collect();
+        assert_eq!(listing.len(), 4);
+        assert_eq!(
+            listing[1],
+            ListingLine::Data {
+                addr: 0x202,
+                bytes: &rom[2..6]
+            }
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use core::fmt;
+
+// A fault raised by a ROM, the emulator stays usable and can be reset
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmuError {
+    UnknownOpcode { pc: u16, op: u16 },
+    StackOverflow { pc: u16, op: u16 }, // CALL with all 16 levels in use
+    StackUnderflow { pc: u16, op: u16 }, // RET with an empty stack
+    MemoryOutOfBounds { pc: u16, addr: usize },
+}
+
+impl EmuError {
+    // Address of the faulting instruction
+    pub fn pc(&self) -> u16 {
+        match *self {
+            EmuError::UnknownOpcode { pc, .. }
+            | EmuError::StackOverflow { pc, .. }
+            | EmuError::StackUnderflow { pc, .. }
+            | EmuError::MemoryOutOfBounds { pc, .. } => pc,
+        }
+    }
+}
+
+impl fmt::Display for EmuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EmuError::UnknownOpcode { pc, op } => {
+                write!(f, "Unimplemented opcode {:#06X} at {:#05X}", op, pc)
+            }
+            EmuError::StackOverflow { pc, op } => {
+                write!(f, "Stack overflow by {:#06X} at {:#05X}", op, pc)
+            }
+            EmuError::StackUnderflow { pc, op } => {
+                write!(f, "Stack underflow by {:#06X} at {:#05X}", op, pc)
+            }
+            EmuError::MemoryOutOfBounds { pc, addr } => {
+                write!(
+                    f,
+                    "Memory access out of bounds at {:#06X} by {:#05X}",
+                    addr, pc
+                )
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EmuError {}
+
+// Why a ROM couldn't be loaded, RAM is left untouched
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadError {
+    Empty,
+    TooLarge { size: usize, capacity: usize }, // capacity: bytes that would fit
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Empty => write!(f, "ROM is empty"),
+            LoadError::TooLarge { size, capacity } => write!(
+                f,
+                "ROM is {} bytes but only {} fit in memory",
+                size, capacity
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LoadError {}
@@ -0,0 +1,133 @@
+// C API, declared in include/chip8_core.h. Build a shared library with
+//
+//     cargo rustc --release --features ffi --crate-type cdylib
+//
+// Every function taking a `Chip8 *` expects a live handle from chip8_create,
+// which must be freed with chip8_destroy and not used from several threads
+// at once. Pointers to data must be valid for the given length.
+#![allow(clippy::missing_safety_doc)]
+
+use std::boxed::Box;
+use std::vec::Vec;
+
+use crate::{Emu, Key, RunState, Variant};
+
+pub const CHIP8_ERROR: i32 = -1;
+
+// Opaque to C
+pub struct Chip8 {
+    emu: Emu,
+    display: Vec<bool>, // unpacked for chip8_get_display
+}
+
+#[no_mangle]
+pub extern "C" fn chip8_create() -> *mut Chip8 {
+    Box::into_raw(Box::new(Chip8 {
+        emu: Emu::new(),
+        display: Vec::new(),
+    }))
+}
+
+// 0 CHIP-8, 1 CHIP-48, 2 SCHIP 1.1, 3 modern SCHIP, 4 XO-CHIP; null for anything else
+#[no_mangle]
+pub extern "C" fn chip8_create_variant(variant: i32) -> *mut Chip8 {
+    let variant = match variant {
+        0 => Variant::CosmacVip,
+        1 => Variant::Chip48,
+        2 => Variant::SuperChipLegacy,
+        3 => Variant::SuperChipModern,
+        4 => Variant::XoChip,
+        _ => return std::ptr::null_mut(),
+    };
+
+    Box::into_raw(Box::new(Chip8 {
+        emu: Emu::with_variant(variant),
+        display: Vec::new(),
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_destroy(chip8: *mut Chip8) {
+    if !chip8.is_null() {
+        drop(Box::from_raw(chip8));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_reset(chip8: *mut Chip8) {
+    (*chip8).emu.reset();
+}
+
+// Loads at 0x200, false if the ROM is empty or doesn't fit in memory
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(chip8: *mut Chip8, data: *const u8, len: usize) -> bool {
+    if data.is_null() {
+        return false;
+    }
+
+    (*chip8)
+        .emu
+        .load(std::slice::from_raw_parts(data, len))
+        .is_ok()
+}
+
+// Runs one instruction. Returns 0 running, 1 waiting for vblank, 2 halted,
+// 3 spinning, or CHIP8_ERROR if the ROM faulted.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_tick(chip8: *mut Chip8) -> i32 {
+    match (*chip8).emu.tick().map(|outcome| outcome.run_state) {
+        Ok(RunState::Running) => 0,
+        Ok(RunState::WaitingForVblank) => 1,
+        Ok(RunState::Halted) => 2,
+        Ok(RunState::Spinning) => 3,
+        Err(_) => CHIP8_ERROR,
+    }
+}
+
+// Runs up to `n` instructions, stopping early on halts and waits. Returns
+// how many ran or CHIP8_ERROR if the ROM faulted.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_tick_many(chip8: *mut Chip8, n: u32) -> i64 {
+    match (*chip8).emu.tick_many(n) {
+        Ok(count) => count as i64,
+        Err(_) => CHIP8_ERROR as i64,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_tick_timers(chip8: *mut Chip8) {
+    (*chip8).emu.tick_timers();
+}
+
+// Keys 0x0-0xF, others are ignored
+#[no_mangle]
+pub unsafe extern "C" fn chip8_keypress(chip8: *mut Chip8, key: u8, pressed: bool) {
+    if let Ok(key) = Key::try_from(key) {
+        (*chip8).emu.keypress(key, pressed);
+    }
+}
+
+// chip8_display_width() * chip8_display_height() pixels, row by row,
+// unpacked into a buffer of the handle. Valid until the next call to this.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_get_display(chip8: *mut Chip8) -> *const bool {
+    let chip8 = &mut *chip8;
+    chip8.display.clear();
+    chip8.display.extend(chip8.emu.get_display());
+    chip8.display.as_ptr()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_display_width(chip8: *const Chip8) -> usize {
+    (*chip8).emu.resolution().width()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_display_height(chip8: *const Chip8) -> usize {
+    (*chip8).emu.resolution().height()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_is_sound_active(chip8: *const Chip8) -> bool {
+    (*chip8).emu.is_sound_active()
+}
@@ -0,0 +1,219 @@
+use alloc::vec::Vec;
+
+// LZW codes are at most this wide
+const MAX_CODE_SIZE: u32 = 12;
+const MAX_CODES: usize = 1 << MAX_CODE_SIZE;
+
+// Octo cartridges are a few hundred pixels across, this keeps a hostile
+// file from asking for 4GiB with a 65535x65535 frame
+const MAX_FRAME_PIXELS: usize = 1 << 20;
+
+const EXTENSION: u8 = 0x21;
+const IMAGE: u8 = 0x2C;
+const TRAILER: u8 = 0x3B;
+
+// The color indices of every frame of a GIF, row by row as stored. Only
+// what Octo cartridges need is kept: no colors, positions or timing.
+pub(crate) struct Gif {
+    pub frames: Vec<Vec<u8>>,
+}
+
+// None if the data isn't a well-formed GIF
+pub(crate) fn decode(data: &[u8]) -> Option<Gif> {
+    let mut reader = Reader { data, offset: 0 };
+
+    if !matches!(reader.bytes(6)?, b"GIF87a" | b"GIF89a") {
+        return None;
+    }
+    reader.bytes(4)?; // screen width and height
+    let flags = reader.byte()?;
+    reader.bytes(2)?; // background color and aspect ratio
+    reader.color_table(flags)?;
+
+    let mut frames = Vec::new();
+    loop {
+        match reader.byte()? {
+            EXTENSION => {
+                reader.byte()?; // label
+                reader.sub_blocks(|_| ())?;
+            }
+            IMAGE => {
+                reader.bytes(4)?; // left and top
+                let size = reader.u16()? as usize * reader.u16()? as usize;
+                if size > MAX_FRAME_PIXELS {
+                    return None;
+                }
+                let flags = reader.byte()?;
+                reader.color_table(flags)?;
+
+                let min_code_size = reader.byte()?;
+                let mut compressed = Vec::new();
+                reader.sub_blocks(|block| compressed.extend_from_slice(block))?;
+
+                let mut pixels = Vec::with_capacity(size);
+                decompress(min_code_size, &compressed, size, &mut pixels)?;
+                pixels.resize(size, 0);
+                frames.push(pixels);
+            }
+            TRAILER => break,
+            _ => return None,
+        }
+    }
+
+    Some(Gif { frames })
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.offset..self.offset + len)?;
+        self.offset += len;
+        Some(bytes)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.bytes(2)?;
+        Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    // Skips the table a screen or image descriptor with these flags announces
+    fn color_table(&mut self, flags: u8) -> Option<()> {
+        if flags & 0x80 != 0 {
+            self.bytes(3 << ((flags & 0x07) + 1))?;
+        }
+        Some(())
+    }
+
+    // Length prefixed blocks up to an empty one
+    fn sub_blocks(&mut self, mut f: impl FnMut(&[u8])) -> Option<()> {
+        loop {
+            let len = self.byte()? as usize;
+            if len == 0 {
+                return Some(());
+            }
+            f(self.bytes(len)?);
+        }
+    }
+}
+
+// Variable width LZW as GIF uses it: codes are packed LSB first and grow
+// by a bit whenever the table fills up their range. Stops at `limit` bytes,
+// codes can expand to far more than the image holds.
+fn decompress(min_code_size: u8, data: &[u8], limit: usize, out: &mut Vec<u8>) -> Option<()> {
+    if !(1..MAX_CODE_SIZE as u8).contains(&min_code_size) {
+        return None;
+    }
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+
+    // Entries for codes past `end`: the code of the string without its
+    // last byte, and that byte
+    let mut table: Vec<(u16, u8)> = Vec::with_capacity(MAX_CODES);
+    let mut code_size = min_code_size as u32 + 1;
+    let mut previous: Option<u16> = None;
+    let (mut bits, mut num_bits, mut offset) = (0u32, 0u32, 0);
+
+    loop {
+        while num_bits < code_size {
+            // Data that ends without an end code ends the image all the same
+            let Some(&byte) = data.get(offset) else {
+                return Some(());
+            };
+            bits |= (byte as u32) << num_bits;
+            num_bits += 8;
+            offset += 1;
+        }
+        let code = (bits & ((1 << code_size) - 1)) as u16;
+        bits >>= code_size;
+        num_bits -= code_size;
+
+        if code == clear {
+            table.clear();
+            code_size = min_code_size as u32 + 1;
+            previous = None;
+            continue;
+        }
+        if code == end {
+            return Some(());
+        }
+
+        let next = end + 1 + table.len() as u16;
+        let start = out.len();
+        match previous {
+            _ if code < next => {
+                expand(&table, end, code, out);
+            }
+            // The string about to be added: the previous one and its first byte
+            Some(previous) if code == next => {
+                expand(&table, end, previous, out);
+                out.push(out[start]);
+            }
+            _ => return None,
+        }
+        if out.len() >= limit {
+            out.truncate(limit);
+            return Some(());
+        }
+
+        if let Some(previous) = previous {
+            if (next as usize) < MAX_CODES {
+                table.push((previous, out[start]));
+                if next + 1 == 1 << code_size && code_size < MAX_CODE_SIZE {
+                    code_size += 1;
+                }
+            }
+        }
+        previous = Some(code);
+    }
+}
+
+// Appends the string of `code`, which the table is known to hold
+fn expand(table: &[(u16, u8)], end: u16, mut code: u16, out: &mut Vec<u8>) {
+    let start = out.len();
+    while code > end {
+        let (prefix, byte) = table[(code - end - 1) as usize];
+        out.push(byte);
+        code = prefix;
+    }
+    out.push(code as u8);
+    out[start..].reverse();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gif(width: u16, height: u16, codes: &[u8]) -> Vec<u8> {
+        let mut data = b"GIF89a".to_vec();
+        data.extend_from_slice(&[1, 0, 1, 0, 0, 0, 0]);
+        data.push(IMAGE);
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data.extend_from_slice(&width.to_le_bytes());
+        data.extend_from_slice(&height.to_le_bytes());
+        data.extend_from_slice(&[0, 2, codes.len() as u8]);
+        data.extend_from_slice(codes);
+        data.extend_from_slice(&[0, TRAILER]);
+        data
+    }
+
+    #[test]
+    fn huge_frames_are_refused() {
+        assert!(decode(&gif(0xFFFF, 0xFFFF, &[0x44, 0x01])).is_none());
+    }
+
+    #[test]
+    fn frames_hold_no_more_than_their_size() {
+        // Clear, then a run of index 0 far longer than the 2x2 frame
+        let codes = [0x04, 0x0C, 0x52, 0x0C, 0x72, 0x0C, 0x08];
+        let frame = &decode(&gif(2, 2, &codes)).unwrap().frames[0];
+        assert_eq!(frame, &[0; 4]);
+    }
+}
@@ -0,0 +1,102 @@
+use alloc::collections::VecDeque;
+
+use crate::*;
+
+// Key changes waiting for their frame, see Emu::queue_key_event()
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct KeyQueue {
+    events: VecDeque<KeyEvent>, // by frame, in the order queued within one
+    changed: u16,               // keys changed this frame, bit N for key N
+}
+
+impl KeyQueue {
+    pub(crate) fn clear(&mut self) {
+        self.events.clear();
+        self.changed = 0;
+    }
+
+    pub(crate) fn end_frame(&mut self) {
+        self.changed = 0;
+    }
+}
+
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    // Queues a key change for the frame where metrics().frames reaches
+    // `event.frame`, applied before the first instruction that runs in it.
+    // Each key changes at most once per frame, later changes wait for the
+    // next one, so a press released right away is still seen by the program.
+    pub fn queue_key_event(&mut self, event: KeyEvent) {
+        let events = &mut self.key_queue.events;
+        let index = events.partition_point(|queued| queued.frame <= event.frame);
+        events.insert(index, event);
+    }
+
+    pub fn pending_key_events(&self) -> usize {
+        self.key_queue.events.len()
+    }
+
+    pub fn clear_key_events(&mut self) {
+        self.key_queue.clear();
+    }
+
+    pub(crate) fn apply_key_events(&mut self) {
+        let frame = self.metrics.frames;
+        let mut index = 0;
+
+        while let Some(&event) = self.key_queue.events.get(index) {
+            if event.frame as u64 > frame {
+                break;
+            }
+
+            // The key already changed this frame, its later events wait
+            // behind this one while other keys go ahead
+            let bit = 1 << event.key.index();
+            if self.key_queue.changed & bit != 0 {
+                index += 1;
+                continue;
+            }
+
+            self.key_queue.events.remove(index);
+            self.key_queue.changed |= bit;
+            self.keys[event.key.index()] = event.pressed;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(frame: u32, key: Key, pressed: bool) -> KeyEvent {
+        KeyEvent {
+            frame,
+            key,
+            pressed,
+        }
+    }
+
+    #[test]
+    fn a_busy_key_doesnt_hold_back_other_keys() {
+        let mut emu = Emu::new();
+        // JP 0x200
+        emu.load(&[0x12, 0x00]).unwrap();
+        emu.queue_key_event(event(0, Key::Key1, true));
+        emu.queue_key_event(event(0, Key::Key1, false));
+        emu.queue_key_event(event(0, Key::Key2, true));
+        emu.queue_key_event(event(0, Key::Key1, true));
+        emu.queue_key_event(event(1, Key::Key3, true));
+
+        emu.tick().unwrap();
+        assert!(emu.keys()[1] && emu.keys()[2] && !emu.keys()[3]);
+        assert_eq!(emu.pending_key_events(), 3);
+
+        // Key 1 goes up and back down a frame at a time
+        emu.tick_timers();
+        emu.tick().unwrap();
+        assert!(!emu.keys()[1] && emu.keys()[3]);
+        emu.tick_timers();
+        emu.tick().unwrap();
+        assert!(emu.keys()[1]);
+        assert_eq!(emu.pending_key_events(), 0);
+    }
+}
@@ -0,0 +1,353 @@
+use core::fmt;
+
+// A decoded opcode. Register operands (x, y) are indices 0x0-0xF.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    Nop,                            // 0000
+    Cls,                            // 00E0
+    Ret,                            // 00EE
+    ScrollDown(u8),                 // 00CN
+    ScrollUp(u8),                   // 00DN
+    ScrollRight,                    // 00FB
+    ScrollLeft,                     // 00FC
+    Exit,                           // 00FD
+    Lores,                          // 00FE
+    Hires,                          // 00FF
+    Jump(u16),                      // 1NNN
+    Call(u16),                      // 2NNN
+    SkipEqImm { x: u8, nn: u8 },    // 3XNN
+    SkipNeImm { x: u8, nn: u8 },    // 4XNN
+    SkipEqReg { x: u8, y: u8 },     // 5XY0
+    SaveRange { x: u8, y: u8 },     // 5XY2
+    LoadRange { x: u8, y: u8 },     // 5XY3
+    SetImm { x: u8, nn: u8 },       // 6XNN
+    AddImm { x: u8, nn: u8 },       // 7XNN
+    Set { x: u8, y: u8 },           // 8XY0
+    Or { x: u8, y: u8 },            // 8XY1
+    And { x: u8, y: u8 },           // 8XY2
+    Xor { x: u8, y: u8 },           // 8XY3
+    Add { x: u8, y: u8 },           // 8XY4
+    Sub { x: u8, y: u8 },           // 8XY5
+    ShiftRight { x: u8, y: u8 },    // 8XY6
+    SubReverse { x: u8, y: u8 },    // 8XY7
+    ShiftLeft { x: u8, y: u8 },     // 8XYE
+    SkipNeReg { x: u8, y: u8 },     // 9XY0
+    SetIndex(u16),                  // ANNN
+    JumpOffset { x: u8, nnn: u16 }, // BNNN, X is the top digit of NNN
+    Random { x: u8, nn: u8 },       // CXNN
+    Draw { x: u8, y: u8, n: u8 },   // DXYN
+    SkipKeyPressed { x: u8 },       // EX9E
+    SkipKeyNotPressed { x: u8 },    // EXA1
+    SetIndexLong(u16),              // F000 NNNN
+    Plane(u8),                      // FN01
+    LoadAudio,                      // F002
+    GetDelay { x: u8 },             // FX07
+    WaitKey { x: u8 },              // FX0A
+    SetDelay { x: u8 },             // FX15
+    SetSound { x: u8 },             // FX18
+    AddIndex { x: u8 },             // FX1E
+    Font { x: u8 },                 // FX29
+    BigFont { x: u8 },              // FX30
+    Bcd { x: u8 },                  // FX33
+    Pitch { x: u8 },                // FX3A
+    Store { x: u8 },                // FX55
+    Load { x: u8 },                 // FX65
+    SaveFlags { x: u8 },            // FX75
+    LoadFlags { x: u8 },            // FX85
+}
+
+// The instruction set an instruction first appeared in, each one extends
+// the previous
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Extension {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    Unknown(u16),
+    MissingOperand(u16), // long opcode, use Instruction::decode_long
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Unknown(op) => write!(f, "Unimplemented opcode: {:#06X}", op),
+            DecodeError::MissingOperand(op) => {
+                write!(f, "Opcode {:#06X} needs a second word", op)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
+
+impl Instruction {
+    // Whether the opcode is followed by a 16-bit operand word (F000 NNNN)
+    pub fn is_long(op: u16) -> bool {
+        op == 0xF000
+    }
+
+    pub fn decode(op: u16) -> Result<Instruction, DecodeError> {
+        let digit1 = (op & 0xF000) >> 12;
+        let digit2 = (op & 0x0F00) >> 8;
+        let digit3 = (op & 0x00F0) >> 4;
+        let digit4 = op & 0x000F;
+
+        let x = digit2 as u8;
+        let y = digit3 as u8;
+        let n = digit4 as u8;
+        let nn = (op & 0x00FF) as u8;
+        let nnn = op & 0x0FFF;
+
+        let instruction = match (digit1, digit2, digit3, digit4) {
+            (0, 0, 0, 0) => Instruction::Nop,
+            (0, 0, 0xE, 0) => Instruction::Cls,
+            (0, 0, 0xE, 0xE) => Instruction::Ret,
+            (0, 0, 0xC, _) => Instruction::ScrollDown(n),
+            (0, 0, 0xD, _) => Instruction::ScrollUp(n),
+            (0, 0, 0xF, 0xB) => Instruction::ScrollRight,
+            (0, 0, 0xF, 0xC) => Instruction::ScrollLeft,
+            (0, 0, 0xF, 0xD) => Instruction::Exit,
+            (0, 0, 0xF, 0xE) => Instruction::Lores,
+            (0, 0, 0xF, 0xF) => Instruction::Hires,
+            (1, _, _, _) => Instruction::Jump(nnn),
+            (2, _, _, _) => Instruction::Call(nnn),
+            (3, _, _, _) => Instruction::SkipEqImm { x, nn },
+            (4, _, _, _) => Instruction::SkipNeImm { x, nn },
+            (5, _, _, 0) => Instruction::SkipEqReg { x, y },
+            (5, _, _, 2) => Instruction::SaveRange { x, y },
+            (5, _, _, 3) => Instruction::LoadRange { x, y },
+            (6, _, _, _) => Instruction::SetImm { x, nn },
+            (7, _, _, _) => Instruction::AddImm { x, nn },
+            (8, _, _, 0) => Instruction::Set { x, y },
+            (8, _, _, 1) => Instruction::Or { x, y },
+            (8, _, _, 2) => Instruction::And { x, y },
+            (8, _, _, 3) => Instruction::Xor { x, y },
+            (8, _, _, 4) => Instruction::Add { x, y },
+            (8, _, _, 5) => Instruction::Sub { x, y },
+            (8, _, _, 6) => Instruction::ShiftRight { x, y },
+            (8, _, _, 7) => Instruction::SubReverse { x, y },
+            (8, _, _, 0xE) => Instruction::ShiftLeft { x, y },
+            (9, _, _, 0) => Instruction::SkipNeReg { x, y },
+            (0xA, _, _, _) => Instruction::SetIndex(nnn),
+            (0xB, _, _, _) => Instruction::JumpOffset { x, nnn },
+            (0xC, _, _, _) => Instruction::Random { x, nn },
+            (0xD, _, _, _) => Instruction::Draw { x, y, n },
+            (0xE, _, 0x9, 0xE) => Instruction::SkipKeyPressed { x },
+            (0xE, _, 0xA, 0x1) => Instruction::SkipKeyNotPressed { x },
+            (0xF, 0, 0x0, 0x0) => return Err(DecodeError::MissingOperand(op)),
+            (0xF, _, 0x0, 0x1) => Instruction::Plane(x),
+            (0xF, 0, 0x0, 0x2) => Instruction::LoadAudio,
+            (0xF, _, 0x0, 0x7) => Instruction::GetDelay { x },
+            (0xF, _, 0x0, 0xA) => Instruction::WaitKey { x },
+            (0xF, _, 0x1, 0x5) => Instruction::SetDelay { x },
+            (0xF, _, 0x1, 0x8) => Instruction::SetSound { x },
+            (0xF, _, 0x1, 0xE) => Instruction::AddIndex { x },
+            (0xF, _, 0x2, 0x9) => Instruction::Font { x },
+            (0xF, _, 0x3, 0x0) => Instruction::BigFont { x },
+            (0xF, _, 0x3, 0x3) => Instruction::Bcd { x },
+            (0xF, _, 0x3, 0xA) => Instruction::Pitch { x },
+            (0xF, _, 0x5, 0x5) => Instruction::Store { x },
+            (0xF, _, 0x6, 0x5) => Instruction::Load { x },
+            (0xF, _, 0x7, 0x5) => Instruction::SaveFlags { x },
+            (0xF, _, 0x8, 0x5) => Instruction::LoadFlags { x },
+            (_, _, _, _) => return Err(DecodeError::Unknown(op)),
+        };
+
+        Ok(instruction)
+    }
+
+    // Opcode pattern as in the Instruction comments, e.g. "8XY4", for
+    // grouping instructions by kind
+    pub fn pattern(&self) -> &'static str {
+        match self {
+            Instruction::Nop => "0000",
+            Instruction::Cls => "00E0",
+            Instruction::Ret => "00EE",
+            Instruction::ScrollDown(_) => "00CN",
+            Instruction::ScrollUp(_) => "00DN",
+            Instruction::ScrollRight => "00FB",
+            Instruction::ScrollLeft => "00FC",
+            Instruction::Exit => "00FD",
+            Instruction::Lores => "00FE",
+            Instruction::Hires => "00FF",
+            Instruction::Jump(_) => "1NNN",
+            Instruction::Call(_) => "2NNN",
+            Instruction::SkipEqImm { .. } => "3XNN",
+            Instruction::SkipNeImm { .. } => "4XNN",
+            Instruction::SkipEqReg { .. } => "5XY0",
+            Instruction::SaveRange { .. } => "5XY2",
+            Instruction::LoadRange { .. } => "5XY3",
+            Instruction::SetImm { .. } => "6XNN",
+            Instruction::AddImm { .. } => "7XNN",
+            Instruction::Set { .. } => "8XY0",
+            Instruction::Or { .. } => "8XY1",
+            Instruction::And { .. } => "8XY2",
+            Instruction::Xor { .. } => "8XY3",
+            Instruction::Add { .. } => "8XY4",
+            Instruction::Sub { .. } => "8XY5",
+            Instruction::ShiftRight { .. } => "8XY6",
+            Instruction::SubReverse { .. } => "8XY7",
+            Instruction::ShiftLeft { .. } => "8XYE",
+            Instruction::SkipNeReg { .. } => "9XY0",
+            Instruction::SetIndex(_) => "ANNN",
+            Instruction::JumpOffset { .. } => "BNNN",
+            Instruction::Random { .. } => "CXNN",
+            Instruction::Draw { .. } => "DXYN",
+            Instruction::SkipKeyPressed { .. } => "EX9E",
+            Instruction::SkipKeyNotPressed { .. } => "EXA1",
+            Instruction::SetIndexLong(_) => "F000 NNNN",
+            Instruction::Plane(_) => "FN01",
+            Instruction::LoadAudio => "F002",
+            Instruction::GetDelay { .. } => "FX07",
+            Instruction::WaitKey { .. } => "FX0A",
+            Instruction::SetDelay { .. } => "FX15",
+            Instruction::SetSound { .. } => "FX18",
+            Instruction::AddIndex { .. } => "FX1E",
+            Instruction::Font { .. } => "FX29",
+            Instruction::BigFont { .. } => "FX30",
+            Instruction::Bcd { .. } => "FX33",
+            Instruction::Pitch { .. } => "FX3A",
+            Instruction::Store { .. } => "FX55",
+            Instruction::Load { .. } => "FX65",
+            Instruction::SaveFlags { .. } => "FX75",
+            Instruction::LoadFlags { .. } => "FX85",
+        }
+    }
+
+    // Decodes an opcode together with the word that follows it
+    pub fn decode_long(op: u16, operand: u16) -> Result<Instruction, DecodeError> {
+        if Instruction::is_long(op) {
+            Ok(Instruction::SetIndexLong(operand))
+        } else {
+            Instruction::decode(op)
+        }
+    }
+
+    // Opcode word; for SetIndexLong this is the F000 prefix, the address follows it.
+    // Operands are masked to their field width.
+    pub fn encode(&self) -> u16 {
+        let xy = |op: u16, x: u8, y: u8| op | ((x as u16 & 0xF) << 8) | ((y as u16 & 0xF) << 4);
+        let xnn = |op: u16, x: u8, nn: u8| op | ((x as u16 & 0xF) << 8) | nn as u16;
+        let x_op = |op: u16, x: u8| op | ((x as u16 & 0xF) << 8);
+
+        match *self {
+            Instruction::Nop => 0x0000,
+            Instruction::Cls => 0x00E0,
+            Instruction::Ret => 0x00EE,
+            Instruction::ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+            Instruction::ScrollUp(n) => 0x00D0 | (n as u16 & 0xF),
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::Lores => 0x00FE,
+            Instruction::Hires => 0x00FF,
+            Instruction::Jump(nnn) => 0x1000 | (nnn & 0xFFF),
+            Instruction::Call(nnn) => 0x2000 | (nnn & 0xFFF),
+            Instruction::SkipEqImm { x, nn } => xnn(0x3000, x, nn),
+            Instruction::SkipNeImm { x, nn } => xnn(0x4000, x, nn),
+            Instruction::SkipEqReg { x, y } => xy(0x5000, x, y),
+            Instruction::SaveRange { x, y } => xy(0x5002, x, y),
+            Instruction::LoadRange { x, y } => xy(0x5003, x, y),
+            Instruction::SetImm { x, nn } => xnn(0x6000, x, nn),
+            Instruction::AddImm { x, nn } => xnn(0x7000, x, nn),
+            Instruction::Set { x, y } => xy(0x8000, x, y),
+            Instruction::Or { x, y } => xy(0x8001, x, y),
+            Instruction::And { x, y } => xy(0x8002, x, y),
+            Instruction::Xor { x, y } => xy(0x8003, x, y),
+            Instruction::Add { x, y } => xy(0x8004, x, y),
+            Instruction::Sub { x, y } => xy(0x8005, x, y),
+            Instruction::ShiftRight { x, y } => xy(0x8006, x, y),
+            Instruction::SubReverse { x, y } => xy(0x8007, x, y),
+            Instruction::ShiftLeft { x, y } => xy(0x800E, x, y),
+            Instruction::SkipNeReg { x, y } => xy(0x9000, x, y),
+            Instruction::SetIndex(nnn) => 0xA000 | (nnn & 0xFFF),
+            Instruction::JumpOffset { nnn, .. } => 0xB000 | (nnn & 0xFFF),
+            Instruction::Random { x, nn } => xnn(0xC000, x, nn),
+            Instruction::Draw { x, y, n } => xy(0xD000, x, y) | (n as u16 & 0xF),
+            Instruction::SkipKeyPressed { x } => x_op(0xE09E, x),
+            Instruction::SkipKeyNotPressed { x } => x_op(0xE0A1, x),
+            Instruction::SetIndexLong(_) => 0xF000,
+            Instruction::Plane(n) => x_op(0xF001, n),
+            Instruction::LoadAudio => 0xF002,
+            Instruction::GetDelay { x } => x_op(0xF007, x),
+            Instruction::WaitKey { x } => x_op(0xF00A, x),
+            Instruction::SetDelay { x } => x_op(0xF015, x),
+            Instruction::SetSound { x } => x_op(0xF018, x),
+            Instruction::AddIndex { x } => x_op(0xF01E, x),
+            Instruction::Font { x } => x_op(0xF029, x),
+            Instruction::BigFont { x } => x_op(0xF030, x),
+            Instruction::Bcd { x } => x_op(0xF033, x),
+            Instruction::Pitch { x } => x_op(0xF03A, x),
+            Instruction::Store { x } => x_op(0xF055, x),
+            Instruction::Load { x } => x_op(0xF065, x),
+            Instruction::SaveFlags { x } => x_op(0xF075, x),
+            Instruction::LoadFlags { x } => x_op(0xF085, x),
+        }
+    }
+
+    // Writes the big-endian encoding into `buf` and returns the number of bytes written.
+    // Panics if `buf` is shorter than `size()`.
+    pub fn encode_into(&self, buf: &mut [u8]) -> usize {
+        buf[..2].copy_from_slice(&self.encode().to_be_bytes());
+
+        if let Instruction::SetIndexLong(nnnn) = *self {
+            buf[2..4].copy_from_slice(&nnnn.to_be_bytes());
+        }
+
+        self.size() as usize
+    }
+
+    // Whether the instruction can move PC anywhere but the next instruction
+    pub fn is_branch(&self) -> bool {
+        matches!(
+            self,
+            Instruction::Ret
+                | Instruction::Exit
+                | Instruction::Jump(_)
+                | Instruction::Call(_)
+                | Instruction::JumpOffset { .. }
+                | Instruction::SkipEqImm { .. }
+                | Instruction::SkipNeImm { .. }
+                | Instruction::SkipEqReg { .. }
+                | Instruction::SkipNeReg { .. }
+                | Instruction::SkipKeyPressed { .. }
+                | Instruction::SkipKeyNotPressed { .. }
+        )
+    }
+
+    pub fn extension(&self) -> Extension {
+        match self {
+            Instruction::ScrollUp(_)
+            | Instruction::SaveRange { .. }
+            | Instruction::LoadRange { .. }
+            | Instruction::SetIndexLong(_)
+            | Instruction::Plane(_)
+            | Instruction::LoadAudio
+            | Instruction::Pitch { .. } => Extension::XoChip,
+            Instruction::ScrollDown(_)
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Exit
+            | Instruction::Lores
+            | Instruction::Hires
+            | Instruction::Draw { n: 0, .. }
+            | Instruction::BigFont { .. }
+            | Instruction::SaveFlags { .. }
+            | Instruction::LoadFlags { .. } => Extension::SuperChip,
+            _ => Extension::Chip8,
+        }
+    }
+
+    // Size in bytes
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::SetIndexLong(_) => 4,
+            _ => 2,
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+
+use crate::*;
+
+// Undo log of executed instructions for stepping backwards in a debugger.
+// Each entry holds the registers from before the instruction plus whatever
+// RAM or screen it overwrote. Writes to the flag storage can't be undone.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Journal {
+    entries: VecDeque<Entry>,
+    capacity: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Entry {
+    registers: Registers,
+    memory: Option<MemoryUndo>,
+    screen: Option<ScreenUndo>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Registers {
+    pc: u16,
+    run_state: RunState,
+    plane_mask: u8,
+    resolution: Resolution,
+    v_reg: [u8; NUM_V_REGS],
+    i_reg: u16,
+    stack: [u16; STACK_SIZE],
+    sp: u16,
+    dt: u8,
+    st: u8,
+    audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    audio_loaded: bool,
+    pitch: u8,
+    rng_state: u128,
+}
+
+// The largest write is FX55 with all 16 registers
+#[derive(Clone, Debug, PartialEq)]
+struct MemoryUndo {
+    addr: usize,
+    len: usize,
+    bytes: [u8; NUM_V_REGS],
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum ScreenUndo {
+    // Drawing is an XOR, so drawing the same sprite again erases it
+    Redraw {
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    },
+    Restore(Box<[[u128; HIRES_SCREEN_HEIGHT]; NUM_PLANES]>),
+}
+
+impl Journal {
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    // How many instructions `step_back` can undo, 0 (the default) disables the journal
+    pub fn set_journal_capacity(&mut self, capacity: usize) {
+        let journal = &mut self.journal;
+        journal.capacity = capacity;
+        while journal.entries.len() > capacity {
+            journal.entries.pop_front();
+        }
+    }
+
+    // Number of instructions that can currently be undone
+    pub fn journal_len(&self) -> usize {
+        self.journal.entries.len()
+    }
+
+    // Undoes the last executed instruction, false if the journal is empty
+    pub fn step_back(&mut self) -> bool {
+        let entry = match self.journal.entries.pop_back() {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        let registers = entry.registers;
+        self.pc = registers.pc;
+        self.run_state = registers.run_state;
+        self.plane_mask = registers.plane_mask;
+        self.resolution = registers.resolution;
+        self.v_reg = registers.v_reg;
+        self.i_reg = registers.i_reg;
+        self.stack = registers.stack;
+        self.sp = registers.sp;
+        self.dt = registers.dt;
+        self.st = registers.st;
+        self.audio_pattern = registers.audio_pattern;
+        self.audio_loaded = registers.audio_loaded;
+        self.pitch = registers.pitch;
+        self.playback_rate = pitch_to_rate(registers.pitch);
+        self.rng.set_state(registers.rng_state);
+
+        // Restore memory before redrawing, the sprite may have overwritten its own data
+        if let Some(memory) = entry.memory {
+            let end = memory.addr + memory.len;
+            self.ram[memory.addr..end].copy_from_slice(&memory.bytes[..memory.len]);
+        }
+
+        match entry.screen {
+            Some(ScreenUndo::Redraw {
+                x,
+                y,
+                width,
+                height,
+            }) => {
+                self.draw_sprite(x, y, width, height);
+            }
+            Some(ScreenUndo::Restore(screen)) => {
+                self.screen = *screen;
+                self.mark_screen_dirty();
+            }
+            None => {}
+        }
+
+        true
+    }
+
+    // Called before executing the instruction at `pc`
+    pub(crate) fn record_undo(&mut self, pc: u16, instruction: Instruction) {
+        if self.journal.capacity == 0 {
+            return;
+        }
+
+        let registers = Registers {
+            pc,
+            run_state: self.run_state,
+            plane_mask: self.plane_mask,
+            resolution: self.resolution,
+            v_reg: self.v_reg,
+            i_reg: self.i_reg,
+            stack: self.stack,
+            sp: self.sp,
+            dt: self.dt,
+            st: self.st,
+            audio_pattern: self.audio_pattern,
+            audio_loaded: self.audio_loaded,
+            pitch: self.pitch,
+            rng_state: self.rng.state(),
+        };
+
+        let written = match instruction {
+            Instruction::SaveRange { x, y } => Some(x.abs_diff(y) as usize + 1),
+            Instruction::Bcd { .. } => Some(3),
+            Instruction::Store { x } => Some(x as usize + 1),
+            _ => None,
+        };
+        let memory = written
+            .and_then(|len| self.ram_range(pc, self.i_reg as usize, len).ok())
+            .map(|range| {
+                let mut bytes = [0; NUM_V_REGS];
+                bytes[..range.len()].copy_from_slice(&self.ram[range.clone()]);
+                MemoryUndo {
+                    addr: range.start,
+                    len: range.len(),
+                    bytes,
+                }
+            });
+
+        let screen = match instruction {
+            Instruction::Draw { x, y, n } => {
+                let (width, height) = self.sprite_dimensions(n);
+                let len = self.sprite_data_len(width, height);
+
+                // Nothing is drawn if the sprite doesn't fit in RAM
+                self.ram_range(pc, self.i_reg as usize, len)
+                    .ok()
+                    .map(|_| ScreenUndo::Redraw {
+                        x: self.v_reg[x as usize] as usize,
+                        y: self.v_reg[y as usize] as usize,
+                        width,
+                        height,
+                    })
+            }
+            Instruction::Cls
+            | Instruction::ScrollDown(_)
+            | Instruction::ScrollUp(_)
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Lores
+            | Instruction::Hires => Some(ScreenUndo::Restore(Box::new(self.screen))),
+            _ => None,
+        };
+
+        let journal = &mut self.journal;
+        if journal.entries.len() == journal.capacity {
+            journal.entries.pop_front();
+        }
+        journal.entries.push_back(Entry {
+            registers,
+            memory,
+            screen,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    // LD V0, 0x7B / LD I, 0x300 / LD B, V0 / LD [I], V2 / LD I, 0x20E /
+    // DRW V1, V1, 5 / CLS, then the sprite
+    const PROGRAM: [u8; 19] = [
+        0x60, 0x7B, 0xA3, 0x00, 0xF0, 0x33, 0xF2, 0x55, 0xA2, 0x0E, 0xD1, 0x15, 0x00, 0xE0, 0xF0,
+        0x90, 0x90, 0x90, 0xF0,
+    ];
+
+    fn assert_same_machine(emu: &Emu, expected: &Emu) {
+        assert_eq!(emu.pc, expected.pc);
+        assert_eq!(emu.v_reg, expected.v_reg);
+        assert_eq!(emu.i_reg, expected.i_reg);
+        assert_eq!((emu.sp, emu.stack), (expected.sp, expected.stack));
+        assert_eq!(emu.ram(), expected.ram());
+        assert_eq!(emu.screen, expected.screen);
+    }
+
+    #[test]
+    fn step_back_undoes_each_instruction() {
+        let mut emu = Emu::new();
+        emu.load(&PROGRAM).unwrap();
+        emu.set_journal_capacity(16);
+
+        let mut history = Vec::new();
+        for _ in 0..7 {
+            history.push(emu.clone());
+            emu.tick().unwrap();
+        }
+        assert_eq!(emu.journal_len(), 7);
+
+        while let Some(expected) = history.pop() {
+            assert!(emu.step_back());
+            assert_same_machine(&emu, &expected);
+        }
+        assert!(!emu.step_back());
+    }
+
+    #[test]
+    fn capacity_keeps_the_latest_instructions() {
+        let mut emu = Emu::new();
+        emu.load(&PROGRAM).unwrap();
+        emu.tick_many(2).unwrap();
+        assert_eq!(emu.journal_len(), 0);
+        assert!(!emu.step_back());
+
+        emu.set_journal_capacity(3);
+        emu.tick_many(4).unwrap();
+        assert_eq!(emu.journal_len(), 3);
+
+        emu.set_journal_capacity(1);
+        assert_eq!(emu.journal_len(), 1);
+        assert!(emu.step_back());
+        assert_eq!(emu.pc, 0x20A);
+        assert!(!emu.step_back());
+    }
+
+    #[test]
+    fn reset_clears_the_journal() {
+        let mut emu = Emu::new();
+        emu.load(&PROGRAM).unwrap();
+        emu.set_journal_capacity(16);
+        emu.tick_many(3).unwrap();
+
+        emu.reset();
+        assert_eq!(emu.journal_len(), 0);
+        assert!(!emu.step_back());
+    }
+}
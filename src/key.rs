@@ -0,0 +1,78 @@
+use core::fmt;
+
+// The keys of the hex keypad, named by the digit they enter
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Key {
+    Key0,
+    Key1,
+    Key2,
+    Key3,
+    Key4,
+    Key5,
+    Key6,
+    Key7,
+    Key8,
+    Key9,
+    KeyA,
+    KeyB,
+    KeyC,
+    KeyD,
+    KeyE,
+    KeyF,
+}
+
+// A number that isn't a key, 0x10 or more
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidKey(pub u8);
+
+impl fmt::Display for InvalidKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid key {:#X}, keys are 0x0-0xF", self.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidKey {}
+
+impl Key {
+    // In keypad order 0-F
+    pub const ALL: [Key; 16] = [
+        Key::Key0,
+        Key::Key1,
+        Key::Key2,
+        Key::Key3,
+        Key::Key4,
+        Key::Key5,
+        Key::Key6,
+        Key::Key7,
+        Key::Key8,
+        Key::Key9,
+        Key::KeyA,
+        Key::KeyB,
+        Key::KeyC,
+        Key::KeyD,
+        Key::KeyE,
+        Key::KeyF,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl TryFrom<u8> for Key {
+    type Error = InvalidKey;
+
+    fn try_from(value: u8) -> Result<Key, InvalidKey> {
+        Key::ALL
+            .get(value as usize)
+            .copied()
+            .ok_or(InvalidKey(value))
+    }
+}
+
+impl From<Key> for u8 {
+    fn from(key: Key) -> u8 {
+        key as u8
+    }
+}
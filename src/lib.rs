@@ -1,16 +1,148 @@
 #![no_std]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(feature = "alloc")]
+pub mod asm;
+mod audio;
+mod clock;
+#[cfg(feature = "alloc")]
+mod debug;
+mod deflicker;
+#[cfg(feature = "diagnostics")]
+mod diagnostics;
+pub mod disasm;
+mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod font;
+#[cfg(feature = "alloc")]
+mod gif;
+pub mod hash;
+#[cfg(feature = "alloc")]
+mod input;
+mod instruction;
+#[cfg(feature = "alloc")]
+mod journal;
+#[cfg(feature = "alloc")]
+pub mod json;
+mod key;
+#[cfg(feature = "alloc")]
+pub mod lint;
+mod memory_map;
+mod metrics;
+#[cfg(feature = "alloc")]
+mod movie;
+#[cfg(feature = "alloc")]
+mod octocart;
+mod palette;
+mod phosphor;
+mod poke;
+#[cfg(feature = "alloc")]
+mod profile;
+mod quirks;
+mod random;
+#[cfg(feature = "alloc")]
+mod rewind;
+#[cfg(feature = "compression")]
+mod rle;
+#[cfg(feature = "std")]
+mod rom_file;
+#[cfg(feature = "alloc")]
+mod romdb;
+#[cfg(feature = "alloc")]
+mod snapshot;
+#[cfg(feature = "alloc")]
+mod stats;
+#[cfg(feature = "alloc")]
+pub mod symbols;
+mod text;
+#[cfg(feature = "alloc")]
+mod trace;
+#[cfg(feature = "std")]
+mod wav;
+
+pub use audio::{AudioSink, Buzzer, Waveform};
+pub use clock::Timing;
+#[cfg(feature = "alloc")]
+pub use debug::{Condition, ConditionError, WatchKind};
+pub use deflicker::{Deflicker, MAX_DEFLICKER_FRAMES};
+#[cfg(feature = "diagnostics")]
+pub use diagnostics::{Diagnostic, DiagnosticSink};
+pub use error::{EmuError, LoadError};
+pub use font::Font;
+pub use hash::RomHash;
+pub use instruction::{DecodeError, Extension, Instruction};
+pub use key::{InvalidKey, Key};
+pub use memory_map::MemoryRegion;
+pub use metrics::Metrics;
+#[cfg(feature = "alloc")]
+pub use movie::{KeyEvent, Movie, MovieError, MoviePlayer, MovieRecorder};
+#[cfg(feature = "alloc")]
+pub use octocart::{Cartridge, CartridgeError};
+pub use palette::Palette;
+pub use phosphor::Phosphor;
+pub use poke::Poke;
+pub use quirks::{LoresDxy0, ParseVariantError, Quirks, Variant};
+pub use random::{RandomSource, Xorshift};
+#[cfg(feature = "alloc")]
+pub use rewind::Rewind;
+#[cfg(feature = "std")]
+pub use rom_file::ReadRomError;
+#[cfg(feature = "alloc")]
+pub use romdb::{RomDatabase, RomDatabaseError, RomInfo};
+#[cfg(feature = "alloc")]
+pub use snapshot::StateError;
+pub use text::TextStyle;
+#[cfg(feature = "alloc")]
+pub use trace::TraceEntry;
+#[cfg(feature = "std")]
+pub use wav::AudioRecorder;
+
+use core::ops::Range;
+
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
+pub const HIRES_SCREEN_WIDTH: usize = 128;
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
+
+pub const NUM_PLANES: usize = 2; // XO-CHIP
+
+const SCREEN_SIZE: usize = HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT;
+
+pub const DEFAULT_RAM_SIZE: usize = 4096;
+pub const MAX_RAM_SIZE: usize = 65536; // XO-CHIP
+
+// With a heap RAM is allocated at ram_size(), without one it's a fixed array
+// and 64KB is too much to carry around in every machine
+#[cfg(feature = "alloc")]
+const RAM_CAPACITY: usize = MAX_RAM_SIZE;
+#[cfg(not(feature = "alloc"))]
+const RAM_CAPACITY: usize = DEFAULT_RAM_SIZE;
 
-const RAM_SIZE: usize = 4096;
 const NUM_V_REGS: usize = 16;
 const STACK_SIZE: usize = 16;
 const NUM_KEYS: usize = 16;
+pub const NUM_FLAGS: usize = 16; // SCHIP uses 8, XO-CHIP all 16
 
 const START_ADDR: u16 = 0x200;
 
-const FONTSET_SIZE: usize = 80;
+// Longest loop body in bytes checked when looking for endless loops
+const MAX_SPIN_LOOP_SIZE: u16 = 32;
+
+const AUDIO_PATTERN_SIZE: usize = 16; // XO-CHIP, 128 one-bit samples
+const AUDIO_PATTERN_BITS: f32 = (AUDIO_PATTERN_SIZE * 8) as f32;
+const AUDIO_BASE_RATE: f32 = 4000.0; // pattern bits per second at the default pitch
+const DEFAULT_PITCH: u8 = 64;
+
+const DEFAULT_SEED: u64 = 10;
+
+pub const FONTSET_SIZE: usize = 80; // 16 4x5 glyphs, 5 bytes each
+const BIG_FONTSET_ADDR: usize = FONTSET_SIZE;
+pub const BIG_FONTSET_SIZE: usize = 160; // 16 8x10 glyphs, 10 bytes each
 
 const FONTSET: [u8; FONTSET_SIZE] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -31,220 +163,1323 @@ const FONTSET: [u8; FONTSET_SIZE] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
-pub struct Emu {
+// SCHIP 8x10 digits, A-F as in Octo
+const BIG_FONTSET: [u8; BIG_FONTSET_SIZE] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    Low,  // 64x32
+    High, // 128x64, SUPER-CHIP
+}
+
+impl Resolution {
+    pub fn width(self) -> usize {
+        match self {
+            Resolution::Low => SCREEN_WIDTH,
+            Resolution::High => HIRES_SCREEN_WIDTH,
+        }
+    }
+
+    pub fn height(self) -> usize {
+        match self {
+            Resolution::Low => SCREEN_HEIGHT,
+            Resolution::High => HIRES_SCREEN_HEIGHT,
+        }
+    }
+}
+
+// Area of the screen in pixels of the current resolution
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    fn screen(resolution: Resolution) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width: resolution.width(),
+            height: resolution.height(),
+        }
+    }
+
+    fn union(self, other: Rect) -> Self {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Self {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunState {
+    Running,
+    WaitingForVblank, // DXYN with the display wait quirk, released by vblank()
+    Halted,           // program executed EXIT (00FD)
+    // Stuck in a loop with no way out, e.g. a jump to itself. Ticking still
+    // runs the loop, so the display keeps updating if it draws.
+    Spinning,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SoundEdge {
+    Started,
+    Stopped,
+}
+
+// What a single tick() did
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TickOutcome {
+    pub run_state: RunState,
+    pub opcode: Option<u16>, // None if nothing ran, e.g. while waiting for vblank
+    pub screen_changed: bool,
+    pub waiting_for_key: bool, // FX0A ran with no key down and will run again
+    pub sound_changed: bool,   // the sound started or stopped
+    pub stopped: Option<Stopped>,
+}
+
+// Why a debugger stopped execution, see add_breakpoint()
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stopped {
+    Breakpoint(u16),      // PC hit a breakpoint, nothing ran
+    Watchpoint(WatchHit), // after the instruction that touched watched RAM
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+// The first watched address an instruction touched
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchHit {
+    pub pc: u16,
+    pub opcode: u16,
+    pub addr: usize,
+    pub access: Access,
+    pub old: u8,
+    pub new: u8,
+}
+
+// Summary of a run_frame() call
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameResult {
+    pub run_state: RunState,
+    pub frames: u32,       // frames emulated, 1 at normal speed
+    pub instructions: u32, // instructions retired
+    pub screen_changed: bool,
+    pub sound_active: bool,       // after the timers ticked
+    pub stopped: Option<Stopped>, // the frame was cut short and timers didn't tick
+}
+
+// Backing store for the RPL user flags (FX75/FX85), e.g. a save file
+pub trait FlagStorage {
+    fn load(&mut self, flags: &mut [u8; NUM_FLAGS]);
+    fn save(&mut self, flags: &[u8; NUM_FLAGS]);
+}
+
+// Keeps the flags for the lifetime of the storage only
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryFlagStorage {
+    flags: [u8; NUM_FLAGS],
+}
+
+impl FlagStorage for MemoryFlagStorage {
+    fn load(&mut self, flags: &mut [u8; NUM_FLAGS]) {
+        *flags = self.flags;
+    }
+
+    fn save(&mut self, flags: &[u8; NUM_FLAGS]) {
+        self.flags = *flags;
+    }
+}
+
+// Cloning snapshots the whole machine, comparing two machines compares every
+// register, RAM byte and pixel
+#[derive(Clone, Debug, PartialEq)]
+pub struct Emu<F: FlagStorage = MemoryFlagStorage, R: RandomSource = Xorshift> {
     pc: u16, // program counter
-    ram: [u8; RAM_SIZE],
-    screen: [bool; SCREEN_WIDTH * SCREEN_HEIGHT],
+    run_state: RunState,
+    #[cfg(feature = "alloc")]
+    ram: alloc::vec::Vec<u8>, // ram_size bytes
+    #[cfg(not(feature = "alloc"))]
+    ram: [u8; RAM_CAPACITY],
+    ram_size: usize,    // addresses wrap at this size
+    program_end: usize, // end of the last ROM loaded, for memory_map()
+    rom_hash: Option<RomHash>,
+    font: [u8; FONTSET_SIZE], // copied into RAM on reset
+    big_font: [u8; BIG_FONTSET_SIZE],
+    // One row per word, column X at bit `width - 1 - X`
+    screen: [[u128; HIRES_SCREEN_HEIGHT]; NUM_PLANES],
+    dirty: Option<Rect>, // changed since the last take_display_dirty()
+    plane_mask: u8,      // bit N selects plane N for draw/clear/scroll
+    resolution: Resolution,
+    quirks: Quirks,
+    permissive: bool, // skip opcodes that don't decode instead of failing
     v_reg: [u8; NUM_V_REGS],
     i_reg: u16, // index register
     stack: [u16; STACK_SIZE],
-    sp: u16, // stack pointer
-    dt: u8,  // delay timer
-    st: u8,  // sound timer
+    sp: u16,        // stack pointer
+    dt: u8,         // delay timer
+    st: u8,         // sound timer
+    sounding: bool, // ST was nonzero during the last frame, for tick_timers()
+    #[cfg(feature = "alloc")]
+    audio_sink: audio::SinkSlot<dyn AudioSink + Send>,
+    buzzer: Buzzer,
+    audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    audio_loaded: bool,  // F002 ran since reset, the buzzer plays until then
+    audio_position: f32, // current bit within the pattern
+    pitch: u8,
+    playback_rate: f32, // derived from pitch
     keys: [bool; NUM_KEYS],
+    keys_polled: u16, // by EX9E/EXA1, bit N for key N
+    waited_for_key: bool,
+    #[cfg(feature = "alloc")]
+    key_queue: input::KeyQueue,
+    #[cfg(feature = "diagnostics")]
+    diagnostic_sink: audio::SinkSlot<dyn DiagnosticSink + Send>,
+    seed: Option<u64>, // restarts `rng` on reset
+    rng: R,            // CXNN
+    flag_storage: F,
+    clock: clock::Clock, // advance()
+    metrics: Metrics,
+    #[cfg(feature = "alloc")]
+    debugger: debug::Debugger,
+    #[cfg(feature = "alloc")]
+    journal: journal::Journal,
+    #[cfg(feature = "alloc")]
+    tracer: trace::Tracer,
+    #[cfg(feature = "alloc")]
+    stats: stats::Stats,
+    #[cfg(feature = "alloc")]
+    profiler: profile::Profiler,
+}
+
+impl Default for Emu {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Emu {
     pub fn new() -> Self {
+        Self::with_flag_storage(MemoryFlagStorage::default())
+    }
+
+    pub fn with_variant(variant: Variant) -> Self {
+        let mut emu = Self::new();
+        emu.set_variant(variant);
+        emu
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        let mut emu = Self::new();
+        emu.reseed(seed);
+        emu
+    }
+}
+
+impl<F: FlagStorage> Emu<F> {
+    pub fn with_flag_storage(flag_storage: F) -> Self {
+        let mut emu = Self::with_flag_storage_and_rng(flag_storage, Xorshift::new(DEFAULT_SEED));
+        emu.seed = Some(DEFAULT_SEED);
+        emu
+    }
+}
+
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    // `rng` is used as is, reset() only restarts it once a seed is set with reseed()
+    pub fn with_flag_storage_and_rng(flag_storage: F, rng: R) -> Self {
         let mut emu = Self {
             pc: START_ADDR,
-            ram: [0; RAM_SIZE],
-            screen: [false; SCREEN_WIDTH * SCREEN_HEIGHT],
+            run_state: RunState::Running,
+            #[cfg(feature = "alloc")]
+            ram: alloc::vec![0; DEFAULT_RAM_SIZE],
+            #[cfg(not(feature = "alloc"))]
+            ram: [0; RAM_CAPACITY],
+            ram_size: DEFAULT_RAM_SIZE,
+            program_end: START_ADDR as usize,
+            rom_hash: None,
+            font: FONTSET,
+            big_font: BIG_FONTSET,
+            screen: [[0; HIRES_SCREEN_HEIGHT]; NUM_PLANES],
+            dirty: Some(Rect::screen(Resolution::Low)),
+            plane_mask: 1,
+            resolution: Resolution::Low,
+            quirks: Quirks::default(),
+            permissive: false,
             v_reg: [0; NUM_V_REGS],
             i_reg: 0,
             stack: [0; STACK_SIZE],
             sp: 0,
             dt: 0,
             st: 0,
+            sounding: false,
+            #[cfg(feature = "alloc")]
+            audio_sink: audio::SinkSlot::default(),
+            buzzer: Buzzer::default(),
+            audio_pattern: [0; AUDIO_PATTERN_SIZE],
+            audio_loaded: false,
+            audio_position: 0.0,
+            pitch: DEFAULT_PITCH,
+            playback_rate: AUDIO_BASE_RATE,
             keys: [false; NUM_KEYS],
+            keys_polled: 0,
+            waited_for_key: false,
+            #[cfg(feature = "alloc")]
+            key_queue: input::KeyQueue::default(),
+            #[cfg(feature = "diagnostics")]
+            diagnostic_sink: audio::SinkSlot::default(),
+            seed: None,
+            rng,
+            flag_storage,
+            clock: clock::Clock::default(),
+            metrics: Metrics::default(),
+            #[cfg(feature = "alloc")]
+            debugger: debug::Debugger::default(),
+            #[cfg(feature = "alloc")]
+            journal: journal::Journal::default(),
+            #[cfg(feature = "alloc")]
+            tracer: trace::Tracer::default(),
+            #[cfg(feature = "alloc")]
+            stats: stats::Stats::default(),
+            #[cfg(feature = "alloc")]
+            profiler: profile::Profiler::default(),
         };
 
-        emu.ram[..FONTSET_SIZE].copy_from_slice(&FONTSET);
-
+        emu.install_fonts();
         emu
     }
 
     pub fn reset(&mut self) {
         self.pc = START_ADDR;
-        self.ram = [0; RAM_SIZE];
-        self.screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
+        self.run_state = RunState::Running;
+        self.ram.fill(0);
+        self.program_end = START_ADDR as usize;
+        self.rom_hash = None;
+        self.screen = [[0; HIRES_SCREEN_HEIGHT]; NUM_PLANES];
+        self.plane_mask = 1;
+        self.resolution = Resolution::Low;
+        self.mark_screen_dirty();
         self.v_reg = [0; NUM_V_REGS];
         self.i_reg = 0;
         self.stack = [0; STACK_SIZE];
         self.sp = 0;
         self.dt = 0;
         self.st = 0;
+        if self.sounding {
+            self.sounding = false;
+            #[cfg(feature = "alloc")]
+            self.notify_audio_sink(SoundEdge::Stopped);
+        }
+        self.audio_pattern = [0; AUDIO_PATTERN_SIZE];
+        self.audio_loaded = false;
+        self.audio_position = 0.0;
+        self.pitch = DEFAULT_PITCH;
+        self.playback_rate = AUDIO_BASE_RATE;
         self.keys = [false; NUM_KEYS];
+        self.keys_polled = 0;
+        self.waited_for_key = false;
+        #[cfg(feature = "alloc")]
+        self.key_queue.clear();
+        if let Some(seed) = self.seed {
+            self.rng.reseed(seed);
+        }
+        self.clock.clear();
+        self.metrics = Metrics::default();
+        #[cfg(feature = "alloc")]
+        self.debugger.clear_resume();
+        #[cfg(feature = "alloc")]
+        self.journal.clear();
+
+        self.install_fonts();
+    }
+
+    // On error PC is left past the faulting instruction
+    pub fn tick(&mut self) -> Result<TickOutcome, EmuError> {
+        #[cfg(feature = "alloc")]
+        self.apply_key_events();
 
-        self.ram[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+        if let RunState::WaitingForVblank | RunState::Halted = self.run_state {
+            return Ok(self.idle_outcome(None));
+        }
+
+        #[cfg(feature = "alloc")]
+        if let Some(stopped) = self.check_breakpoint() {
+            return Ok(self.idle_outcome(Some(stopped)));
+        }
+
+        // Fetch & decode
+        let pc = self.pc;
+        let instruction = match self.fetch_instruction() {
+            Ok(instruction) => instruction,
+            // Skipped like a no-op, PC is already past it
+            Err(EmuError::UnknownOpcode { op, .. }) if self.permissive => {
+                #[cfg(feature = "diagnostics")]
+                self.warn(Diagnostic::UnknownOpcode { pc, op });
+                return Ok(TickOutcome {
+                    opcode: Some(op),
+                    ..self.idle_outcome(None)
+                });
+            }
+            Err(error) => return Err(error),
+        };
+        let sound_active = self.is_sound_active();
+        #[cfg(feature = "alloc")]
+        let watched = self.check_watchpoints(pc, instruction);
+        #[cfg(feature = "diagnostics")]
+        let font_write = self.check_font_write(pc, instruction);
+
+        // Execute
+        self.execute_at(pc, instruction)?;
+        #[cfg(feature = "diagnostics")]
+        self.diagnose(pc, instruction, font_write);
+
+        #[cfg(feature = "alloc")]
+        let stopped = watched.map(|mut hit| {
+            hit.new = self.ram[hit.addr];
+            Stopped::Watchpoint(hit)
+        });
+        #[cfg(not(feature = "alloc"))]
+        let stopped = None;
+
+        Ok(TickOutcome {
+            run_state: self.run_state,
+            opcode: Some(instruction.encode()),
+            screen_changed: matches!(
+                instruction,
+                Instruction::Cls
+                    | Instruction::ScrollDown(_)
+                    | Instruction::ScrollUp(_)
+                    | Instruction::ScrollRight
+                    | Instruction::ScrollLeft
+                    | Instruction::Lores
+                    | Instruction::Hires
+                    | Instruction::Draw { .. }
+            ),
+            waiting_for_key: matches!(instruction, Instruction::WaitKey { .. }) && self.pc == pc,
+            sound_changed: self.is_sound_active() != sound_active,
+            stopped,
+        })
+    }
+
+    fn idle_outcome(&self, stopped: Option<Stopped>) -> TickOutcome {
+        TickOutcome {
+            run_state: self.run_state,
+            opcode: None,
+            screen_changed: false,
+            waiting_for_key: false,
+            sound_changed: false,
+            stopped,
+        }
     }
 
-    pub fn tick(&mut self) {
-        // Fetch
-        let op = self.fetch();
+    // Runs up to `n` instructions in one go, stopping early once the machine
+    // halts, blocks on vblank or a key, or a debugger stop hits. Returns how many ran.
+    pub fn tick_many(&mut self, n: u32) -> Result<u32, EmuError> {
+        let mut count = 0;
 
-        // Decode & execute
-        self.execute(op);
+        while count < n {
+            let outcome = self.tick()?;
+            if outcome.opcode.is_none() {
+                break;
+            }
+
+            count += 1;
+            if outcome.waiting_for_key
+                || outcome.stopped.is_some()
+                || matches!(
+                    outcome.run_state,
+                    RunState::WaitingForVblank | RunState::Halted
+                )
+            {
+                break;
+            }
+        }
+
+        Ok(count)
     }
 
-    // Called once per frame
-    pub fn tick_timers(&mut self) {
+    // Ticks until `predicate` holds, checking it before the first tick and after
+    // each one. Gives up after `max_cycles` ticks, once the machine halts or
+    // when a debugger stop hits.
+    // Timers don't run, so a DXYN waiting for vblank stays blocked.
+    pub fn run_until(
+        &mut self,
+        max_cycles: u32,
+        mut predicate: impl FnMut(&Self) -> bool,
+    ) -> Result<bool, EmuError> {
+        for _ in 0..max_cycles {
+            if predicate(self) {
+                return Ok(true);
+            }
+            let outcome = self.tick()?;
+            if outcome.stopped.is_some() || outcome.run_state == RunState::Halted {
+                break;
+            }
+        }
+
+        Ok(predicate(self))
+    }
+
+    // Called once per frame, also signals the vertical blank. Returns when
+    // the sound turned on or off, comparing the frame that just ended with
+    // the one before so sounds shorter than a frame still start and stop.
+    pub fn tick_timers(&mut self) -> Option<SoundEdge> {
+        self.vblank();
+        self.metrics.frames += 1;
+        #[cfg(feature = "alloc")]
+        self.record_profile_frame();
+        #[cfg(feature = "alloc")]
+        self.key_queue.end_frame();
+
         if self.dt > 0 {
             self.dt -= 1;
         }
 
-        if self.st > 0 {
-            if self.st == 1 {
-                // beep
+        let sounded = self.st > 0;
+        self.st = self.st.saturating_sub(1);
+        let edge = match (self.sounding, sounded) {
+            (false, true) => Some(SoundEdge::Started),
+            (true, false) => Some(SoundEdge::Stopped),
+            _ => None,
+        };
+        self.sounding = sounded;
+        #[cfg(feature = "alloc")]
+        if let Some(edge) = edge {
+            self.notify_audio_sink(edge);
+        }
+        edge
+    }
+
+    // Fills `samples` with sound while the sound timer is active, silence
+    // otherwise: the XO-CHIP audio pattern once F002 has loaded one, the
+    // buzzer tone before that, see set_buzzer(). Playback continues where the previous call stopped.
+    pub fn render_audio(&mut self, samples: &mut [f32], sample_rate: u32) {
+        // A zero sample rate has no samples to play the sound in
+        if self.st == 0 || sample_rate == 0 {
+            samples.fill(0.0);
+            return;
+        }
+
+        // The buzzer plays one period of its waveform per pattern length
+        let buzzer = !self.audio_loaded;
+        let step = match buzzer {
+            true => self.buzzer.frequency * AUDIO_PATTERN_BITS / sample_rate as f32,
+            false => self.playback_rate / sample_rate as f32,
+        };
+
+        for sample in samples.iter_mut() {
+            let bit = self.audio_position as usize;
+            let value = match buzzer {
+                true => self
+                    .buzzer
+                    .waveform
+                    .sample(self.audio_position / AUDIO_PATTERN_BITS),
+                false if self.audio_pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 => 1.0,
+                false => -1.0,
+            };
+            *sample = value * self.buzzer.volume;
+
+            self.audio_position += step;
+            while self.audio_position >= AUDIO_PATTERN_BITS {
+                self.audio_position -= AUDIO_PATTERN_BITS;
             }
-            self.st -= 1;
         }
     }
 
-    pub fn get_display(&self) -> &[bool] {
-        &self.screen
+    pub fn is_sound_active(&self) -> bool {
+        self.st > 0
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    // Audio pattern bits per second, 4000 * 2^((pitch - 64) / 48)
+    pub fn playback_rate(&self) -> f32 {
+        self.playback_rate
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn i_reg(&self) -> u16 {
+        self.i_reg
+    }
+
+    pub fn v_reg(&self) -> &[u8; NUM_V_REGS] {
+        &self.v_reg
+    }
+
+    pub fn dt(&self) -> u8 {
+        self.dt
+    }
+
+    pub fn st(&self) -> u8 {
+        self.st
+    }
+
+    // All entries including stale ones above SP, see call_stack()
+    pub fn stack(&self) -> &[u16; STACK_SIZE] {
+        &self.stack
+    }
+
+    // The addressable RAM, ram_size() bytes
+    pub fn ram(&self) -> &[u8] {
+        &self.ram[..self.ram_size]
+    }
+
+    // Return addresses pushed by CALL, the innermost last
+    pub fn call_stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+
+    // Address of the CALL for each call_stack() entry
+    pub fn call_sites(&self) -> impl Iterator<Item = u16> + '_ {
+        self.call_stack().iter().map(|addr| addr.wrapping_sub(2))
+    }
+
+    pub fn sp(&self) -> u16 {
+        self.sp
+    }
+
+    pub fn run_state(&self) -> RunState {
+        self.run_state
+    }
+
+    // Releases a DXYN waiting for the display; tick_timers() does this already
+    pub fn vblank(&mut self) {
+        if self.run_state == RunState::WaitingForVblank {
+            self.run_state = RunState::Running;
+        }
+    }
+
+    // One bool per pixel, row by row, rows are `resolution().width()` pixels
+    // wide. Unpacked from get_display_packed() as it goes.
+    pub fn get_display(&self) -> impl Iterator<Item = bool> + '_ {
+        self.get_plane_display(0)
+    }
+
+    pub fn get_plane_display(&self, plane: usize) -> impl Iterator<Item = bool> + '_ {
+        let width = self.resolution.width();
+        self.get_plane_display_packed(plane)
+            .iter()
+            .flat_map(move |&row| (0..width).rev().map(move |bit| (row >> bit) & 1 != 0))
+    }
+
+    // One word per row with the leftmost pixel in bit `resolution().width() - 1`,
+    // so lores rows fit in a u64 and `to_be_bytes` gives MSB-first 1bpp data
+    pub fn get_display_packed(&self) -> &[u128] {
+        self.get_plane_display_packed(0)
+    }
+
+    pub fn get_plane_display_packed(&self, plane: usize) -> &[u128] {
+        &self.screen[plane][..self.resolution.height()]
+    }
+
+    // 1 bit per pixel into `buffer`, rows of `resolution().width() / 8`
+    // bytes with the leftmost pixel in the MSB of the first, as most
+    // graphics APIs take monochrome bitmaps. Stops at the end of `buffer`.
+    pub fn render_bits(&self, plane: usize, buffer: &mut [u8]) {
+        let row_bytes = self.resolution.width() / 8;
+        let rows = buffer.chunks_exact_mut(row_bytes);
+        for (bytes, row) in rows.zip(self.get_plane_display_packed(plane)) {
+            bytes.copy_from_slice(&row.to_be_bytes()[16 - row_bytes..]);
+        }
+    }
+
+    // One 2-bit color index per pixel, bit N set if plane N is lit
+    pub fn get_display_colors(&self) -> impl Iterator<Item = u8> + '_ {
+        self.get_plane_display(0)
+            .zip(self.get_plane_display(1))
+            .map(|(lit0, lit1)| (lit0 as u8) | ((lit1 as u8) << 1))
+    }
+
+    // Bounding box of everything drawn, cleared or scrolled since the last
+    // call, None if the screen hasn't changed
+    pub fn take_display_dirty(&mut self) -> Option<Rect> {
+        self.dirty.take()
+    }
+
+    pub fn plane_mask(&self) -> u8 {
+        self.plane_mask
+    }
+
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    // Applies the variant's quirks and memory size
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.quirks = variant.quirks();
+        self.set_ram_size(variant.ram_size());
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    // Restarts the CXNN random sequence, reset() also restarts it from this seed
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+        self.rng.reseed(seed);
+    }
+
+    pub fn rng(&self) -> &R {
+        &self.rng
     }
 
-    pub fn keypress(&mut self, index: usize, pressed: bool) {
-        self.keys[index] = pressed;
+    pub fn rng_mut(&mut self) -> &mut R {
+        &mut self.rng
     }
 
-    pub fn load(&mut self, data: &[u8]) {
-        let start = START_ADDR as usize;
-        let end = start + data.len();
-        self.ram[start..end].copy_from_slice(data);
+    pub fn flag_storage(&self) -> &F {
+        &self.flag_storage
     }
 
-    fn push(&mut self, value: u16) {
-        self.stack[self.sp as usize] = value;
+    pub fn flag_storage_mut(&mut self) -> &mut F {
+        &mut self.flag_storage
+    }
+
+    pub fn keypress(&mut self, key: Key, pressed: bool) {
+        self.keys[key.index()] = pressed;
+    }
+
+    // Indexed by Key::index()
+    pub fn keys(&self) -> &[bool; NUM_KEYS] {
+        &self.keys
+    }
+
+    // Keys the program checked with EX9E/EXA1 since the last reset, the
+    // controls a game uses unless it also waits with FX0A
+    pub fn polled_keys(&self) -> impl Iterator<Item = Key> + '_ {
+        Key::ALL
+            .into_iter()
+            .filter(|key| self.keys_polled & (1 << key.index()) != 0)
+    }
+
+    // Whether the program waited for any key with FX0A since the last reset
+    pub fn has_waited_for_key(&self) -> bool {
+        self.waited_for_key
+    }
+
+    // Whether the program is stuck on an FX0A until a key goes down, for
+    // frontends to prompt for input
+    pub fn is_waiting_for_key(&self) -> bool {
+        let op = self.peek(self.pc).map(Instruction::decode);
+        self.run_state == RunState::Running
+            && matches!(op, Some(Ok(Instruction::WaitKey { .. })))
+            && !self.keys.contains(&true)
+    }
+
+    pub fn load(&mut self, data: &[u8]) -> Result<(), LoadError> {
+        self.load_at(START_ADDR, data)?;
+        self.program_end = START_ADDR as usize + data.len();
+        self.rom_hash = Some(RomHash::of(data));
+        Ok(())
+    }
+
+    // Places data anywhere in RAM, like fonts, fixtures or overlays. Unlike
+    // load() it doesn't count as the program, programs with another entry
+    // point can be started with poke().set_pc() after this.
+    pub fn load_at(&mut self, addr: u16, data: &[u8]) -> Result<(), LoadError> {
+        let start = addr as usize;
+        let capacity = self.ram_size.saturating_sub(start);
+        if data.is_empty() {
+            return Err(LoadError::Empty);
+        }
+        if data.len() > capacity {
+            return Err(LoadError::TooLarge {
+                size: data.len(),
+                capacity,
+            });
+        }
+
+        self.ram[start..start + data.len()].copy_from_slice(data);
+        #[cfg(feature = "alloc")]
+        self.journal.clear();
+        Ok(())
+    }
+
+    // Replaces the 4x5 digits FX29 points at, here and after every reset
+    pub fn load_fontset(&mut self, font: &[u8; FONTSET_SIZE]) {
+        self.font = *font;
+        self.install_fonts();
+    }
+
+    // Replaces the 8x10 digits FX30 points at, here and after every reset
+    pub fn load_big_fontset(&mut self, font: &[u8; BIG_FONTSET_SIZE]) {
+        self.big_font = *font;
+        self.install_fonts();
+    }
+
+    fn install_fonts(&mut self) {
+        self.ram[..FONTSET_SIZE].copy_from_slice(&self.font);
+        self.ram[BIG_FONTSET_ADDR..BIG_FONTSET_ADDR + BIG_FONTSET_SIZE]
+            .copy_from_slice(&self.big_font);
+        #[cfg(feature = "alloc")]
+        self.journal.clear();
+    }
+
+    // Of the last ROM loaded, to look it up in a RomDatabase
+    pub fn rom_hash(&self) -> Option<RomHash> {
+        self.rom_hash
+    }
+
+    pub fn ram_size(&self) -> usize {
+        self.ram_size
+    }
+
+    // Clamped to DEFAULT_RAM_SIZE..=MAX_RAM_SIZE, XO-CHIP programs may use the
+    // full 64KB. Without the `alloc` feature RAM stays at DEFAULT_RAM_SIZE.
+    pub fn set_ram_size(&mut self, size: usize) {
+        self.ram_size = size.clamp(DEFAULT_RAM_SIZE, RAM_CAPACITY);
+        #[cfg(feature = "alloc")]
+        self.ram.resize(self.ram_size, 0);
+    }
+
+    pub fn is_permissive(&self) -> bool {
+        self.permissive
+    }
+
+    // Permissive machines skip opcodes that don't decode instead of failing
+    // with EmuError::UnknownOpcode
+    pub fn set_permissive(&mut self, permissive: bool) {
+        self.permissive = permissive;
+    }
+
+    // None if the stack is full
+    fn push(&mut self, value: u16) -> Option<()> {
+        *self.stack.get_mut(self.sp as usize)? = value;
         self.sp += 1;
+        Some(())
+    }
+
+    // None if the stack is empty
+    fn pop(&mut self) -> Option<u16> {
+        self.sp = self.sp.checked_sub(1)?;
+        Some(self.stack[self.sp as usize])
+    }
+
+    // Switching modes clears the screen since the row stride changes
+    fn set_resolution(&mut self, resolution: Resolution) {
+        self.resolution = resolution;
+        self.screen = [[0; HIRES_SCREEN_HEIGHT]; NUM_PLANES];
+        self.mark_screen_dirty();
+    }
+
+    fn is_plane_selected(&self, plane: usize) -> bool {
+        self.plane_mask & (1 << plane) != 0
     }
 
-    fn pop(&mut self) -> u16 {
-        self.sp -= 1;
-        self.stack[self.sp as usize]
+    fn clear_screen(&mut self) {
+        for plane in 0..NUM_PLANES {
+            if self.is_plane_selected(plane) {
+                self.screen[plane] = [0; HIRES_SCREEN_HEIGHT];
+            }
+        }
+        self.mark_screen_dirty();
     }
 
-    fn fetch(&mut self) -> u16 {
-        let higher_byte = self.ram[self.pc as usize] as u16;
-        let lower_byte = self.ram[(self.pc + 1) as usize] as u16;
+    fn mark_dirty(&mut self, rect: Rect) {
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => dirty.union(rect),
+            None => rect,
+        });
+    }
 
-        self.pc += 2;
+    fn mark_screen_dirty(&mut self) {
+        self.mark_dirty(Rect::screen(self.resolution));
+    }
+
+    // Bits of a row that are on screen in the current resolution
+    fn row_mask(&self) -> u128 {
+        u128::MAX >> (HIRES_SCREEN_WIDTH - self.resolution.width())
+    }
+
+    // Scrolls operate on the active resolution and the selected planes;
+    // pixels shifted off the edge are lost
+    fn scroll_down(&mut self, n: usize) {
+        let height = self.resolution.height();
+        let n = n.min(height);
+
+        for plane in 0..NUM_PLANES {
+            if !self.is_plane_selected(plane) {
+                continue;
+            }
+
+            let rows = &mut self.screen[plane];
+            rows.copy_within(0..height - n, n);
+            rows[..n].fill(0);
+            self.mark_screen_dirty();
+        }
+    }
+
+    fn scroll_up(&mut self, n: usize) {
+        let height = self.resolution.height();
+        let n = n.min(height);
+
+        for plane in 0..NUM_PLANES {
+            if !self.is_plane_selected(plane) {
+                continue;
+            }
+
+            let rows = &mut self.screen[plane];
+            rows.copy_within(n..height, 0);
+            rows[height - n..height].fill(0);
+            self.mark_screen_dirty();
+        }
+    }
+
+    fn scroll_right(&mut self, n: usize) {
+        let height = self.resolution.height();
+
+        for plane in 0..NUM_PLANES {
+            if !self.is_plane_selected(plane) {
+                continue;
+            }
+
+            for row in &mut self.screen[plane][..height] {
+                *row >>= n;
+            }
+            self.mark_screen_dirty();
+        }
+    }
+
+    fn scroll_left(&mut self, n: usize) {
+        let height = self.resolution.height();
+        let row_mask = self.row_mask();
+
+        for plane in 0..NUM_PLANES {
+            if !self.is_plane_selected(plane) {
+                continue;
+            }
+
+            for row in &mut self.screen[plane][..height] {
+                *row = (*row << n) & row_mask;
+            }
+            self.mark_screen_dirty();
+        }
+    }
+
+    // Width and height in pixels drawn by DXYN
+    fn sprite_dimensions(&self, n: u8) -> (usize, usize) {
+        match n {
+            0 if self.resolution == Resolution::High => (16, 16),
+            0 => match self.quirks.lores_dxy0 {
+                LoresDxy0::Sprite16x16 => (16, 16),
+                LoresDxy0::Sprite8x16 => (8, 16),
+            },
+            _ => (8, n as usize),
+        }
+    }
+
+    // Every selected plane reads its own copy of the sprite data
+    fn sprite_data_len(&self, sprite_width: usize, sprite_height: usize) -> usize {
+        let num_planes = self.plane_mask.count_ones() as usize;
+        num_planes * sprite_height * sprite_width / 8
+    }
+
+    // XORs a sprite onto each selected plane, returns the number of rows that erased a pixel.
+    // With several planes selected, the sprite data for each plane follows the previous one.
+    fn draw_sprite(
+        &mut self,
+        x: usize,
+        y: usize,
+        sprite_width: usize,
+        sprite_height: usize,
+    ) -> usize {
+        let width = self.resolution.width();
+        let height = self.resolution.height();
+        let bytes_per_row = sprite_width / 8;
+        // The starting position always wraps, only the sprite's pixels may be clipped
+        let (x, y) = (x % width, y % height);
+        let clip = self.quirks.clip_sprites;
+        let row_mask = self.row_mask();
+        let mut sprite_addr = self.i_reg as usize;
+        let mut collided_rows = 0;
+
+        for plane in 0..NUM_PLANES {
+            if !self.is_plane_selected(plane) {
+                continue;
+            }
+
+            let mut plane_collided_rows = 0;
+
+            for delta_y in 0..sprite_height {
+                let y = y + delta_y;
+                if clip && y >= height {
+                    break;
+                }
+                let y = y % height;
+
+                // Line the sprite row up with column 0, then move it to column X
+                let row_addr = sprite_addr + delta_y * bytes_per_row;
+                let sprite_row = self.ram[row_addr..row_addr + bytes_per_row]
+                    .iter()
+                    .fold(0u128, |bits, &byte| (bits << 8) | byte as u128);
+                let aligned = sprite_row << (width - sprite_width);
+                let pixels = if clip || x == 0 {
+                    aligned >> x
+                } else {
+                    ((aligned >> x) | (aligned << (width - x))) & row_mask
+                };
+
+                let row = &mut self.screen[plane][y];
+                if *row & pixels != 0 {
+                    plane_collided_rows += 1;
+                }
+                *row ^= pixels;
+
+                if pixels != 0 {
+                    // Columns of the highest and lowest set bits
+                    let left = pixels.leading_zeros() as usize - (HIRES_SCREEN_WIDTH - width);
+                    let right = width - 1 - pixels.trailing_zeros() as usize;
+                    self.mark_dirty(Rect {
+                        x: left,
+                        y,
+                        width: right - left + 1,
+                        height: 1,
+                    });
+                }
+            }
+
+            collided_rows = collided_rows.max(plane_collided_rows);
+            sprite_addr += sprite_height * bytes_per_row;
+        }
+
+        collided_rows
+    }
+
+    // F000 NNNN is 4 bytes long, so skipping over it moves PC past both words
+    fn skip_next(&mut self) {
+        let is_long = self.peek(self.pc).is_some_and(Instruction::is_long);
+        let width = if is_long { 4 } else { 2 };
+        self.pc = self.pc.wrapping_add(width);
+    }
+
+    // Whether jumping back from `jump_addr` to `target` repeats a short run of
+    // straight-line code forever, since nothing in it can branch out. Waiting
+    // for a key counts as a way out, the program is idle rather than stuck.
+    fn is_endless_loop(&self, target: u16, jump_addr: u16) -> bool {
+        if target > jump_addr || jump_addr - target > MAX_SPIN_LOOP_SIZE {
+            return false;
+        }
+
+        let mut addr = target;
+        while addr < jump_addr {
+            let instruction = match self.peek(addr).map(Instruction::decode) {
+                Some(Ok(instruction)) => instruction,
+                Some(Err(DecodeError::MissingOperand(op))) => match self.peek(addr.wrapping_add(2))
+                {
+                    Some(operand) => match Instruction::decode_long(op, operand) {
+                        Ok(instruction) => instruction,
+                        Err(_) => return false,
+                    },
+                    None => return false,
+                },
+                _ => return false,
+            };
+
+            if instruction.is_branch() || matches!(instruction, Instruction::WaitKey { .. }) {
+                return false;
+            }
+            addr = match addr.checked_add(instruction.size()) {
+                Some(next) => next,
+                None => return false,
+            };
+        }
+
+        addr == jump_addr
+    }
+
+    // The `len` bytes at `addr`, or the first address past the end of RAM.
+    // `pc` is the address of the instruction making the access, for errors.
+    fn ram_range(&self, pc: u16, addr: usize, len: usize) -> Result<Range<usize>, EmuError> {
+        let end = addr + len;
+        if end > self.ram_size {
+            let addr = addr.max(self.ram_size);
+            return Err(EmuError::MemoryOutOfBounds { pc, addr });
+        }
+
+        Ok(addr..end)
+    }
+
+    fn peek(&self, addr: u16) -> Option<u16> {
+        let bytes = self.ram[..self.ram_size].get(addr as usize..addr as usize + 2)?;
 
         // Big Endian
-        (higher_byte << 8) | lower_byte
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
     }
 
-    fn execute(&mut self, op: u16) {
-        let digit1 = (op & 0xF000) >> 12;
-        let digit2 = (op & 0x0F00) >> 8;
-        let digit3 = (op & 0x00F0) >> 4;
-        let digit4 = op & 0x000F;
+    fn fetch_instruction(&mut self) -> Result<Instruction, EmuError> {
+        let pc = self.pc;
+        let op = self.fetch(pc)?;
 
-        match (digit1, digit2, digit3, digit4) {
+        let decoded = if Instruction::is_long(op) {
+            let operand = self.fetch(pc)?;
+            Instruction::decode_long(op, operand)
+        } else {
+            Instruction::decode(op)
+        };
+
+        decoded.map_err(|_| EmuError::UnknownOpcode { pc, op })
+    }
+
+    // `pc` is the start of the instruction being fetched, for errors
+    fn fetch(&mut self, pc: u16) -> Result<u16, EmuError> {
+        let range = self.ram_range(pc, self.pc as usize, 2)?;
+        let op = u16::from_be_bytes([self.ram[range.start], self.ram[range.start + 1]]);
+        self.pc = self.pc.wrapping_add(2);
+        Ok(op)
+    }
+
+    // Runs an instruction as if it had been fetched from PC
+    pub fn execute(&mut self, instruction: Instruction) -> Result<(), EmuError> {
+        self.execute_at(self.pc, instruction)
+    }
+
+    // `pc` is the address the instruction was fetched from, for errors
+    fn execute_at(&mut self, pc: u16, instruction: Instruction) -> Result<(), EmuError> {
+        #[cfg(feature = "alloc")]
+        self.record_undo(pc, instruction);
+
+        self.execute_instruction(pc, instruction)?;
+
+        self.metrics.instructions += 1;
+        if let Instruction::Draw { .. } = instruction {
+            self.metrics.draws += 1;
+        }
+
+        #[cfg(feature = "alloc")]
+        self.record_trace(pc, instruction);
+        #[cfg(feature = "alloc")]
+        self.record_stats(pc, instruction);
+        #[cfg(feature = "alloc")]
+        self.record_profile(pc, instruction);
+
+        Ok(())
+    }
+
+    fn execute_instruction(&mut self, pc: u16, instruction: Instruction) -> Result<(), EmuError> {
+        let op = instruction.encode();
+
+        match instruction {
             // NOP
-            (0, 0, 0, 0) => {}
+            Instruction::Nop => {}
 
             // CLS, clear screen
-            (0, 0, 0xE, 0) => {
-                self.screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
+            Instruction::Cls => {
+                self.clear_screen();
             }
 
             // RET, return from subroutine
-            (0, 0, 0xE, 0xE) => {
-                let return_addr = self.pop();
+            Instruction::Ret => {
+                let return_addr = self.pop().ok_or(EmuError::StackUnderflow { pc, op })?;
                 self.pc = return_addr;
             }
 
+            // SCD N, scroll display down N pixels
+            Instruction::ScrollDown(n) => {
+                let n = n as usize;
+                self.scroll_down(n);
+            }
+
+            // SCU N, scroll display up N pixels (XO-CHIP)
+            Instruction::ScrollUp(n) => {
+                let n = n as usize;
+                self.scroll_up(n);
+            }
+
+            // SCR, scroll display right 4 pixels
+            Instruction::ScrollRight => {
+                self.scroll_right(4);
+            }
+
+            // SCL, scroll display left 4 pixels
+            Instruction::ScrollLeft => {
+                self.scroll_left(4);
+            }
+
+            // EXIT, stop the interpreter
+            Instruction::Exit => {
+                self.run_state = RunState::Halted;
+            }
+
+            // LOW, switch to 64x32 mode
+            Instruction::Lores => {
+                self.set_resolution(Resolution::Low);
+            }
+
+            // HIGH, switch to 128x64 mode
+            Instruction::Hires => {
+                self.set_resolution(Resolution::High);
+            }
+
             // JMP NNN, jump
-            (1, _, _, _) => {
-                let nnn = op & 0xFFF;
+            Instruction::Jump(nnn) => {
+                if self.is_endless_loop(nnn, pc) {
+                    self.run_state = RunState::Spinning;
+                } else if self.run_state == RunState::Spinning {
+                    // The loop was rewritten into one that can end
+                    self.run_state = RunState::Running;
+                }
                 self.pc = nnn;
             }
 
             // CALL NNN, call subroutine (and then jump)
-            (2, _, _, _) => {
-                let nnn = op & 0xFFF;
-                self.push(self.pc);
+            Instruction::Call(nnn) => {
+                self.push(self.pc)
+                    .ok_or(EmuError::StackOverflow { pc, op })?;
                 self.pc = nnn;
             }
 
             // Skip next opcode if VX == NN
-            (3, _, _, _) => {
-                let x = digit2 as usize;
-                let nn = (op & 0x00FF) as u8;
+            Instruction::SkipEqImm { x, nn } => {
+                let x = x as usize;
 
                 if self.v_reg[x] == nn {
-                    self.pc += 2;
+                    self.skip_next();
                 }
             }
 
             // Skip next opcode if VX != NN
-            (4, _, _, _) => {
-                let x = digit2 as usize;
-                let nn = (op & 0x00FF) as u8;
+            Instruction::SkipNeImm { x, nn } => {
+                let x = x as usize;
 
                 if self.v_reg[x] != nn {
-                    self.pc += 2;
+                    self.skip_next();
                 }
             }
 
             // Skip next opcode if VX == VY
-            (5, _, _, 0) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Instruction::SkipEqReg { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
 
                 if self.v_reg[x] == self.v_reg[y] {
-                    self.pc += 2;
+                    self.skip_next();
+                }
+            }
+
+            // Store VX thru VY into RAM at I, descending if X > Y; I is unchanged
+            Instruction::SaveRange { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
+                let count = x.abs_diff(y) + 1;
+                let range = self.ram_range(pc, self.i_reg as usize, count)?;
+
+                for (i, addr) in range.enumerate() {
+                    let reg = if x <= y { x + i } else { x - i };
+                    self.ram[addr] = self.v_reg[reg];
+                }
+            }
+
+            // Fill VX thru VY from RAM at I, descending if X > Y; I is unchanged
+            Instruction::LoadRange { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
+                let count = x.abs_diff(y) + 1;
+                let range = self.ram_range(pc, self.i_reg as usize, count)?;
+
+                for (i, addr) in range.enumerate() {
+                    let reg = if x <= y { x + i } else { x - i };
+                    self.v_reg[reg] = self.ram[addr];
                 }
             }
 
             // VX = NN
-            (6, _, _, _) => {
-                let x = digit2 as usize;
-                let nn = (op & 0x00FF) as u8;
+            Instruction::SetImm { x, nn } => {
+                let x = x as usize;
                 self.v_reg[x] = nn;
             }
 
             // VX += NN, doesn't affect carry flag
-            (7, _, _, _) => {
-                let x = digit2 as usize;
-                let nn = (op & 0x00FF) as u8;
+            Instruction::AddImm { x, nn } => {
+                let x = x as usize;
                 self.v_reg[x] = self.v_reg[x].wrapping_add(nn);
             }
 
             // VX = VY
-            (8, _, _, 0) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Instruction::Set { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
                 self.v_reg[x] = self.v_reg[y];
             }
 
-            // VX |= VY
-            (8, _, _, 1) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            // VX |= VY; VF = 0 with the VF reset quirk
+            Instruction::Or { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
                 self.v_reg[x] |= self.v_reg[y];
+
+                if self.quirks.vf_reset {
+                    self.v_reg[0xF] = 0;
+                }
             }
 
-            // VX &= VY
-            (8, _, _, 2) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            // VX &= VY; VF = 0 with the VF reset quirk
+            Instruction::And { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
                 self.v_reg[x] &= self.v_reg[y];
+
+                if self.quirks.vf_reset {
+                    self.v_reg[0xF] = 0;
+                }
             }
 
-            // VX ^= VY
-            (8, _, _, 3) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            // VX ^= VY; VF = 0 with the VF reset quirk
+            Instruction::Xor { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
                 self.v_reg[x] ^= self.v_reg[y];
+
+                if self.quirks.vf_reset {
+                    self.v_reg[0xF] = 0;
+                }
             }
 
             // VX += VY; set VF if carry
-            (8, _, _, 4) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Instruction::Add { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
 
                 let (result, carry) = self.v_reg[x].overflowing_add(self.v_reg[y]);
 
@@ -253,9 +1488,9 @@ impl Emu {
             }
 
             // VX -= VY; clear VF if borrow
-            (8, _, _, 5) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Instruction::Sub { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
 
                 let (result, borrow) = self.v_reg[x].overflowing_sub(self.v_reg[y]);
 
@@ -263,18 +1498,24 @@ impl Emu {
                 self.v_reg[0xF] = if borrow { 0 } else { 1 };
             }
 
-            // VX >>= 1; store dropped bit in VF
-            (8, _, _, 6) => {
-                let x = digit2 as usize;
-                let dropped = self.v_reg[x] * 1;
+            // VX >>= 1; store dropped bit in VF (VX = VY >> 1 with shift quirk)
+            Instruction::ShiftRight { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
+
+                if self.quirks.shift_uses_vy {
+                    self.v_reg[x] = self.v_reg[y];
+                }
+
+                let dropped = self.v_reg[x] & 1;
                 self.v_reg[x] >>= 1;
                 self.v_reg[0xF] = dropped;
             }
 
             // VX = VY - VX; clear VF if borrow
-            (8, _, _, 7) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Instruction::SubReverse { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
 
                 let (result, borrow) = self.v_reg[y].overflowing_sub(self.v_reg[x]);
 
@@ -282,108 +1523,137 @@ impl Emu {
                 self.v_reg[0xF] = if borrow { 0 } else { 1 };
             }
 
-            // VX <<= 1; store dropped bit in VF
-            (8, _, _, 0xE) => {
-                let x = digit2 as usize;
+            // VX <<= 1; store dropped bit in VF (VX = VY << 1 with shift quirk)
+            Instruction::ShiftLeft { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
+
+                if self.quirks.shift_uses_vy {
+                    self.v_reg[x] = self.v_reg[y];
+                }
+
                 let dropped = (self.v_reg[x] >> 7) & 1;
                 self.v_reg[x] <<= 1;
                 self.v_reg[0xF] = dropped;
             }
 
             // Skip next opcode if VX != VY
-            (9, _, _, 0) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Instruction::SkipNeReg { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
 
                 if self.v_reg[x] != self.v_reg[y] {
-                    self.pc += 2;
+                    self.skip_next();
                 }
             }
 
             // I = NNN
-            (0xA, _, _, _) => {
-                let nnn = op & 0x0FFF;
+            Instruction::SetIndex(nnn) => {
                 self.i_reg = nnn;
             }
 
-            // Jump to V0 + NNN
-            (0xB, _, _, _) => {
-                let nnn = op & 0x0FFF;
-                self.pc = (self.v_reg[0] as u16) + nnn;
+            // Jump to V0 + NNN, or XNN + VX with the jump quirk
+            Instruction::JumpOffset { x, nnn } => {
+                let offset_reg = if self.quirks.jump_uses_vx {
+                    x as usize
+                } else {
+                    0
+                };
+                self.pc = (self.v_reg[offset_reg] as u16) + nnn;
             }
 
             // VX = rand() & NN
-            (0xC, _, _, _) => {
-                let x = digit2 as usize;
-                let nn = (op & 0x00FF) as u8;
-                let mut rng = oorandom::Rand32::new(10);
-                let rnd: u8 = rng.rand_u32() as u8;
+            Instruction::Random { x, nn } => {
+                let x = x as usize;
+                let rnd = self.rng.next_byte();
                 self.v_reg[x] = rnd & nn;
             }
 
             // Draw sprite at (VX, VY), N pixels tall, XORed onto screen, VF set if any erased
-            (0xD, _, _, _) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
-                let n = digit4 as usize;
-                let mut flipped = false;
+            // DXY0 draws a 16x16 sprite (SCHIP), or 8x16 in lores depending on config
+            Instruction::Draw { x, y, n } => {
+                let x = x as usize;
+                let y = y as usize;
 
                 let x = self.v_reg[x] as usize;
                 let y = self.v_reg[y] as usize;
 
-                for delta_y in 0..n {
-                    let flips = self.ram[(self.i_reg as usize) + delta_y];
-
-                    for delta_x in 0..8 {
-                        let flip = flips & (0x80 >> delta_x) != 0;
-
-                        if flip {
-                            let x = (x + delta_x) % SCREEN_WIDTH;
-                            let y = (y + delta_y) % SCREEN_HEIGHT;
+                let (sprite_width, sprite_height) = self.sprite_dimensions(n);
+                let sprite_size = self.sprite_data_len(sprite_width, sprite_height);
+                self.ram_range(pc, self.i_reg as usize, sprite_size)?;
 
-                            let index = y * SCREEN_WIDTH + x;
+                let collided_rows = self.draw_sprite(x, y, sprite_width, sprite_height);
 
-                            flipped |= self.screen[index];
-                            self.screen[index] ^= true;
+                // SCHIP 1.1 reports the number of collided rows in hires mode
+                self.v_reg[0xF] = match self.resolution {
+                    Resolution::High => collided_rows as u8,
+                    Resolution::Low => {
+                        if collided_rows > 0 {
+                            1
+                        } else {
+                            0
                         }
                     }
-                }
+                };
 
-                self.v_reg[0xF] = if flipped { 1 } else { 0 };
+                if self.quirks.display_wait {
+                    self.run_state = RunState::WaitingForVblank;
+                }
             }
 
-            // Skip next opcode if key index in VX is pressed
-            (0xE, _, 0x9, 0xE) => {
-                let x = digit2 as usize;
-                let key_index = self.v_reg[x] as usize;
+            // Skip next opcode if key index in VX is pressed. Only the low
+            // nibble of VX counts, as on the COSMAC VIP.
+            Instruction::SkipKeyPressed { x } => {
+                let x = x as usize;
+                let key_index = (self.v_reg[x] & 0xF) as usize;
                 let pressed = self.keys[key_index];
+                self.keys_polled |= 1 << key_index;
 
                 if pressed {
-                    self.pc += 2;
+                    self.skip_next();
                 }
             }
 
             // Skip next opcode if key index in VX is not pressed
-            (0xE, _, 0xA, 0x1) => {
-                let x = digit2 as usize;
-                let key_index = self.v_reg[x] as usize;
+            Instruction::SkipKeyNotPressed { x } => {
+                let x = x as usize;
+                let key_index = (self.v_reg[x] & 0xF) as usize;
                 let pressed = self.keys[key_index];
+                self.keys_polled |= 1 << key_index;
 
                 if !pressed {
-                    self.pc += 2;
+                    self.skip_next();
                 }
             }
 
+            // I = NNNN, long index load spanning the next word
+            Instruction::SetIndexLong(nnnn) => {
+                self.i_reg = nnnn;
+            }
+
+            // Load 16-byte audio pattern from RAM at I
+            Instruction::LoadAudio => {
+                let range = self.ram_range(pc, self.i_reg as usize, AUDIO_PATTERN_SIZE)?;
+                self.audio_pattern.copy_from_slice(&self.ram[range]);
+                self.audio_loaded = true;
+            }
+
+            // PLANE N, select drawing planes by bitmask
+            Instruction::Plane(n) => {
+                self.plane_mask = n & 0b11;
+            }
+
             // VX = Delay Timer
-            (0xF, _, 0x0, 0x7) => {
-                let x = digit2 as usize;
+            Instruction::GetDelay { x } => {
+                let x = x as usize;
                 self.v_reg[x] = self.dt;
             }
 
             // Waits for key press, store index in VX, blocking
-            (0xF, _, 0x0, 0xA) => {
-                let x = digit2 as usize;
+            Instruction::WaitKey { x } => {
+                let x = x as usize;
                 let mut pressed = false;
+                self.waited_for_key = true;
 
                 for index in 0..NUM_KEYS {
                     if self.keys[index] {
@@ -394,69 +1664,483 @@ impl Emu {
                 }
 
                 if !pressed {
-                    // Redo opcode
-                    self.pc -= 2;
+                    // Redo opcode, PC wrapped to 0 after one at the end of 64KB
+                    self.pc = self.pc.wrapping_sub(2);
                 }
             }
 
             // Delay Timer = VX
-            (0xF, _, 0x1, 0x5) => {
-                let x = digit2 as usize;
+            Instruction::SetDelay { x } => {
+                let x = x as usize;
                 self.dt = self.v_reg[x];
             }
 
             // Sound Timer
-            (0xF, _, 0x1, 0x8) => {
-                let x = digit2 as usize;
+            Instruction::SetSound { x } => {
+                let x = x as usize;
                 self.st = self.v_reg[x];
             }
 
             // I += VX
-            (0xF, _, 0x1, 0xE) => {
-                let x = digit2 as usize;
+            Instruction::AddIndex { x } => {
+                let x = x as usize;
                 self.i_reg = self.i_reg.wrapping_add(self.v_reg[x] as u16);
             }
 
             // I = address of font character in VX
-            (0xF, _, 0x2, 0x9) => {
-                let x = digit2 as usize;
+            Instruction::Font { x } => {
+                let x = x as usize;
                 let c = self.v_reg[x];
                 self.i_reg = 5 * c as u16;
             }
 
+            // I = address of big font character in VX
+            Instruction::BigFont { x } => {
+                let x = x as usize;
+                let c = self.v_reg[x] & 0xF;
+                self.i_reg = (BIG_FONTSET_ADDR as u16) + 10 * c as u16;
+            }
+
+            // PITCH = VX, sets the audio pattern playback rate
+            Instruction::Pitch { x } => {
+                let x = x as usize;
+                self.pitch = self.v_reg[x];
+                self.playback_rate = pitch_to_rate(self.pitch);
+            }
+
             // Store BCD encoding of VX inot I
-            (0xF, _, 0x3, 0x3) => {
-                let x = digit2 as usize;
+            Instruction::Bcd { x } => {
+                let x = x as usize;
                 let num = self.v_reg[x];
+                let range = self.ram_range(pc, self.i_reg as usize, 3)?;
 
-                for i in 0..3 {
-                    let digit = (num / u8::pow(10, 2 - i)) % 10;
-                    let addr = (self.i_reg + i as u16) as usize;
+                for (i, addr) in range.enumerate() {
+                    let digit = (num / u8::pow(10, 2 - i as u32)) % 10;
                     self.ram[addr] = digit;
                 }
             }
 
             // Store V0 thru VX into RAM address starting at I (inclusive)
-            (0xF, _, 0x5, 0x5) => {
-                let x = digit2 as usize;
+            // I is left at I + X + 1 with the memory quirk
+            Instruction::Store { x } => {
+                let x = x as usize;
+                let range = self.ram_range(pc, self.i_reg as usize, x + 1)?;
 
-                for i in 0..=x {
-                    let addr = (self.i_reg as usize) + i;
-                    self.ram[addr] = self.v_reg[i];
+                self.ram[range].copy_from_slice(&self.v_reg[..=x]);
+
+                if self.quirks.load_store_increments_i {
+                    self.i_reg = self.i_reg.wrapping_add(x as u16 + 1);
                 }
             }
 
             // Fill V0 thru VX with RAM values starting at I (inclusive)
-            (0xF, _, 0x6, 0x5) => {
-                let x = digit2 as usize;
-                for i in 0..=x {
-                    let addr = (self.i_reg as usize) + i;
-                    self.v_reg[i] = self.ram[addr];
+            // I is left at I + X + 1 with the memory quirk
+            Instruction::Load { x } => {
+                let x = x as usize;
+                let range = self.ram_range(pc, self.i_reg as usize, x + 1)?;
+
+                self.v_reg[..=x].copy_from_slice(&self.ram[range]);
+
+                if self.quirks.load_store_increments_i {
+                    self.i_reg = self.i_reg.wrapping_add(x as u16 + 1);
                 }
             }
 
-            // unimplemented opcode
-            (_, _, _, _) => unimplemented!("Unimplemented opcode: {}", op),
+            // Store V0 thru VX into the RPL user flags
+            Instruction::SaveFlags { x } => {
+                let x = x as usize;
+                let mut flags = [0; NUM_FLAGS];
+
+                self.flag_storage.load(&mut flags);
+                flags[..=x].copy_from_slice(&self.v_reg[..=x]);
+                self.flag_storage.save(&flags);
+            }
+
+            // Fill V0 thru VX from the RPL user flags
+            Instruction::LoadFlags { x } => {
+                let x = x as usize;
+                let mut flags = [0; NUM_FLAGS];
+
+                self.flag_storage.load(&mut flags);
+                self.v_reg[..=x].copy_from_slice(&flags[..=x]);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// 4000 * 2^((pitch - 64) / 48) without relying on std float functions
+fn pitch_to_rate(pitch: u8) -> f32 {
+    let exponent = pitch as i32 - DEFAULT_PITCH as i32;
+    let whole = exponent.div_euclid(48);
+    let frac = exponent.rem_euclid(48) as f32 / 48.0;
+
+    // 2^frac = e^(frac * ln 2), the series converges quickly for frac < 1
+    let x = frac * core::f32::consts::LN_2;
+    let mut term = 1.0;
+    let mut frac_pow = 1.0;
+    for n in 1..10 {
+        term *= x / n as f32;
+        frac_pow += term;
+    }
+
+    let mut rate = AUDIO_BASE_RATE * frac_pow;
+    for _ in 0..whole.abs() {
+        rate = if whole > 0 { rate * 2.0 } else { rate / 2.0 };
+    }
+
+    rate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A machine in `resolution` with every pixel of plane 0 lit, about to
+    // run `program`
+    fn full_screen(resolution: Resolution, program: &[u8]) -> Emu {
+        let mut emu = Emu::new();
+        emu.load(program).unwrap();
+        emu.set_resolution(resolution);
+        let row_mask = emu.row_mask();
+        emu.screen[0][..resolution.height()].fill(row_mask);
+        emu
+    }
+
+    fn run(emu: &mut Emu, instructions: usize) {
+        for _ in 0..instructions {
+            emu.tick().unwrap();
+        }
+    }
+
+    fn lit(emu: &Emu, x: usize, y: usize) -> bool {
+        let width = emu.resolution.width();
+        (emu.screen[0][y] >> (width - 1 - x)) & 1 != 0
+    }
+
+    fn row_lit(emu: &Emu, y: usize) -> bool {
+        (0..emu.resolution.width()).all(|x| lit(emu, x, y))
+    }
+
+    fn row_blank(emu: &Emu, y: usize) -> bool {
+        (0..emu.resolution.width()).all(|x| !lit(emu, x, y))
+    }
+
+    fn column_lit(emu: &Emu, x: usize) -> bool {
+        (0..emu.resolution.height()).all(|y| lit(emu, x, y))
+    }
+
+    fn column_blank(emu: &Emu, x: usize) -> bool {
+        (0..emu.resolution.height()).all(|y| !lit(emu, x, y))
+    }
+
+    const RESOLUTIONS: [Resolution; 2] = [Resolution::Low, Resolution::High];
+
+    // A 0xA5 sprite row at (3, 1) and the last pixel of the screen lit
+    fn sprinkled(resolution: Resolution) -> Emu {
+        let mut emu = Emu::new();
+        emu.set_resolution(resolution);
+        let width = resolution.width();
+        emu.screen[0][1] = 0xA5 << (width - 8 - 3);
+        emu.screen[0][resolution.height() - 1] = 1;
+        emu
+    }
+
+    #[test]
+    fn display_views_unpack_the_screen() {
+        for resolution in RESOLUTIONS {
+            let emu = sprinkled(resolution);
+            let (width, height) = (resolution.width(), resolution.height());
+
+            let mut pixels = 0;
+            for (index, pixel) in emu.get_display().enumerate() {
+                assert_eq!(pixel, lit(&emu, index % width, index / width));
+                pixels += 1;
+            }
+            assert_eq!(pixels, width * height);
+            let colors = emu.get_display_colors().zip(emu.get_display());
+            assert!(colors.into_iter().all(|(color, lit)| color == lit as u8));
+
+            let mut bits = [0xFF; SCREEN_SIZE / 8 + 1];
+            emu.render_bits(0, &mut bits);
+            let row_bytes = width / 8;
+            assert_eq!(bits[row_bytes..row_bytes + 2], [0x14, 0xA0]);
+            assert_eq!(bits[width * height / 8 - 1], 0x01);
+            assert!(bits[..row_bytes].iter().all(|&byte| byte == 0));
+            // Past the screen is left alone
+            assert_eq!(bits[width * height / 8], 0xFF);
+        }
+    }
+
+    #[test]
+    fn zero_sample_rate_renders_silence() {
+        let mut emu = Emu::new();
+        // LD VF, 5; LD ST, VF
+        emu.load(&[0x6F, 0x05, 0xFF, 0x18]).unwrap();
+        run(&mut emu, 2);
+
+        let mut samples = [1.0; 16];
+        emu.render_audio(&mut samples, 0);
+        assert_eq!(samples, [0.0; 16]);
+    }
+
+    #[test]
+    fn a_loaded_all_zero_pattern_replaces_the_buzzer() {
+        let mut emu = Emu::new();
+        // LD I, 0x300; LD AUDIO, [I]; LD VF, 5; LD ST, VF
+        emu.load(&[0xA3, 0x00, 0xF0, 0x02, 0x6F, 0x05, 0xFF, 0x18])
+            .unwrap();
+        run(&mut emu, 4);
+
+        let mut samples = [0.0; 256];
+        emu.render_audio(&mut samples, 44100);
+        assert!(samples.iter().all(|sample| *sample == -1.0));
+
+        // Back to the buzzer until the next F002
+        emu.reset();
+        emu.st = 5;
+        emu.render_audio(&mut samples, 44100);
+        assert!(samples.contains(&1.0) && samples.contains(&-1.0));
+    }
+
+    #[test]
+    fn ram_only_grows_when_asked() {
+        let mut emu = Emu::new();
+        assert_eq!(emu.ram().len(), DEFAULT_RAM_SIZE);
+        assert!(core::mem::size_of::<Emu>() < MAX_RAM_SIZE / 4);
+
+        emu.set_ram_size(MAX_RAM_SIZE);
+        assert_eq!(emu.ram().len(), RAM_CAPACITY);
+        emu.set_ram_size(0);
+        assert_eq!(emu.ram().len(), DEFAULT_RAM_SIZE);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn wait_key_at_the_end_of_memory_waits_in_place() {
+        let mut emu = Emu::new();
+        emu.set_ram_size(MAX_RAM_SIZE);
+        // LD V0, K
+        emu.ram[0xFFFE..].copy_from_slice(&[0xF0, 0x0A]);
+        emu.pc = 0xFFFE;
+        run(&mut emu, 2);
+        assert_eq!(emu.pc, 0xFFFE);
+
+        emu.keypress(Key::Key7, true);
+        run(&mut emu, 1);
+        assert_eq!((emu.pc, emu.v_reg[0]), (0, 7));
+    }
+
+    // Draws an 8 pixel wide sprite of `rows` at X, Y over a screen with
+    // one lit pixel, with or without clipping
+    fn draw_over_pixel(clip: bool, (x, y): (u8, u8), rows: &[u8], pixel: (usize, usize)) -> Emu {
+        let mut emu = Emu::new();
+        emu.set_quirks(Quirks {
+            clip_sprites: clip,
+            ..Quirks::default()
+        });
+        // LD I, 0x300; LD V0, X; LD V1, Y; DRW V0, V1, N
+        let n = rows.len() as u8;
+        emu.load(&[0xA3, 0x00, 0x60, x, 0x61, y, 0xD0, 0x10 | n])
+            .unwrap();
+        emu.ram[0x300..0x300 + rows.len()].copy_from_slice(rows);
+        emu.screen[0][pixel.1] |= 1 << (SCREEN_WIDTH - 1 - pixel.0);
+        run(&mut emu, 4);
+        emu
+    }
+
+    #[test]
+    fn sprite_past_the_right_edge() {
+        for clip in [false, true] {
+            let emu = draw_over_pixel(clip, (60, 0), &[0xFF], (62, 0));
+
+            assert!(lit(&emu, 60, 0) && lit(&emu, 61, 0) && lit(&emu, 63, 0));
+            assert!(!lit(&emu, 62, 0), "clip {}", clip);
+            assert_eq!(emu.v_reg[0xF], 1, "clip {}", clip);
         }
     }
+
+    #[test]
+    fn sprite_wraps_onto_the_left_edge_unless_clipped() {
+        for clip in [false, true] {
+            let emu = draw_over_pixel(clip, (60, 0), &[0xFF], (1, 0));
+
+            let wrapped = [0, 2, 3].map(|x| lit(&emu, x, 0));
+            assert_eq!(wrapped, [!clip; 3], "clip {}", clip);
+            assert_eq!(lit(&emu, 1, 0), clip, "clip {}", clip);
+            assert_eq!(emu.v_reg[0xF], !clip as u8, "clip {}", clip);
+            assert!((4..60).all(|x| !lit(&emu, x, 0)));
+        }
+    }
+
+    #[test]
+    fn sprite_past_the_bottom_edge() {
+        for clip in [false, true] {
+            let emu = draw_over_pixel(clip, (0, 28), &[0x80; 8], (0, 30));
+
+            assert!(lit(&emu, 0, 28) && lit(&emu, 0, 29) && lit(&emu, 0, 31));
+            assert!(!lit(&emu, 0, 30), "clip {}", clip);
+            assert_eq!(emu.v_reg[0xF], 1, "clip {}", clip);
+        }
+    }
+
+    #[test]
+    fn sprite_wraps_onto_the_top_edge_unless_clipped() {
+        for clip in [false, true] {
+            let emu = draw_over_pixel(clip, (0, 28), &[0x80; 8], (0, 1));
+
+            let wrapped = [0, 2, 3].map(|y| lit(&emu, 0, y));
+            assert_eq!(wrapped, [!clip; 3], "clip {}", clip);
+            assert_eq!(lit(&emu, 0, 1), clip, "clip {}", clip);
+            assert_eq!(emu.v_reg[0xF], !clip as u8, "clip {}", clip);
+            assert!((4..28).all(|y| !lit(&emu, 0, y)));
+        }
+    }
+
+    #[test]
+    fn sprite_start_wraps_even_when_clipping() {
+        for clip in [false, true] {
+            // X = 64 + 2, Y = 32 + 3
+            let emu = draw_over_pixel(clip, (66, 35), &[0x80], (2, 3));
+
+            assert!(!lit(&emu, 2, 3), "clip {}", clip);
+            assert_eq!(emu.v_reg[0xF], 1, "clip {}", clip);
+        }
+    }
+
+    #[test]
+    fn scroll_down_blanks_the_top_rows() {
+        for resolution in RESOLUTIONS {
+            // SCD 5
+            let mut emu = full_screen(resolution, &[0x00, 0xC5]);
+            run(&mut emu, 1);
+
+            let height = resolution.height();
+            assert!((0..5).all(|y| row_blank(&emu, y)), "{:?}", resolution);
+            assert!((5..height).all(|y| row_lit(&emu, y)), "{:?}", resolution);
+        }
+    }
+
+    #[test]
+    fn scroll_down_past_the_bottom_blanks_the_screen() {
+        // SCD 15 twice in lores, more than the 32 rows
+        let mut emu = full_screen(Resolution::Low, &[0x00, 0xCF, 0x00, 0xCF, 0x00, 0xCF]);
+        run(&mut emu, 3);
+        assert!((0..SCREEN_HEIGHT).all(|y| row_blank(&emu, y)));
+    }
+
+    #[test]
+    fn scroll_up_drops_the_top_rows() {
+        for resolution in RESOLUTIONS {
+            // SCU 3
+            let mut emu = full_screen(resolution, &[0x00, 0xD3]);
+            let height = resolution.height();
+            // Only the top and bottom rows lit
+            emu.screen[0][1..height - 1].fill(0);
+            run(&mut emu, 1);
+
+            // The top row is gone, the bottom one moved up and left blank rows behind
+            assert!(
+                (0..height - 4).all(|y| row_blank(&emu, y)),
+                "{:?}",
+                resolution
+            );
+            assert!(row_lit(&emu, height - 4), "{:?}", resolution);
+            assert!(
+                (height - 3..height).all(|y| row_blank(&emu, y)),
+                "{:?}",
+                resolution
+            );
+        }
+    }
+
+    #[test]
+    fn scroll_up_only_moves_the_selected_planes() {
+        // PLANE 2; SCU 1
+        let mut emu = full_screen(Resolution::Low, &[0xF2, 0x01, 0x00, 0xD1]);
+        emu.screen[1] = emu.screen[0];
+        run(&mut emu, 2);
+
+        assert!(row_lit(&emu, SCREEN_HEIGHT - 1));
+        assert_eq!(emu.screen[1][SCREEN_HEIGHT - 1], 0);
+    }
+
+    #[test]
+    fn scroll_right_blanks_the_left_columns() {
+        for resolution in RESOLUTIONS {
+            // SCR
+            let mut emu = full_screen(resolution, &[0x00, 0xFB]);
+            run(&mut emu, 1);
+
+            let width = resolution.width();
+            assert!((0..4).all(|x| column_blank(&emu, x)), "{:?}", resolution);
+            assert!((4..width).all(|x| column_lit(&emu, x)), "{:?}", resolution);
+        }
+    }
+
+    #[test]
+    fn scroll_left_blanks_the_right_columns() {
+        for resolution in RESOLUTIONS {
+            // SCL
+            let mut emu = full_screen(resolution, &[0x00, 0xFC]);
+            run(&mut emu, 1);
+
+            let width = resolution.width();
+            assert!(
+                (0..width - 4).all(|x| column_lit(&emu, x)),
+                "{:?}",
+                resolution
+            );
+            assert!(
+                (width - 4..width).all(|x| column_blank(&emu, x)),
+                "{:?}",
+                resolution
+            );
+            // Nothing is kept past the edge of the lores screen
+            assert_eq!(emu.screen[0][0] & !emu.row_mask(), 0);
+        }
+    }
+
+    #[test]
+    fn loops_waiting_for_keys_are_not_spinning() {
+        // wait: LD V0, K / SE V0, 5 / JP wait / end: JP end
+        let mut emu = Emu::new();
+        emu.load(&[0xF0, 0x0A, 0x30, 0x05, 0x12, 0x00, 0x12, 0x06])
+            .unwrap();
+        run(&mut emu, 10);
+        assert_eq!(emu.run_state(), RunState::Running);
+
+        emu.keypress(Key::Key3, true);
+        run(&mut emu, 10);
+        emu.keypress(Key::Key3, false);
+        assert_eq!(emu.run_state(), RunState::Running);
+
+        emu.keypress(Key::Key5, true);
+        run(&mut emu, 10);
+        assert_eq!(emu.pc, 0x206);
+        assert_eq!(emu.run_state(), RunState::Spinning);
+
+        // wait: LD V0, K / JP wait
+        let mut emu = Emu::new();
+        emu.load(&[0xF0, 0x0A, 0x12, 0x00]).unwrap();
+        emu.keypress(Key::Key1, true);
+        run(&mut emu, 10);
+        assert_eq!(emu.run_state(), RunState::Running);
+    }
+
+    #[test]
+    fn spinning_ends_when_the_loop_can_exit() {
+        // loop: LD V0, 1 / JP loop / JP 0x208
+        let mut emu = Emu::new();
+        emu.load(&[0x60, 0x01, 0x12, 0x00, 0x12, 0x08]).unwrap();
+        run(&mut emu, 2);
+        assert_eq!(emu.run_state(), RunState::Spinning);
+
+        // Rewritten to SE V0, 1, which skips the jump back
+        emu.ram[0x200] = 0x30;
+        run(&mut emu, 2);
+        assert_eq!(emu.pc, 0x208);
+        assert_eq!(emu.run_state(), RunState::Running);
+    }
 }
@@ -0,0 +1,582 @@
+use alloc::collections::BTreeSet;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+
+use crate::disasm::{self, ListingLine};
+use crate::*;
+
+// Interpreter work area below the program, the fonts of this crate sit
+// before it but other interpreters keep their variables here
+const RESERVED: Range<usize> = BIG_FONTSET_ADDR + BIG_FONTSET_SIZE..START_ADDR as usize;
+
+// What lint() found in a ROM
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub findings: Vec<Finding>, // in address order
+    pub extension: Extension,   // largest instruction set used
+    // Platforms that run every instruction and fit the ROM, the findings
+    // may still tell them apart
+    pub compatible: Vec<Variant>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub addr: u16, // of the instruction
+    pub issue: Issue,
+}
+
+// Constructs that behave differently between interpreters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Issue {
+    ShiftSource,    // 8XY6/8XYE with X != Y, see Quirks::shift_uses_vy
+    JumpOffset,     // BNNN with X != 0, see Quirks::jump_uses_vx
+    IndexIncrement, // FX55/FX65 followed by a use of I, see Quirks::load_store_increments_i
+    // DXYN at constant coordinates crossing the screen edge, see Quirks::clip_sprites
+    SpriteWrap { x: u8, y: u8 },
+    UninitializedRead(u16), // RAM the program never writes, at this address
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#05X}: ", self.addr)?;
+
+        match self.issue {
+            Issue::ShiftSource => write!(
+                f,
+                "shift with X != Y, the COSMAC VIP and XO-CHIP shift VY, others VX"
+            ),
+            Issue::JumpOffset => write!(
+                f,
+                "BNNN with X != 0, CHIP-48 and SCHIP add VX instead of V0"
+            ),
+            Issue::IndexIncrement => write!(
+                f,
+                "I is used after FX55/FX65, only the COSMAC VIP, CHIP-48 and XO-CHIP advance it"
+            ),
+            Issue::SpriteWrap { x, y } => write!(
+                f,
+                "sprite at ({}, {}) crosses the screen edge, XO-CHIP wraps it and others clip it",
+                x, y
+            ),
+            Issue::UninitializedRead(addr) => write!(
+                f,
+                "reads {:#05X} which the program never writes, interpreters leave different data there",
+                addr
+            ),
+        }
+    }
+}
+
+// Statically checks the reachable code of a ROM loaded at 0x200 for
+// portability problems. Register values are only tracked through straight
+// code, so only sprites drawn right after setting their coordinates are
+// checked.
+pub fn lint(rom: &[u8]) -> Report {
+    let code = reachable_code(&disasm::analyze(rom, START_ADDR));
+    Linter::run(rom, &code).into_report(rom)
+}
+
+// Everything known about a ROM before running it, see analyze_rom()
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RomReport {
+    pub size: usize,
+    pub extended: Vec<(u16, Instruction)>, // reachable instructions beyond CHIP-8
+    pub unknown: Vec<(u16, u16)>,          // reachable opcodes that don't decode, by address
+    pub capacity: Vec<(Variant, usize)>,   // bytes each platform can load a program into
+    pub lint: Report,
+    pub suggestion: Suggestion,
+}
+
+// The instruction sets, size limits and quirks a ROM loaded at 0x200
+// depends on, for frontends to pick or warn about a configuration
+pub fn analyze_rom(rom: &[u8]) -> RomReport {
+    let analysis = disasm::analyze(rom, START_ADDR);
+    let code = reachable_code(&analysis);
+    let linter = Linter::run(rom, &code);
+
+    RomReport {
+        size: rom.len(),
+        extended: code
+            .iter()
+            .copied()
+            .filter(|(_, instruction)| instruction.extension() != Extension::Chip8)
+            .collect(),
+        unknown: analysis
+            .unknown()
+            .map(|line| (line.addr, line.op))
+            .collect(),
+        capacity: Variant::ALL
+            .into_iter()
+            .map(|variant| (variant, variant.ram_size() - START_ADDR as usize))
+            .collect(),
+        suggestion: linter.suggest(rom),
+        lint: linter.into_report(rom),
+    }
+}
+
+fn reachable_code(analysis: &disasm::Analysis) -> Vec<(u16, Instruction)> {
+    analysis
+        .listing()
+        .filter_map(|line| match line {
+            ListingLine::Code(line) => Some((line.addr, line.instruction.ok()?)),
+            ListingLine::Data { .. } => None,
+        })
+        .collect()
+}
+
+// Quirks and the closest platform a ROM was probably written for, to
+// configure the emulator for unknown ROMs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Suggestion {
+    pub variant: Variant,
+    pub quirks: Quirks, // may differ from the variant's where the code says so
+    pub hints: Vec<Hint>,
+}
+
+// What a Suggestion is based on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hint {
+    Extension(Extension),       // the instructions used
+    ShiftUsesVy(bool),          // from the registers 8XY6/8XYE name
+    LoadStoreIncrementsI(bool), // from what follows FX55/FX65
+    JumpUsesVx(bool),           // from the register set before BNNN
+    KnownRom,                   // a RomDatabase names the platform
+}
+
+impl fmt::Display for Hint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Hint::Extension(Extension::Chip8) => write!(f, "uses only CHIP-8 instructions"),
+            Hint::Extension(Extension::SuperChip) => write!(f, "uses SCHIP instructions"),
+            Hint::Extension(Extension::XoChip) => write!(f, "uses XO-CHIP instructions"),
+            Hint::ShiftUsesVy(true) => write!(f, "shifts name a second register"),
+            Hint::ShiftUsesVy(false) => write!(f, "shifts name V0 as the second register"),
+            Hint::LoadStoreIncrementsI(true) => {
+                write!(f, "FX55/FX65 run in sequence without setting I")
+            }
+            Hint::LoadStoreIncrementsI(false) => {
+                write!(f, "FX65 reads back what FX55 stored without setting I")
+            }
+            Hint::JumpUsesVx(true) => write!(f, "BXNN jumps right after setting VX"),
+            Hint::JumpUsesVx(false) => write!(f, "BNNN jumps right after setting V0"),
+            Hint::KnownRom => write!(f, "the ROM database names its platform"),
+        }
+    }
+}
+
+// Guesses the quirks a ROM needs from its instructions and common idioms,
+// starting from the usual platform for its instruction set: the COSMAC VIP
+// for CHIP-8, modern SCHIP and XO-CHIP. Quirks without a telltale idiom
+// keep that platform's setting.
+pub fn suggest(rom: &[u8]) -> Suggestion {
+    let code = reachable_code(&disasm::analyze(rom, START_ADDR));
+    Linter::run(rom, &code).suggest(rom)
+}
+
+// suggest() that trusts the platform `database` lists for the ROM over
+// guesses
+pub fn suggest_with(rom: &[u8], database: &RomDatabase) -> Suggestion {
+    let known = database.lookup(&RomHash::of(rom));
+    match known.and_then(|info| info.variant) {
+        Some(variant) => Suggestion {
+            variant,
+            quirks: variant.quirks(),
+            hints: vec![Hint::KnownRom],
+        },
+        None => suggest(rom),
+    }
+}
+
+// Instructions something other than the previous instruction can lead to
+fn branch_targets(code: &[(u16, Instruction)]) -> BTreeSet<u16> {
+    let mut targets = BTreeSet::new();
+
+    for (index, &(_, instruction)) in code.iter().enumerate() {
+        match instruction {
+            Instruction::Jump(nnn) | Instruction::Call(nnn) => {
+                targets.insert(nnn);
+            }
+            // Skips lead past the next instruction
+            Instruction::SkipEqImm { .. }
+            | Instruction::SkipNeImm { .. }
+            | Instruction::SkipEqReg { .. }
+            | Instruction::SkipNeReg { .. }
+            | Instruction::SkipKeyPressed { .. }
+            | Instruction::SkipKeyNotPressed { .. } => {
+                if let Some(&(skipped, instruction)) = code.get(index + 1) {
+                    targets.insert(skipped.wrapping_add(instruction.size()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    targets
+}
+
+// Votes for quirks from idioms in the code, positive for on
+#[derive(Clone, Copy, Debug, Default)]
+struct Votes {
+    shift_uses_vy: i32,
+    load_store_increments_i: i32,
+    jump_uses_vx: i32,
+}
+
+struct Linter {
+    findings: Vec<Finding>,
+    extension: Extension,
+    votes: Votes,
+    targets: BTreeSet<u16>,
+    resolution: Option<Resolution>,
+    // Values known from the straight code since the last branch target
+    v: [Option<u8>; NUM_V_REGS],
+    i: Option<usize>,
+    written: u16, // registers written since the last branch target, bit X for VX
+    stored_at: Option<(u16, Instruction)>, // FX55/FX65 whose effect on I is still pending
+    reads: Vec<(u16, Range<usize>)>,
+    writes: Vec<Range<usize>>,
+    unknown_writes: bool, // a write through an unknown I, could be anywhere
+}
+
+impl Linter {
+    fn run(rom: &[u8], code: &[(u16, Instruction)]) -> Self {
+        let uses_hires = code.iter().any(|(_, i)| *i == Instruction::Hires);
+        let uses_lores = code.iter().any(|(_, i)| *i == Instruction::Lores);
+        // Both modes make the resolution at a given draw unknown
+        let resolution = match (uses_hires, uses_lores) {
+            (false, _) => Some(Resolution::Low),
+            (true, false) => Some(Resolution::High),
+            (true, true) => None,
+        };
+
+        let mut linter = Linter {
+            findings: Vec::new(),
+            extension: code
+                .iter()
+                .map(|(_, instruction)| instruction.extension())
+                .max()
+                .unwrap_or(Extension::Chip8),
+            votes: Votes::default(),
+            targets: branch_targets(code),
+            resolution,
+            v: [None; NUM_V_REGS],
+            i: None,
+            written: 0,
+            stored_at: None,
+            reads: Vec::new(),
+            writes: Vec::new(),
+            unknown_writes: false,
+        };
+        for &(addr, instruction) in code {
+            linter.visit(addr, instruction);
+        }
+
+        // Reads are only known to be uninitialized once every write is known
+        let program_end = START_ADDR as usize + rom.len();
+        for (addr, range) in core::mem::take(&mut linter.reads) {
+            let uninitialized = range.clone().find(|&target| {
+                let written = linter.unknown_writes
+                    || linter
+                        .writes
+                        .iter()
+                        .any(|written| written.contains(&target));
+                (target >= program_end || RESERVED.contains(&target)) && !written
+            });
+            if let Some(target) = uninitialized {
+                linter.report(addr, Issue::UninitializedRead(target as u16));
+            }
+        }
+        linter.findings.sort_by_key(|finding| finding.addr);
+
+        linter
+    }
+
+    fn into_report(self, rom: &[u8]) -> Report {
+        let compatible = Variant::ALL
+            .into_iter()
+            .filter(|&variant| self.runs_on(variant, rom))
+            .collect();
+
+        Report {
+            findings: self.findings,
+            extension: self.extension,
+            compatible,
+        }
+    }
+
+    fn suggest(&self, rom: &[u8]) -> Suggestion {
+        let base = match self.extension {
+            Extension::Chip8 => Variant::CosmacVip,
+            Extension::SuperChip => Variant::SuperChipModern,
+            Extension::XoChip => Variant::XoChip,
+        };
+
+        let mut quirks = base.quirks();
+        let mut hints = vec![Hint::Extension(self.extension)];
+        let votes = self.votes;
+        if votes.shift_uses_vy != 0 {
+            quirks.shift_uses_vy = votes.shift_uses_vy > 0;
+            hints.push(Hint::ShiftUsesVy(quirks.shift_uses_vy));
+        }
+        if votes.load_store_increments_i != 0 {
+            quirks.load_store_increments_i = votes.load_store_increments_i > 0;
+            hints.push(Hint::LoadStoreIncrementsI(quirks.load_store_increments_i));
+        }
+        if votes.jump_uses_vx != 0 {
+            quirks.jump_uses_vx = votes.jump_uses_vx > 0;
+            hints.push(Hint::JumpUsesVx(quirks.jump_uses_vx));
+        }
+
+        // The platform that runs the ROM with the fewest quirks overridden,
+        // the base platform on ties
+        let differences = |variant: Variant| {
+            let other = variant.quirks();
+            [
+                other.shift_uses_vy != quirks.shift_uses_vy,
+                other.load_store_increments_i != quirks.load_store_increments_i,
+                other.jump_uses_vx != quirks.jump_uses_vx,
+            ]
+            .into_iter()
+            .filter(|&differs| differs)
+            .count()
+        };
+        let variant = Variant::ALL
+            .into_iter()
+            .filter(|&variant| self.runs_on(variant, rom))
+            .min_by_key(|&variant| (differences(variant), variant != base))
+            .unwrap_or(base);
+
+        Suggestion {
+            variant,
+            quirks,
+            hints,
+        }
+    }
+
+    // Whether `variant` has every instruction used and room for the ROM
+    fn runs_on(&self, variant: Variant, rom: &[u8]) -> bool {
+        self.extension <= variant.extension()
+            && rom.len() <= variant.ram_size() - START_ADDR as usize
+    }
+
+    fn report(&mut self, addr: u16, issue: Issue) {
+        if !self.findings.contains(&Finding { addr, issue }) {
+            self.findings.push(Finding { addr, issue });
+        }
+    }
+
+    fn forget(&mut self) {
+        self.v = [None; NUM_V_REGS];
+        self.i = None;
+        self.written = 0;
+        self.stored_at = None;
+    }
+
+    fn read(&mut self, addr: u16, len: usize) {
+        if let Some(i) = self.i {
+            self.reads.push((addr, i..i + len));
+        }
+    }
+
+    fn write(&mut self, len: usize) {
+        match self.i {
+            Some(i) => self.writes.push(i..i + len),
+            None => self.unknown_writes = true,
+        }
+    }
+
+    fn visit(&mut self, addr: u16, instruction: Instruction) {
+        if self.targets.contains(&addr) {
+            self.forget();
+        }
+
+        let reads_i = matches!(
+            instruction,
+            Instruction::Draw { .. }
+                | Instruction::Store { .. }
+                | Instruction::Load { .. }
+                | Instruction::SaveRange { .. }
+                | Instruction::LoadRange { .. }
+                | Instruction::Bcd { .. }
+                | Instruction::AddIndex { .. }
+                | Instruction::LoadAudio
+        );
+        if let (true, Some((stored_at, stored))) = (reads_i, self.stored_at) {
+            self.report(stored_at, Issue::IndexIncrement);
+            // Repeating the same transfer walks through memory, the opposite
+            // one reads back or overwrites the same bytes
+            match (stored, instruction) {
+                (Instruction::Store { .. }, Instruction::Store { .. })
+                | (Instruction::Load { .. }, Instruction::Load { .. }) => {
+                    self.votes.load_store_increments_i += 1
+                }
+                (Instruction::Store { .. }, Instruction::Load { .. })
+                | (Instruction::Load { .. }, Instruction::Store { .. }) => {
+                    self.votes.load_store_increments_i -= 1
+                }
+                _ => {}
+            }
+        }
+
+        match instruction {
+            Instruction::ShiftRight { x, y } | Instruction::ShiftLeft { x, y } if x != y => {
+                self.report(addr, Issue::ShiftSource);
+                // Assemblers write `SHR VX` as 8X06
+                self.votes.shift_uses_vy += if y == 0 { -1 } else { 1 };
+            }
+            Instruction::JumpOffset { x, .. } if x != 0 => {
+                self.report(addr, Issue::JumpOffset);
+                match (self.written & (1 << x) != 0, self.written & 1 != 0) {
+                    (true, false) => self.votes.jump_uses_vx += 1,
+                    (false, true) => self.votes.jump_uses_vx -= 1,
+                    _ => {}
+                }
+            }
+            Instruction::Draw { x, y, n } => self.check_sprite(addr, x, y, n),
+            _ => {}
+        }
+
+        self.track(addr, instruction);
+
+        // Registers after a call or a skipped instruction are unknown
+        if instruction.is_branch() {
+            self.forget();
+        }
+    }
+
+    fn check_sprite(&mut self, addr: u16, x: u8, y: u8, n: u8) {
+        let (width, height) = match n {
+            0 => (16, 16),
+            n => (8, n as usize),
+        };
+        let len = if n == 0 { 32 } else { n as usize };
+        self.read(addr, len);
+
+        let (Some(resolution), Some(vx), Some(vy)) =
+            (self.resolution, self.v[x as usize], self.v[y as usize])
+        else {
+            return;
+        };
+        // The top left corner always wraps
+        let left = vx as usize % resolution.width();
+        let top = vy as usize % resolution.height();
+        if left + width > resolution.width() || top + height > resolution.height() {
+            self.report(addr, Issue::SpriteWrap { x: vx, y: vy });
+        }
+    }
+
+    // Follows the registers, I and the RAM accesses
+    fn track(&mut self, addr: u16, instruction: Instruction) {
+        match instruction {
+            Instruction::SetImm { x, nn } => self.v[x as usize] = Some(nn),
+            Instruction::AddImm { x, nn } => {
+                self.v[x as usize] = self.v[x as usize].map(|vx| vx.wrapping_add(nn))
+            }
+            Instruction::Set { x, y } => self.v[x as usize] = self.v[y as usize],
+            Instruction::SetIndex(nnn) | Instruction::SetIndexLong(nnn) => {
+                self.i = Some(nnn as usize);
+                self.stored_at = None;
+            }
+            Instruction::AddIndex { x } => {
+                self.i = self
+                    .i
+                    .zip(self.v[x as usize])
+                    .map(|(i, vx)| i + vx as usize);
+            }
+            Instruction::Font { .. } | Instruction::BigFont { .. } => {
+                self.i = None;
+                self.stored_at = None;
+            }
+            Instruction::Store { x } => {
+                self.write(x as usize + 1);
+                self.i = None;
+                self.stored_at = Some((addr, instruction));
+            }
+            Instruction::Load { x } => {
+                self.read(addr, x as usize + 1);
+                self.i = None;
+                self.stored_at = Some((addr, instruction));
+            }
+            Instruction::SaveRange { x, y } => self.write(x.abs_diff(y) as usize + 1),
+            Instruction::LoadRange { x, y } => self.read(addr, x.abs_diff(y) as usize + 1),
+            Instruction::Bcd { .. } => self.write(3),
+            Instruction::LoadAudio => self.read(addr, AUDIO_PATTERN_SIZE),
+            _ => {}
+        }
+
+        if let Instruction::SetImm { x, .. }
+        | Instruction::AddImm { x, .. }
+        | Instruction::Set { x, .. } = instruction
+        {
+            self.written |= 1 << x;
+        }
+        let clobbered = clobbered(instruction);
+        self.written |= clobbered;
+        for (x, v) in self.v.iter_mut().enumerate() {
+            if clobbered & (1 << x) != 0 {
+                *v = None;
+            }
+        }
+    }
+}
+
+// Registers an instruction changes in ways track() doesn't follow, bit X for VX
+fn clobbered(instruction: Instruction) -> u16 {
+    let range = |x: u8, y: u8| (2u16 << x.max(y)).wrapping_sub(1 << x.min(y));
+
+    match instruction {
+        Instruction::Or { x, .. }
+        | Instruction::And { x, .. }
+        | Instruction::Xor { x, .. }
+        | Instruction::Add { x, .. }
+        | Instruction::Sub { x, .. }
+        | Instruction::ShiftRight { x, .. }
+        | Instruction::SubReverse { x, .. }
+        | Instruction::ShiftLeft { x, .. } => 1 << x | 1 << 0xF,
+        Instruction::Random { x, .. }
+        | Instruction::GetDelay { x }
+        | Instruction::WaitKey { x } => 1 << x,
+        Instruction::Draw { .. } => 1 << 0xF,
+        Instruction::Load { x } | Instruction::LoadFlags { x } => range(0, x),
+        Instruction::LoadRange { x, y } => range(x, y),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    // CLS; JP 0x202
+    const ROM: [u8; 4] = [0x00, 0xE0, 0x12, 0x02];
+
+    fn database(variant: Option<Variant>) -> RomDatabase {
+        let mut database = RomDatabase::new();
+        let info = RomInfo {
+            title: "Test".to_string(),
+            author: None,
+            variant,
+            keys: Vec::new(),
+        };
+        database.insert(RomHash::of(&ROM).sha1, info);
+        database
+    }
+
+    #[test]
+    fn listed_platforms_win_over_guesses() {
+        let guess = suggest(&ROM);
+        assert_eq!(guess.variant, Variant::CosmacVip);
+
+        let known = suggest_with(&ROM, &database(Some(Variant::SuperChipLegacy)));
+        assert_eq!(known.variant, Variant::SuperChipLegacy);
+        assert_eq!(known.quirks, Variant::SuperChipLegacy.quirks());
+        assert_eq!(known.hints, [Hint::KnownRom]);
+
+        // Known without a platform, or not known at all
+        assert_eq!(suggest_with(&ROM, &database(None)), guess);
+        assert_eq!(suggest_with(&ROM, &RomDatabase::new()), guess);
+    }
+}
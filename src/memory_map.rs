@@ -0,0 +1,90 @@
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+#[cfg(feature = "alloc")]
+use core::fmt::Write;
+use core::ops::Range;
+
+use crate::*;
+
+#[cfg(feature = "alloc")]
+const DUMP_WIDTH: usize = 16; // bytes per hexdump line
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryRegion {
+    Font,     // 4x5 hex digits for FX29
+    BigFont,  // 8x10 digits for FX30
+    Reserved, // rest of the interpreter area below 0x200
+    Program,  // the ROM as loaded by load()
+    Free,     // after the ROM up to 4KB
+    Extended, // XO-CHIP RAM past 4KB
+}
+
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    // The regions of the addressable RAM in address order, empty ones left out
+    pub fn memory_map(&self) -> impl Iterator<Item = (MemoryRegion, Range<usize>)> {
+        let start = START_ADDR as usize;
+        let end = self.ram_size;
+        let program_end = self.program_end.min(DEFAULT_RAM_SIZE);
+        let big_font_end = BIG_FONTSET_ADDR + BIG_FONTSET_SIZE;
+
+        [
+            (MemoryRegion::Font, 0..FONTSET_SIZE),
+            (MemoryRegion::BigFont, BIG_FONTSET_ADDR..big_font_end),
+            (MemoryRegion::Reserved, big_font_end..start),
+            (MemoryRegion::Program, start..self.program_end),
+            (MemoryRegion::Free, program_end..DEFAULT_RAM_SIZE),
+            (
+                MemoryRegion::Extended,
+                self.program_end.max(DEFAULT_RAM_SIZE)..end,
+            ),
+        ]
+        .into_iter()
+        .filter(|(_, range)| !range.is_empty())
+    }
+
+    // None past the end of RAM
+    pub fn region_of(&self, addr: usize) -> Option<MemoryRegion> {
+        self.memory_map()
+            .find(|(_, range)| range.contains(&addr))
+            .map(|(region, _)| region)
+    }
+
+    // None unless `range` lies within the addressable RAM
+    pub fn ram_slice(&self, range: Range<usize>) -> Option<&[u8]> {
+        self.ram().get(range)
+    }
+
+    // Hex and ASCII dump, 16 bytes per line, clipped to the addressable RAM:
+    //
+    //     0200  00 E0 A2 2A 60 0C 61 08  D0 1F 70 09 A2 39 D0 1F  |...*`.a...p..9..|
+    #[cfg(feature = "alloc")]
+    pub fn dump_ram(&self, range: Range<usize>) -> String {
+        let end = range.end.min(self.ram_size);
+        let start = range.start.min(end);
+        let mut out = String::new();
+
+        for (line, bytes) in self.ram[start..end].chunks(DUMP_WIDTH).enumerate() {
+            let _ = write!(out, "{:04X} ", start + line * DUMP_WIDTH);
+            for index in 0..DUMP_WIDTH {
+                if index == DUMP_WIDTH / 2 {
+                    out.push(' ');
+                }
+                match bytes.get(index) {
+                    Some(byte) => {
+                        let _ = write!(out, " {:02X}", byte);
+                    }
+                    None => out.push_str("   "),
+                }
+            }
+
+            out.push_str("  |");
+            for &byte in bytes {
+                let printable = byte.is_ascii_graphic() || byte == b' ';
+                out.push(if printable { byte as char } else { '.' });
+            }
+            out.push_str("|\n");
+        }
+
+        out
+    }
+}
@@ -0,0 +1,37 @@
+use core::time::Duration;
+
+use crate::*;
+
+// Counters since the last reset(), see Emu::metrics()
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    pub instructions: u64,
+    pub frames: u64, // tick_timers() calls
+    pub draws: u64,  // DXYN instructions
+}
+
+impl Metrics {
+    // Millions of instructions per second over `elapsed` of wall-clock time,
+    // which the caller measures since the core has no clock
+    pub fn mips(&self, elapsed: Duration) -> f64 {
+        let seconds = elapsed.as_secs_f64();
+        if seconds == 0.0 {
+            return 0.0;
+        }
+        self.instructions as f64 / seconds / 1_000_000.0
+    }
+
+    // Instructions per 60Hz frame, the usual way to state CHIP-8 speed
+    pub fn instructions_per_frame(&self) -> f64 {
+        if self.frames == 0 {
+            return 0.0;
+        }
+        self.instructions as f64 / self.frames as f64
+    }
+}
+
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    pub fn metrics(&self) -> Metrics {
+        self.metrics
+    }
+}
@@ -0,0 +1,245 @@
+// Key input recorded per frame for deterministic playback. Movies are
+// stored as, big-endian:
+//
+//     magic    "C8MV"
+//     version  u16, currently 1
+//     frames   u32, length of the recording
+//     count    u32, number of events
+//     events   frame u32, key u8, pressed u8 (0 or 1), sorted by frame
+//
+// Playback only reproduces a run if the machine starts from the same state,
+// with the same quirks and random seed.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{Emu, FlagStorage, Key, RandomSource};
+
+const MAGIC: &[u8; 4] = b"C8MV";
+const VERSION: u16 = 1;
+const HEADER_SIZE: usize = 14;
+const EVENT_SIZE: usize = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub frame: u32,
+    pub key: Key,
+    pub pressed: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Movie {
+    pub frames: u32,
+    pub events: Vec<KeyEvent>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovieError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    InvalidEvent(usize), // index of an out of order or out of range event
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::BadMagic => write!(f, "Not a movie"),
+            MovieError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported movie version {}", version)
+            }
+            MovieError::Truncated => write!(f, "Movie is truncated"),
+            MovieError::InvalidEvent(index) => write!(f, "Movie event {} is invalid", index),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MovieError {}
+
+impl Movie {
+    pub fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_be_bytes());
+        out.extend_from_slice(&self.frames.to_be_bytes());
+        out.extend_from_slice(&(self.events.len() as u32).to_be_bytes());
+
+        for event in &self.events {
+            out.extend_from_slice(&event.frame.to_be_bytes());
+            out.push(event.key.into());
+            out.push(event.pressed as u8);
+        }
+    }
+
+    pub fn read(data: &[u8]) -> Result<Movie, MovieError> {
+        if data.len() < HEADER_SIZE {
+            return Err(MovieError::Truncated);
+        }
+        if &data[..4] != MAGIC {
+            return Err(MovieError::BadMagic);
+        }
+
+        let version = u16::from_be_bytes([data[4], data[5]]);
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+
+        let frames = u32::from_be_bytes([data[6], data[7], data[8], data[9]]);
+        let count = u32::from_be_bytes([data[10], data[11], data[12], data[13]]) as usize;
+        let body = &data[HEADER_SIZE..];
+        if body.len() / EVENT_SIZE < count {
+            return Err(MovieError::Truncated);
+        }
+
+        let mut events = Vec::with_capacity(count);
+        for (index, bytes) in body.chunks_exact(EVENT_SIZE).take(count).enumerate() {
+            let key = Key::try_from(bytes[4]).map_err(|_| MovieError::InvalidEvent(index))?;
+            let event = KeyEvent {
+                frame: u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+                key,
+                pressed: bytes[5] != 0,
+            };
+
+            let in_order = events
+                .last()
+                .is_none_or(|last: &KeyEvent| last.frame <= event.frame);
+            if !in_order || event.frame >= frames || bytes[5] > 1 {
+                return Err(MovieError::InvalidEvent(index));
+            }
+            events.push(event);
+        }
+
+        Ok(Movie { frames, events })
+    }
+}
+
+// Records key events and forwards them to the machine. Like MoviePlayer,
+// keys reach the machine only at frame boundaries: call `start_frame`
+// before running each frame and `end_frame` after.
+#[derive(Clone, Debug, Default)]
+pub struct MovieRecorder {
+    movie: Movie,
+    pending: Vec<(Key, bool)>, // since the last start_frame
+}
+
+impl MovieRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Held until the next start_frame
+    pub fn keypress(&mut self, key: Key, pressed: bool) {
+        self.pending.push((key, pressed));
+    }
+
+    // Applies and records the keys pressed since the previous frame
+    pub fn start_frame<F: FlagStorage, R: RandomSource>(&mut self, emu: &mut Emu<F, R>) {
+        for (key, pressed) in self.pending.drain(..) {
+            emu.keypress(key, pressed);
+            self.movie.events.push(KeyEvent {
+                frame: self.movie.frames,
+                key,
+                pressed,
+            });
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        self.movie.frames += 1;
+    }
+
+    // Keys still pending never reached the machine and are left out
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+// Replays a movie. Call `start_frame` before running each frame in place of
+// forwarding the user's input.
+#[derive(Clone, Debug)]
+pub struct MoviePlayer<'a> {
+    movie: &'a Movie,
+    frame: u32,
+    next_event: usize,
+}
+
+impl<'a> MoviePlayer<'a> {
+    pub fn new(movie: &'a Movie) -> Self {
+        Self {
+            movie,
+            frame: 0,
+            next_event: 0,
+        }
+    }
+
+    // Applies the key events of the next frame, false once the movie is over
+    pub fn start_frame<F: FlagStorage, R: RandomSource>(&mut self, emu: &mut Emu<F, R>) -> bool {
+        if self.is_finished() {
+            return false;
+        }
+
+        for event in &self.movie.events[self.next_event..] {
+            if event.frame != self.frame {
+                break;
+            }
+            emu.keypress(event.key, event.pressed);
+            self.next_event += 1;
+        }
+
+        self.frame += 1;
+        true
+    }
+
+    // Frames played so far
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.movie.frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counts the instructions that saw key 5 held in V1:
+    // LD V0, 5; SKNP V0; ADD V1, 1; JP 0x202
+    const PROGRAM: [u8; 8] = [0x60, 0x05, 0xE0, 0xA1, 0x71, 0x01, 0x12, 0x02];
+
+    fn run(emu: &mut Emu, cycles: u32) {
+        for _ in 0..cycles {
+            emu.tick().unwrap();
+        }
+    }
+
+    #[test]
+    fn playback_sees_keys_when_the_recording_did() {
+        let mut emu = Emu::new();
+        emu.load(&PROGRAM).unwrap();
+        let mut recorder = MovieRecorder::new();
+        for frame in 0..10 {
+            recorder.start_frame(&mut emu);
+            // Pressed and released halfway through frames
+            run(&mut emu, 5);
+            if frame == 2 || frame == 6 {
+                recorder.keypress(Key::Key5, frame == 2);
+            }
+            run(&mut emu, 5);
+            recorder.end_frame();
+        }
+        let recorded = emu.v_reg()[1];
+        let movie = recorder.finish();
+
+        let mut emu = Emu::new();
+        emu.load(&PROGRAM).unwrap();
+        let mut player = MoviePlayer::new(&movie);
+        while player.start_frame(&mut emu) {
+            run(&mut emu, 10);
+        }
+        assert_eq!(player.frame(), 10);
+        assert_eq!(emu.v_reg()[1], recorded);
+        assert_ne!(recorded, 0);
+    }
+}
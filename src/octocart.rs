@@ -0,0 +1,170 @@
+// Octo cartridges: GIF images with a program's source and settings hidden
+// in the two low bits of every color index. Taken across all frames in
+// order, each four indices make a byte, most significant bits first. The
+// bytes hold a big-endian u32 length and then that many characters of
+// JSON:
+//
+//     {"program": "<Octo source>", "options": {"tickrate": 20, ...}}
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::asm::{self, AsmError};
+use crate::gif;
+use crate::json::{Json, JsonError};
+use crate::*;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cartridge {
+    pub source: String,
+    pub rom: Vec<u8>, // the source assembled
+    pub quirks: Quirks,
+    pub palette: Palette,
+    pub font: Option<Font>, // None for Octo fonts this crate doesn't have
+    pub instructions_per_frame: Option<u32>, // Octo's tickrate
+    pub ram_size: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CartridgeError {
+    Gif,         // not a GIF or a damaged one
+    MissingData, // a GIF without a program in it
+    Json(JsonError),
+    Asm(AsmError),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::Gif => write!(f, "not a GIF image"),
+            CartridgeError::MissingData => write!(f, "no Octo program in the image"),
+            CartridgeError::Json(error) => write!(f, "damaged cartridge data, {}", error),
+            CartridgeError::Asm(error) => write!(f, "cartridge program: {}", error),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CartridgeError {}
+
+impl Cartridge {
+    pub fn parse(gif: &[u8]) -> Result<Self, CartridgeError> {
+        let gif = gif::decode(gif).ok_or(CartridgeError::Gif)?;
+        let indices: Vec<u8> = gif.frames.concat();
+        let mut bytes = indices
+            .chunks_exact(4)
+            .map(|bits| bits.iter().fold(0, |byte, index| (byte << 2) | (index & 3)));
+
+        let mut len = [0; 4];
+        for byte in &mut len {
+            *byte = bytes.next().ok_or(CartridgeError::MissingData)?;
+        }
+        let len = u32::from_be_bytes(len) as usize;
+        if len > bytes.len() {
+            return Err(CartridgeError::MissingData);
+        }
+        // Octo writes JavaScript's UTF-16 code units truncated to bytes
+        let text: String = bytes.take(len).map(char::from).collect();
+
+        let json = Json::parse(&text).map_err(CartridgeError::Json)?;
+        let source = json
+            .get("program")
+            .as_str()
+            .ok_or(CartridgeError::MissingData)?;
+        let program = asm::octo::assemble(source).map_err(CartridgeError::Asm)?;
+        let options = json.get("options");
+
+        Ok(Cartridge {
+            source: source.into(),
+            rom: program.bytes,
+            quirks: quirks(options),
+            palette: palette(options),
+            font: match options.get("fontStyle").as_str() {
+                Some("octo") | None => Some(Font::Octo),
+                Some("vip") => Some(Font::CosmacVip),
+                Some("dream6800") => Some(Font::Dream6800),
+                Some("eti660") => Some(Font::Eti660),
+                Some(_) => None,
+            },
+            instructions_per_frame: options
+                .get("tickrate")
+                .as_i64()
+                .and_then(|rate| u32::try_from(rate).ok()),
+            // Octo's maxSize is the room for programs
+            ram_size: options
+                .get("maxSize")
+                .as_i64()
+                .map_or(MAX_RAM_SIZE, |size| {
+                    // Clamped as i64 first, a negative size would wrap as usize
+                    let room = (MAX_RAM_SIZE - START_ADDR as usize) as i64;
+                    let size = size.clamp(0, room) as usize + START_ADDR as usize;
+                    size.max(DEFAULT_RAM_SIZE)
+                }),
+        })
+    }
+}
+
+// Octo's quirk options are all off by default, which is XO-CHIP
+fn quirks(options: &Json) -> Quirks {
+    let mut quirks = Variant::XoChip.quirks();
+    let option = |name: &str| options.get(name).as_bool();
+
+    if let Some(shift) = option("shiftQuirks") {
+        quirks.shift_uses_vy = !shift;
+    }
+    if let Some(load_store) = option("loadStoreQuirks") {
+        quirks.load_store_increments_i = !load_store;
+    }
+    if let Some(jump) = option("jumpQuirks") {
+        quirks.jump_uses_vx = jump;
+    }
+    if let Some(logic) = option("logicQuirks") {
+        quirks.vf_reset = logic;
+    }
+    if let Some(clip) = option("clipQuirks") {
+        quirks.clip_sprites = clip;
+    }
+    if let Some(vblank) = option("vBlankQuirks") {
+        quirks.display_wait = vblank;
+    }
+
+    quirks
+}
+
+// Colors are `#RRGGBB` strings, missing or malformed ones keep Octo's default
+fn palette(options: &Json) -> Palette {
+    let mut palette = Palette::default();
+    let names = ["backgroundColor", "fillColor", "fillColor2", "blendColor"];
+
+    for (color, name) in palette.colors.iter_mut().zip(names) {
+        let rgb = options
+            .get(name)
+            .as_str()
+            .and_then(|text| text.strip_prefix('#'))
+            .filter(|hex| hex.len() == 6)
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok());
+        if let Some(rgb) = rgb {
+            let [_, r, g, b] = rgb.to_be_bytes();
+            *color = [r, g, b, 0xFF];
+        }
+    }
+
+    palette
+}
+
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    // Configures the machine as the cartridge says and loads its program.
+    // The palette is left to the frontend.
+    pub fn load_cartridge(&mut self, cartridge: &Cartridge) -> Result<(), LoadError> {
+        self.set_quirks(cartridge.quirks);
+        self.set_ram_size(cartridge.ram_size);
+        if let Some(font) = cartridge.font {
+            self.set_font(font);
+        }
+        if let Some(rate) = cartridge.instructions_per_frame {
+            self.set_instructions_per_second(rate.saturating_mul(60));
+        }
+        self.load(&cartridge.rom)
+    }
+}
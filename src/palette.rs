@@ -0,0 +1,41 @@
+use crate::{Emu, FlagStorage, RandomSource};
+
+// RGBA colors indexed by the 2-bit values of `Emu::get_display_colors`:
+// background, plane 0, plane 1, both planes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [[u8; 4]; 4],
+}
+
+impl Palette {
+    // Two colors for single-plane programs, overlapping planes use the foreground
+    pub fn mono(background: [u8; 4], foreground: [u8; 4]) -> Self {
+        Self {
+            colors: [background, foreground, foreground, foreground],
+        }
+    }
+}
+
+impl Default for Palette {
+    // Octo's default colors
+    fn default() -> Self {
+        Self {
+            colors: [
+                [0x99, 0x66, 0x00, 0xFF],
+                [0xFF, 0xCC, 0x00, 0xFF],
+                [0xFF, 0x66, 0x00, 0xFF],
+                [0x66, 0x22, 0x00, 0xFF],
+            ],
+        }
+    }
+}
+
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    // Writes 4 bytes per pixel, row by row. `buffer` should hold
+    // width * height * 4 bytes, pixels that don't fit are skipped.
+    pub fn render_rgba(&self, buffer: &mut [u8], palette: &Palette) {
+        for (pixel, color) in buffer.chunks_exact_mut(4).zip(self.get_display_colors()) {
+            pixel.copy_from_slice(&palette.colors[color as usize]);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use crate::*;
+
+const LIT: u8 = 0xFF;
+
+// The afterglow of a CRT: pixels that go out fade to black over a number of
+// frames instead of vanishing, hiding the flicker of sprites erased and
+// redrawn every frame. Call `update` once per frame and draw `levels`
+// instead of the display. A pixel is lit if it is in either plane.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Phosphor {
+    levels: [u8; SCREEN_SIZE], // 0 black to 255 lit
+    resolution: Resolution,
+    fade_frames: u8,
+}
+
+impl Phosphor {
+    // 0 frames turns pixels off right away like the display does
+    pub fn new(fade_frames: u8) -> Self {
+        Self {
+            levels: [0; SCREEN_SIZE],
+            resolution: Resolution::Low,
+            fade_frames,
+        }
+    }
+
+    pub fn fade_frames(&self) -> u8 {
+        self.fade_frames
+    }
+
+    pub fn set_fade_frames(&mut self, fade_frames: u8) {
+        self.fade_frames = fade_frames;
+    }
+
+    pub fn update<F: FlagStorage, R: RandomSource>(&mut self, emu: &Emu<F, R>) {
+        // Nothing carries over between resolutions
+        if emu.resolution() != self.resolution {
+            self.resolution = emu.resolution();
+            self.clear();
+        }
+
+        // Off pixels lose an equal share each frame
+        let fade = match self.fade_frames {
+            0 => LIT,
+            frames => LIT.div_ceil(frames),
+        };
+        for (level, color) in self.levels.iter_mut().zip(emu.get_display_colors()) {
+            *level = match color {
+                0 => level.saturating_sub(fade),
+                _ => LIT,
+            };
+        }
+    }
+
+    // One brightness per pixel, row by row, at the resolution of the last update
+    pub fn levels(&self) -> &[u8] {
+        &self.levels[..self.resolution.width() * self.resolution.height()]
+    }
+
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    pub fn clear(&mut self) {
+        self.levels = [0; SCREEN_SIZE];
+    }
+}
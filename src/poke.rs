@@ -0,0 +1,55 @@
+use crate::*;
+
+// Direct writes to the machine for debuggers, trainers and test fixtures,
+// see Emu::poke(). Values are only checked to stay within range, and
+// step_back() can't undo any of them.
+pub struct Poke<'a, F: FlagStorage, R: RandomSource> {
+    emu: &'a mut Emu<F, R>,
+}
+
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    pub fn poke(&mut self) -> Poke<'_, F, R> {
+        Poke { emu: self }
+    }
+}
+
+impl<F: FlagStorage, R: RandomSource> Poke<'_, F, R> {
+    // Also resumes a halted or spinning machine
+    pub fn set_pc(&mut self, pc: u16) -> &mut Self {
+        self.emu.pc = pc;
+        self.emu.run_state = RunState::Running;
+        self
+    }
+
+    // Panics if `x` isn't 0x0-0xF
+    pub fn set_v(&mut self, x: usize, value: u8) -> &mut Self {
+        self.emu.v_reg[x] = value;
+        self
+    }
+
+    pub fn set_i(&mut self, i: u16) -> &mut Self {
+        self.emu.i_reg = i;
+        self
+    }
+
+    pub fn set_dt(&mut self, dt: u8) -> &mut Self {
+        self.emu.dt = dt;
+        self
+    }
+
+    pub fn set_st(&mut self, st: u8) -> &mut Self {
+        self.emu.st = st;
+        self
+    }
+
+    // Writes nothing and returns false if `data` doesn't fit in RAM at `addr`
+    pub fn write_ram(&mut self, addr: usize, data: &[u8]) -> bool {
+        let end = addr.saturating_add(data.len());
+        if end > self.emu.ram_size {
+            return false;
+        }
+
+        self.emu.ram[addr..end].copy_from_slice(data);
+        true
+    }
+}
@@ -0,0 +1,157 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::*;
+
+// Execution timeline in Chrome's trace event format, for chrome://tracing or
+// Perfetto. Time is counted in instructions, each one lasting 1us, so the
+// timeline shows where the instructions go rather than wall-clock time.
+// Frames are on one track, subroutines and instructions on another.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Profiler {
+    enabled: bool,
+    instructions: bool, // an event per instruction, large
+    events: Vec<Event>,
+    time: u64,
+    frame_start: u64,
+    depth: usize, // open subroutine spans
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Event {
+    Frame {
+        start: u64,
+        end: u64,
+    },
+    Instruction {
+        pc: u16,
+        instruction: Instruction,
+        time: u64,
+    },
+    Call {
+        target: u16,
+        time: u64,
+    },
+    Return {
+        time: u64,
+    },
+}
+
+const FRAME_TRACK: u32 = 1;
+const CODE_TRACK: u32 = 2;
+
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    // Starts a new profile, discarding the previous one. With `instructions`
+    // every instruction becomes an event, otherwise only frames and subroutines.
+    pub fn start_profile(&mut self, instructions: bool) {
+        self.profiler = profile::Profiler {
+            enabled: true,
+            instructions,
+            ..Default::default()
+        };
+    }
+
+    // Stops recording, the profile stays available
+    pub fn stop_profile(&mut self) {
+        self.profiler.enabled = false;
+    }
+
+    // The profile as a trace event JSON document
+    pub fn profile_json(&self) -> String {
+        let mut json = String::from("{\"traceEvents\":[");
+
+        let _ = write!(
+            json,
+            "\n{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":\"frames\"}}}},\
+             \n{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":\"code\"}}}}",
+            FRAME_TRACK, CODE_TRACK
+        );
+
+        for event in &self.profiler.events {
+            json.push_str(",\n");
+            let _ = match *event {
+                Event::Frame { start, end } => write!(
+                    json,
+                    "{{\"name\":\"frame\",\"ph\":\"X\",\"pid\":1,\"tid\":{},\"ts\":{},\"dur\":{}}}",
+                    FRAME_TRACK,
+                    start,
+                    end - start
+                ),
+                Event::Instruction {
+                    pc,
+                    instruction,
+                    time,
+                } => write!(
+                    json,
+                    "{{\"name\":\"{}\",\"ph\":\"X\",\"pid\":1,\"tid\":{},\"ts\":{},\"dur\":1,\"args\":{{\"pc\":\"{:#05X}\"}}}}",
+                    instruction, CODE_TRACK, time, pc
+                ),
+                Event::Call { target, time } => write!(
+                    json,
+                    "{{\"name\":\"sub {:#05X}\",\"ph\":\"B\",\"pid\":1,\"tid\":{},\"ts\":{}}}",
+                    target, CODE_TRACK, time
+                ),
+                Event::Return { time } => write!(
+                    json,
+                    "{{\"ph\":\"E\",\"pid\":1,\"tid\":{},\"ts\":{}}}",
+                    CODE_TRACK, time
+                ),
+            };
+        }
+
+        json.push_str("\n]}\n");
+        json
+    }
+
+    // Called after executing the instruction at `pc`
+    pub(crate) fn record_profile(&mut self, pc: u16, instruction: Instruction) {
+        let profiler = &mut self.profiler;
+        if !profiler.enabled {
+            return;
+        }
+
+        let time = profiler.time;
+        if profiler.instructions {
+            profiler.events.push(Event::Instruction {
+                pc,
+                instruction,
+                time,
+            });
+        }
+        profiler.time += 1;
+
+        match instruction {
+            // The span starts after the CALL, which belongs to the caller
+            Instruction::Call(target) => {
+                profiler.events.push(Event::Call {
+                    target,
+                    time: profiler.time,
+                });
+                profiler.depth += 1;
+            }
+            // Returns from before the profile started have no span to end
+            Instruction::Ret if profiler.depth > 0 => {
+                profiler.events.push(Event::Return {
+                    time: profiler.time,
+                });
+                profiler.depth -= 1;
+            }
+            _ => {}
+        }
+    }
+
+    // Called on every timer tick
+    pub(crate) fn record_profile_frame(&mut self) {
+        let profiler = &mut self.profiler;
+        if !profiler.enabled {
+            return;
+        }
+
+        profiler.events.push(Event::Frame {
+            start: profiler.frame_start,
+            end: profiler.time,
+        });
+        profiler.frame_start = profiler.time;
+    }
+}
@@ -0,0 +1,314 @@
+use core::fmt;
+use core::str::FromStr;
+
+use crate::Extension;
+
+// Behaviors that differ between CHIP-8 interpreters. The defaults match
+// the original behavior of this crate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    // 8XY6/8XYE shift VY into VX instead of shifting VX
+    pub shift_uses_vy: bool,
+    // FX55/FX65 leave I at I + X + 1
+    pub load_store_increments_i: bool,
+    // BXNN jumps to XNN + VX instead of NNN + V0
+    pub jump_uses_vx: bool,
+    // 8XY1/8XY2/8XY3 reset VF to 0
+    pub vf_reset: bool,
+    // Sprite pixels past the screen edge are clipped instead of wrapping
+    pub clip_sprites: bool,
+    // DXYN waits for the next vertical blank
+    pub display_wait: bool,
+    pub lores_dxy0: LoresDxy0,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: false,
+            vf_reset: false,
+            clip_sprites: false,
+            display_wait: false,
+            lores_dxy0: LoresDxy0::Sprite16x16,
+        }
+    }
+}
+
+// How DXY0 behaves in lores mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoresDxy0 {
+    Sprite16x16, // modern SCHIP / XO-CHIP
+    Sprite8x16,  // original SCHIP 1.1
+}
+
+// Platforms with a known quirk and memory configuration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    CosmacVip,
+    Chip48,
+    SuperChipLegacy, // SCHIP 1.1 on the HP48
+    SuperChipModern, // SCHIP as implemented by Octo and most modern interpreters
+    XoChip,
+}
+
+impl Variant {
+    // In historical order
+    pub const ALL: [Variant; 5] = [
+        Variant::CosmacVip,
+        Variant::Chip48,
+        Variant::SuperChipLegacy,
+        Variant::SuperChipModern,
+        Variant::XoChip,
+    ];
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Variant::CosmacVip => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                vf_reset: true,
+                clip_sprites: true,
+                display_wait: true,
+                lores_dxy0: LoresDxy0::Sprite8x16,
+            },
+            Variant::Chip48 => Quirks {
+                shift_uses_vy: false,
+                load_store_increments_i: true,
+                jump_uses_vx: true,
+                vf_reset: false,
+                clip_sprites: true,
+                display_wait: false,
+                lores_dxy0: LoresDxy0::Sprite8x16,
+            },
+            Variant::SuperChipLegacy => Quirks {
+                shift_uses_vy: false,
+                load_store_increments_i: false,
+                jump_uses_vx: true,
+                vf_reset: false,
+                clip_sprites: true,
+                display_wait: false,
+                lores_dxy0: LoresDxy0::Sprite8x16,
+            },
+            Variant::SuperChipModern => Quirks {
+                shift_uses_vy: false,
+                load_store_increments_i: false,
+                jump_uses_vx: true,
+                vf_reset: false,
+                clip_sprites: true,
+                display_wait: false,
+                lores_dxy0: LoresDxy0::Sprite16x16,
+            },
+            Variant::XoChip => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                vf_reset: false,
+                clip_sprites: false,
+                display_wait: false,
+                lores_dxy0: LoresDxy0::Sprite16x16,
+            },
+        }
+    }
+
+    // The largest instruction set the platform runs
+    pub fn extension(self) -> Extension {
+        match self {
+            Variant::CosmacVip | Variant::Chip48 => Extension::Chip8,
+            Variant::SuperChipLegacy | Variant::SuperChipModern => Extension::SuperChip,
+            Variant::XoChip => Extension::XoChip,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Variant::CosmacVip => "cosmac-vip",
+            Variant::Chip48 => "chip-48",
+            Variant::SuperChipLegacy => "schip-legacy",
+            Variant::SuperChipModern => "schip-modern",
+            Variant::XoChip => "xo-chip",
+        }
+    }
+
+    pub fn ram_size(self) -> usize {
+        match self {
+            Variant::XoChip => crate::MAX_RAM_SIZE,
+            _ => crate::DEFAULT_RAM_SIZE,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseVariantError;
+
+impl fmt::Display for ParseVariantError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown variant, expected one of")?;
+        for (i, variant) in Variant::ALL.iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            write!(f, "{}{}", separator, variant)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseVariantError {}
+
+// Parses the names Display produces, ignoring case
+impl FromStr for Variant {
+    type Err = ParseVariantError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Variant::ALL
+            .into_iter()
+            .find(|variant| variant.name().eq_ignore_ascii_case(name))
+            .ok_or(ParseVariantError)
+    }
+}
+
+// Names used by the command-line tools, e.g. `schip-modern`
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+    use crate::{asm, Emu, RunState};
+
+    // Tries every quirk the way quirk test ROMs do and draws a digit for
+    // each in the top row, 1 where the quirk showed: vf_reset, shift_uses_vy,
+    // load_store_increments_i, jump_uses_vx, clip_sprites, display_wait
+    const PROBE: &str = "
+                LD VF, 5            ; vf_reset
+                OR V1, V1
+                LD VA, 1
+                SE VF, 0
+                LD VA, 0
+
+                LD V1, 1            ; shift_uses_vy
+                LD V2, 4
+                SHR V1, V2
+                LD VB, 0
+                SNE V1, 2
+                LD VB, 1
+
+                LD V0, 0xAA         ; load_store_increments_i
+                LD V1, 0xBB
+                LD I, scratch
+                LD [I], V1
+                LD V0, [I]
+                LD VC, 1
+                SNE V0, 0xAA
+                LD VC, 0
+
+                LD V0, 0            ; jump_uses_vx, lands 4 bytes further with V2
+                LD V2, 4
+                JP V0, jump
+        jump:   LD VD, 0
+                JP jumped
+                LD VD, 1
+        jumped:
+
+                LD I, bar           ; clip_sprites, does the bar wrap onto the pixel?
+                LD V0, 60
+                LD V1, 31
+                DRW V0, V1, 1
+                LD I, pixel
+                LD V2, 0
+                DRW V2, V1, 1
+                LD VE, 1
+                SE VF, 0
+                LD VE, 0
+                DRW V2, V1, 1
+                LD I, bar
+                DRW V0, V1, 1
+
+                LD I, pixel         ; display_wait, how many draws fit in 3 frames?
+                LD V5, 3
+                LD DT, V5
+                LD V6, 0
+        wait:   DRW V2, V1, 1
+                DRW V2, V1, 1
+                ADD V6, 1
+                LD V5, DT
+                SE V5, 0
+                JP wait
+                LD V7, 10
+                SUB V7, V6
+                LD V9, VF
+
+                LD V3, 0
+                LD V4, 0
+                LD F, VA
+                DRW V3, V4, 5
+                ADD V3, 5
+                LD F, VB
+                DRW V3, V4, 5
+                ADD V3, 5
+                LD F, VC
+                DRW V3, V4, 5
+                ADD V3, 5
+                LD F, VD
+                DRW V3, V4, 5
+                ADD V3, 5
+                LD F, VE
+                DRW V3, V4, 5
+                ADD V3, 5
+                LD F, V9
+                DRW V3, V4, 5
+        done:   JP done
+
+        bar:    DB 0xFF
+        pixel:  DB 0x80
+        scratch: DB 0, 0, 0xCC
+    ";
+
+    // The digits in the top row of the screen, None where there's no 0 or 1
+    fn read_digits(emu: &Emu) -> [Option<bool>; 6] {
+        let glyph = |slot: usize| -> [u8; 5] {
+            let x = slot * 5;
+            core::array::from_fn(|y| (emu.screen[0][y] >> (64 - 8 - x)) as u8 & 0xF0)
+        };
+        let zero = &emu.font[..5];
+        let one = &emu.font[5..10];
+
+        core::array::from_fn(|slot| match glyph(slot) {
+            glyph if glyph == zero => Some(false),
+            glyph if glyph == one => Some(true),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn variants_show_their_quirks() {
+        let probe = asm::assemble(PROBE).unwrap();
+
+        for variant in Variant::ALL {
+            let mut emu = Emu::with_variant(variant);
+            emu.load(&probe.bytes).unwrap();
+            for _ in 0..60 {
+                if emu.run_frame(100).unwrap().run_state == RunState::Spinning {
+                    break;
+                }
+            }
+            assert_eq!(emu.run_state(), RunState::Spinning, "{:?}", variant);
+
+            let quirks = variant.quirks();
+            let expected = [
+                quirks.vf_reset,
+                quirks.shift_uses_vy,
+                quirks.load_store_increments_i,
+                quirks.jump_uses_vx,
+                quirks.clip_sprites,
+                quirks.display_wait,
+            ];
+            assert_eq!(read_digits(&emu), expected.map(Some), "{:?}", variant);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+// Source of the random numbers returned by CXNN, e.g. a hardware RNG on a
+// microcontroller. Deterministic sources should support seeding and saving
+// their state so replays, save states and stepping back reproduce them.
+pub trait RandomSource {
+    fn next_byte(&mut self) -> u8;
+
+    // Restarts the sequence, sources that can't be seeded ignore it
+    fn reseed(&mut self, _seed: u64) {}
+
+    // Everything needed to continue the sequence later, 0 if it can't be restored
+    fn state(&self) -> u128 {
+        0
+    }
+
+    fn set_state(&mut self, _state: u128) {}
+}
+
+// xorshift64*, the default source. Small and fast, good enough for games.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Xorshift {
+    state: u64, // never 0
+}
+
+impl Xorshift {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Self { state: 1 };
+        rng.reseed(seed);
+        rng
+    }
+}
+
+impl RandomSource for Xorshift {
+    fn next_byte(&mut self) -> u8 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+
+        // The high bits are the best distributed
+        (x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        // SplitMix64 spreads similar seeds apart and maps 0 to a valid state
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        self.state = if z == 0 { 1 } else { z };
+    }
+
+    fn state(&self) -> u128 {
+        self.state as u128
+    }
+
+    fn set_state(&mut self, state: u128) {
+        if state as u64 != 0 {
+            self.state = state as u64;
+        }
+    }
+}
+
+// PCG32 from the `oorandom` crate
+#[cfg(feature = "oorandom")]
+impl RandomSource for oorandom::Rand32 {
+    fn next_byte(&mut self) -> u8 {
+        (self.rand_u32() >> 24) as u8
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        *self = oorandom::Rand32::new(seed);
+    }
+
+    fn state(&self) -> u128 {
+        let (state, increment) = oorandom::Rand32::state(self);
+        ((state as u128) << 64) | increment as u128
+    }
+
+    fn set_state(&mut self, state: u128) {
+        *self = oorandom::Rand32::from_state(((state >> 64) as u64, state as u64));
+    }
+}
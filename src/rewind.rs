@@ -0,0 +1,95 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::{Emu, FlagStorage, RandomSource};
+
+// Ring of recent save states for stepping back in time. Call `record` once
+// per frame and `step_back` to restore the frames in reverse order. The
+// oldest states are dropped to stay within the memory budget.
+#[derive(Clone, Debug)]
+pub struct Rewind {
+    states: VecDeque<Vec<u8>>,
+    budget: usize, // bytes
+    used: usize,
+    interval: u32, // frames between recorded states
+    frames_since_record: u32,
+}
+
+impl Rewind {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            states: VecDeque::new(),
+            budget,
+            used: 0,
+            interval: 1,
+            frames_since_record: 0,
+        }
+    }
+
+    // Records every `interval` frames, each step back then goes back that far
+    pub fn set_interval(&mut self, interval: u32) {
+        self.interval = interval.max(1);
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict();
+    }
+
+    pub fn record<F: FlagStorage, R: RandomSource>(&mut self, emu: &Emu<F, R>) {
+        self.frames_since_record += 1;
+        if self.frames_since_record < self.interval {
+            return;
+        }
+        self.frames_since_record = 0;
+
+        let mut state = Vec::new();
+        #[cfg(feature = "compression")]
+        emu.save_state_compressed(&mut state);
+        #[cfg(not(feature = "compression"))]
+        emu.save_state(&mut state);
+
+        self.used += state.len();
+        self.states.push_back(state);
+        self.evict();
+    }
+
+    // Restores the most recent state and forgets it, false once there's nothing left
+    pub fn step_back<F: FlagStorage, R: RandomSource>(&mut self, emu: &mut Emu<F, R>) -> bool {
+        let state = match self.states.pop_back() {
+            Some(state) => state,
+            None => return false,
+        };
+
+        self.used -= state.len();
+        self.frames_since_record = 0;
+        emu.load_state(&state).is_ok()
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.states.clear();
+        self.used = 0;
+        self.frames_since_record = 0;
+    }
+
+    fn evict(&mut self) {
+        while self.used > self.budget {
+            match self.states.pop_front() {
+                Some(state) => self.used -= state.len(),
+                None => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+// PackBits-style run-length encoding. Each packet starts with a control byte:
+//
+//     0..=127    the next N + 1 bytes are copied as is
+//     128..=255  the next byte is repeated N - 126 times (2..=129)
+
+use alloc::vec::Vec;
+
+const MAX_LITERAL: usize = 128;
+const MAX_RUN: usize = 129;
+
+pub fn compress(data: &[u8], out: &mut Vec<u8>) {
+    let mut literal_start = 0;
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let byte = data[pos];
+        let run = data[pos..]
+            .iter()
+            .take(MAX_RUN)
+            .take_while(|&&b| b == byte)
+            .count();
+
+        // A run of 2 only pays off when it doesn't split a literal
+        if run >= 3 || (run == 2 && literal_start == pos) {
+            flush_literal(&data[literal_start..pos], out);
+            out.push((run + 126) as u8);
+            out.push(byte);
+            pos += run;
+            literal_start = pos;
+        } else {
+            pos += 1;
+            if pos - literal_start == MAX_LITERAL {
+                flush_literal(&data[literal_start..pos], out);
+                literal_start = pos;
+            }
+        }
+    }
+
+    flush_literal(&data[literal_start..], out);
+}
+
+fn flush_literal(literal: &[u8], out: &mut Vec<u8>) {
+    if !literal.is_empty() {
+        out.push((literal.len() - 1) as u8);
+        out.extend_from_slice(literal);
+    }
+}
+
+// None if the data ends in the middle of a packet or would decode to more
+// than `max_len` bytes
+pub fn decompress(mut data: &[u8], max_len: usize, out: &mut Vec<u8>) -> Option<()> {
+    let limit = out.len() + max_len;
+    while let Some((&control, rest)) = data.split_first() {
+        if control < 128 {
+            let len = control as usize + 1;
+            if out.len() + len > limit {
+                return None;
+            }
+            out.extend_from_slice(rest.get(..len)?);
+            data = &rest[len..];
+        } else {
+            let len = control as usize - 126;
+            if out.len() + len > limit {
+                return None;
+            }
+            let byte = *rest.first()?;
+            out.resize(out.len() + len, byte);
+            data = &rest[1..];
+        }
+    }
+
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut data = Vec::from(*b"abcabc");
+        data.resize(300, 0);
+        data.extend_from_slice(b"xyzzy");
+        let mut packed = Vec::new();
+        compress(&data, &mut packed);
+        assert!(packed.len() < 20);
+
+        let mut unpacked = Vec::new();
+        decompress(&packed, data.len(), &mut unpacked).unwrap();
+        assert_eq!(unpacked, data);
+    }
+
+    #[test]
+    fn output_past_the_limit_is_rejected() {
+        // Two runs of 129 zeros
+        let packed = [255, 0, 255, 0];
+        let mut out = Vec::new();
+        assert_eq!(decompress(&packed, 257, &mut out), None);
+        assert!(out.len() <= 257);
+
+        out.clear();
+        assert_eq!(decompress(&packed, 258, &mut out), Some(()));
+        assert_eq!(out.len(), 258);
+    }
+
+    #[test]
+    fn truncated_packets_are_rejected() {
+        let mut out = Vec::new();
+        assert_eq!(decompress(&[3, 1, 2], 16, &mut out), None);
+        assert_eq!(decompress(&[200], 16, &mut out), None);
+    }
+}
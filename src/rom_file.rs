@@ -0,0 +1,59 @@
+use std::io::{self, Read};
+use std::path::Path;
+use std::vec::Vec;
+use std::{error, fmt, fs};
+
+use crate::*;
+
+#[derive(Debug)]
+pub enum ReadRomError {
+    Io(io::Error), // the file is missing, unreadable, ...
+    Load(LoadError),
+}
+
+impl fmt::Display for ReadRomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadRomError::Io(error) if error.kind() == io::ErrorKind::NotFound => {
+                write!(f, "ROM not found")
+            }
+            ReadRomError::Io(error) => write!(f, "couldn't read the ROM: {}", error),
+            ReadRomError::Load(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl error::Error for ReadRomError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ReadRomError::Io(error) => Some(error),
+            ReadRomError::Load(error) => Some(error),
+        }
+    }
+}
+
+impl From<io::Error> for ReadRomError {
+    fn from(error: io::Error) -> Self {
+        ReadRomError::Io(error)
+    }
+}
+
+impl From<LoadError> for ReadRomError {
+    fn from(error: LoadError) -> Self {
+        ReadRomError::Load(error)
+    }
+}
+
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    // load() for the contents of a file
+    pub fn load_rom_file(&mut self, path: impl AsRef<Path>) -> Result<(), ReadRomError> {
+        self.load_from_reader(fs::File::open(path)?)
+    }
+
+    // load() for everything `reader` returns until its end
+    pub fn load_from_reader(&mut self, mut reader: impl Read) -> Result<(), ReadRomError> {
+        let mut rom = Vec::new();
+        reader.read_to_end(&mut rom)?;
+        Ok(self.load(&rom)?)
+    }
+}
@@ -0,0 +1,124 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{RomHash, Variant};
+
+// What a database knows about a ROM, for frontends to title it, pick a
+// platform and label controls
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RomInfo {
+    pub title: String,
+    pub author: Option<String>,
+    pub variant: Option<Variant>, // the platform it was written for
+    pub keys: Vec<(String, u8)>,  // what the game uses each key for, e.g. ("up", 5)
+}
+
+// ROM metadata by SHA-1, one tab separated line per ROM:
+//
+//     sha1	title	author	variant	keys
+//
+// with `-` for an unknown author or variant and keys as `up=5,down=8`.
+// Empty lines and lines starting with `#` are skipped. No table comes with
+// the crate, frontends load one of their own.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RomDatabase {
+    roms: BTreeMap<[u8; 20], RomInfo>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RomDatabaseError {
+    pub line: usize, // 1-based
+}
+
+impl fmt::Display for RomDatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}: expected a SHA-1, title, author, variant and keys",
+            self.line
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RomDatabaseError {}
+
+impl RomDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, RomDatabaseError> {
+        let mut database = Self::new();
+
+        for (index, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = RomDatabaseError { line: index + 1 };
+            let (sha1, info) = parse_line(line).ok_or(error)?;
+            database.insert(sha1, info);
+        }
+
+        Ok(database)
+    }
+
+    pub fn insert(&mut self, sha1: [u8; 20], info: RomInfo) {
+        self.roms.insert(sha1, info);
+    }
+
+    pub fn lookup(&self, hash: &RomHash) -> Option<&RomInfo> {
+        self.roms.get(&hash.sha1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+}
+
+fn parse_line(line: &str) -> Option<([u8; 20], RomInfo)> {
+    let mut fields = line.split('\t');
+    let mut field = || fields.next().map(str::trim);
+
+    let hex = field()?;
+    let title = field()?;
+    let author = field()?;
+    let variant = field()?;
+    let keys = field()?;
+    if fields.next().is_some() || hex.len() != 40 || title.is_empty() {
+        return None;
+    }
+
+    let mut sha1 = [0; 20];
+    for (byte, digits) in sha1.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(core::str::from_utf8(digits).ok()?, 16).ok()?;
+    }
+
+    let info = RomInfo {
+        title: title.to_string(),
+        author: (author != "-").then(|| author.to_string()),
+        variant: match variant {
+            "-" => None,
+            name => Some(name.parse().ok()?),
+        },
+        keys: keys
+            .split(',')
+            .filter(|key| !key.is_empty() && *key != "-")
+            .map(|key| {
+                let (action, key) = key.split_once('=')?;
+                let key = u8::from_str_radix(key.trim(), 16)
+                    .ok()
+                    .filter(|&k| k < 16)?;
+                Some((action.trim().to_string(), key))
+            })
+            .collect::<Option<_>>()?,
+    };
+
+    Some((sha1, info))
+}
@@ -0,0 +1,568 @@
+// Binary save states. All integers are big-endian.
+//
+//     magic     "C8ST"
+//     version   u16, currently 3
+//     length    u32, payload bytes
+//     payload
+//     checksum  u32, Adler-32 of the payload
+//
+// Version 1 payload:
+//
+//     pc u16, run_state u8 (0 running, 1 waiting for vblank, 2 halted, 3 spinning)
+//     ram_size u32, ram[..ram_size]
+//     resolution u8 (0 low, 1 high), plane_mask u8
+//     screen, 1024 bytes per plane, 8 pixels per byte, MSB first, rows as
+//         wide as the resolution, zero padded
+//     quirks u8 (bits 0-5: shift_uses_vy, load_store_increments_i, jump_uses_vx,
+//         vf_reset, clip_sprites, display_wait), lores_dxy0 u8 (0 16x16, 1 8x16)
+//     v_reg[16], i_reg u16, stack[16] u16, sp u8, dt u8, st u8
+//     audio_pattern[16], audio_position f32, pitch u8
+//     keys u16, bit N for key N
+//
+// Version 2 appends:
+//
+//     has_seed u8 (0 or 1), seed u64, rng state u128 (see RandomSource::state)
+//
+// Version 3 appends:
+//
+//     instructions u64, frames u64, draws u64 (see Metrics)
+//     sounding u8 (0 or 1), keys_polled u16, waited_for_key u8 (0 or 1)
+//     font[80], big_font[160], program_end u32
+//     audio_loaded u8 (0 or 1), F002 ran since reset
+//
+// Older states leave what they don't have as it is.
+//
+// The flag storage isn't part of the state since it outlives the machine.
+//
+// With the `compression` feature, compressed states are "C8SZ", the u32 length
+// of the uncompressed state, then the state run-length encoded as in `rle`.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+#[cfg(feature = "compression")]
+use crate::rle;
+use crate::*;
+
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u16 = 3;
+const HEADER_SIZE: usize = 10;
+const CHECKSUM_SIZE: usize = 4;
+#[cfg(feature = "compression")]
+const COMPRESSED_MAGIC: &[u8; 4] = b"C8SZ";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateError {
+    BadMagic,
+    UnsupportedVersion(u16), // written by a newer version of the crate
+    Truncated,
+    ChecksumMismatch,
+    InvalidValue, // a field outside its valid range
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "Not a save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported save state version {}", version)
+            }
+            StateError::Truncated => write!(f, "Save state is truncated"),
+            StateError::ChecksumMismatch => write!(f, "Save state is corrupted"),
+            StateError::InvalidValue => write!(f, "Save state contains an invalid value"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for StateError {}
+
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    // Appends a snapshot of the machine to `out`
+    pub fn save_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_be_bytes());
+
+        let length_pos = out.len();
+        out.extend_from_slice(&[0; 4]);
+        let payload_start = out.len();
+
+        out.extend_from_slice(&self.pc.to_be_bytes());
+        out.push(match self.run_state {
+            RunState::Running => 0,
+            RunState::WaitingForVblank => 1,
+            RunState::Halted => 2,
+            RunState::Spinning => 3,
+        });
+
+        out.extend_from_slice(&(self.ram_size as u32).to_be_bytes());
+        out.extend_from_slice(&self.ram[..self.ram_size]);
+
+        out.push(match self.resolution {
+            Resolution::Low => 0,
+            Resolution::High => 1,
+        });
+        out.push(self.plane_mask);
+        let row_bytes = self.resolution.width() / 8;
+        for rows in &self.screen {
+            let start = out.len();
+            for row in &rows[..self.resolution.height()] {
+                out.extend_from_slice(&row.to_be_bytes()[16 - row_bytes..]);
+            }
+            out.resize(start + SCREEN_SIZE / 8, 0);
+        }
+
+        let quirks = &self.quirks;
+        let quirk_bits = [
+            quirks.shift_uses_vy,
+            quirks.load_store_increments_i,
+            quirks.jump_uses_vx,
+            quirks.vf_reset,
+            quirks.clip_sprites,
+            quirks.display_wait,
+        ];
+        let quirk_bits = quirk_bits
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, &set)| bits | ((set as u8) << i));
+        out.push(quirk_bits);
+        out.push(match quirks.lores_dxy0 {
+            LoresDxy0::Sprite16x16 => 0,
+            LoresDxy0::Sprite8x16 => 1,
+        });
+
+        out.extend_from_slice(&self.v_reg);
+        out.extend_from_slice(&self.i_reg.to_be_bytes());
+        for addr in &self.stack {
+            out.extend_from_slice(&addr.to_be_bytes());
+        }
+        out.push(self.sp as u8);
+        out.push(self.dt);
+        out.push(self.st);
+
+        out.extend_from_slice(&self.audio_pattern);
+        out.extend_from_slice(&self.audio_position.to_bits().to_be_bytes());
+        out.push(self.pitch);
+
+        let keys = self
+            .keys
+            .iter()
+            .enumerate()
+            .fold(0u16, |bits, (i, &pressed)| bits | ((pressed as u16) << i));
+        out.extend_from_slice(&keys.to_be_bytes());
+
+        out.push(self.seed.is_some() as u8);
+        out.extend_from_slice(&self.seed.unwrap_or(0).to_be_bytes());
+        out.extend_from_slice(&self.rng.state().to_be_bytes());
+
+        let metrics = &self.metrics;
+        for counter in [metrics.instructions, metrics.frames, metrics.draws] {
+            out.extend_from_slice(&counter.to_be_bytes());
+        }
+        out.push(self.sounding as u8);
+        out.extend_from_slice(&self.keys_polled.to_be_bytes());
+        out.push(self.waited_for_key as u8);
+        out.extend_from_slice(&self.font);
+        out.extend_from_slice(&self.big_font);
+        out.extend_from_slice(&(self.program_end as u32).to_be_bytes());
+        out.push(self.audio_loaded as u8);
+
+        let length = (out.len() - payload_start) as u32;
+        out[length_pos..payload_start].copy_from_slice(&length.to_be_bytes());
+        let checksum = adler32(&out[payload_start..]);
+        out.extend_from_slice(&checksum.to_be_bytes());
+    }
+
+    // Like `save_state`, but a few hundred bytes for a typical program instead of 4KB+
+    #[cfg(feature = "compression")]
+    pub fn save_state_compressed(&self, out: &mut Vec<u8>) {
+        let mut state = Vec::new();
+        self.save_state(&mut state);
+
+        out.extend_from_slice(COMPRESSED_MAGIC);
+        out.extend_from_slice(&(state.len() as u32).to_be_bytes());
+        rle::compress(&state, out);
+    }
+
+    // Restores a snapshot made by `save_state` (or `save_state_compressed`),
+    // leaving the machine untouched on error
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        #[cfg(feature = "compression")]
+        if data.starts_with(COMPRESSED_MAGIC) {
+            return self.load_compressed_state(&data[COMPRESSED_MAGIC.len()..]);
+        }
+
+        if data.len() < HEADER_SIZE {
+            return Err(StateError::Truncated);
+        }
+        if &data[..4] != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+
+        let version = u16::from_be_bytes([data[4], data[5]]);
+        if version == 0 || version > VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        let length = u32::from_be_bytes([data[6], data[7], data[8], data[9]]) as usize;
+        let payload = data[HEADER_SIZE..]
+            .get(..length)
+            .ok_or(StateError::Truncated)?;
+        let checksum = data[HEADER_SIZE + length..]
+            .get(..CHECKSUM_SIZE)
+            .ok_or(StateError::Truncated)?;
+        if adler32(payload).to_be_bytes() != checksum {
+            return Err(StateError::ChecksumMismatch);
+        }
+
+        let mut reader = Reader { data: payload };
+
+        let pc = reader.u16()?;
+        let run_state = match reader.u8()? {
+            0 => RunState::Running,
+            1 => RunState::WaitingForVblank,
+            2 => RunState::Halted,
+            3 => RunState::Spinning,
+            _ => return Err(StateError::InvalidValue),
+        };
+
+        let ram_size = reader.u32()? as usize;
+        if !(DEFAULT_RAM_SIZE..=MAX_RAM_SIZE).contains(&ram_size) {
+            return Err(StateError::InvalidValue);
+        }
+        let ram = reader.bytes(ram_size)?;
+
+        let resolution = match reader.u8()? {
+            0 => Resolution::Low,
+            1 => Resolution::High,
+            _ => return Err(StateError::InvalidValue),
+        };
+        let plane_mask = reader.u8()?;
+        if plane_mask >> NUM_PLANES != 0 {
+            return Err(StateError::InvalidValue);
+        }
+        let screen = reader.bytes(NUM_PLANES * SCREEN_SIZE / 8)?;
+
+        let quirk_bits = reader.u8()?;
+        let quirk = |i: u8| quirk_bits & (1 << i) != 0;
+        let lores_dxy0 = match reader.u8()? {
+            0 => LoresDxy0::Sprite16x16,
+            1 => LoresDxy0::Sprite8x16,
+            _ => return Err(StateError::InvalidValue),
+        };
+        let quirks = Quirks {
+            shift_uses_vy: quirk(0),
+            load_store_increments_i: quirk(1),
+            jump_uses_vx: quirk(2),
+            vf_reset: quirk(3),
+            clip_sprites: quirk(4),
+            display_wait: quirk(5),
+            lores_dxy0,
+        };
+
+        let v_reg = reader.bytes(NUM_V_REGS)?;
+        let i_reg = reader.u16()?;
+        let mut stack = [0; STACK_SIZE];
+        for addr in stack.iter_mut() {
+            *addr = reader.u16()?;
+        }
+        let sp = reader.u8()? as u16;
+        if sp as usize > STACK_SIZE {
+            return Err(StateError::InvalidValue);
+        }
+        let dt = reader.u8()?;
+        let st = reader.u8()?;
+
+        let audio_pattern = reader.bytes(AUDIO_PATTERN_SIZE)?;
+        let audio_position = f32::from_bits(reader.u32()?);
+        if !(0.0..AUDIO_PATTERN_BITS).contains(&audio_position) {
+            return Err(StateError::InvalidValue);
+        }
+        let pitch = reader.u8()?;
+        let keys = reader.u16()?;
+
+        // Version 1 states keep the current random sequence
+        let rng = if version >= 2 {
+            let has_seed = reader.u8()?;
+            let seed = reader.u64()?;
+            let state = ((reader.u64()? as u128) << 64) | reader.u64()? as u128;
+            match has_seed {
+                0 => Some((None, state)),
+                1 => Some((Some(seed), state)),
+                _ => return Err(StateError::InvalidValue),
+            }
+        } else {
+            None
+        };
+
+        let extra = if version >= 3 {
+            let metrics = Metrics {
+                instructions: reader.u64()?,
+                frames: reader.u64()?,
+                draws: reader.u64()?,
+            };
+            let sounding = reader.bool()?;
+            let keys_polled = reader.u16()?;
+            let waited_for_key = reader.bool()?;
+            let font = reader.bytes(FONTSET_SIZE)?;
+            let big_font = reader.bytes(BIG_FONTSET_SIZE)?;
+            let program_end = reader.u32()? as usize;
+            if !(START_ADDR as usize..=ram_size).contains(&program_end) {
+                return Err(StateError::InvalidValue);
+            }
+            let audio_loaded = reader.bool()?;
+            Some(Extra {
+                metrics,
+                sounding,
+                keys_polled,
+                waited_for_key,
+                font,
+                big_font,
+                program_end,
+                audio_loaded,
+            })
+        } else {
+            None
+        };
+
+        // Everything is validated, commit
+        self.journal.clear();
+        self.pc = pc;
+        self.run_state = run_state;
+        self.ram_size = ram_size;
+        self.ram.clear();
+        self.ram.extend_from_slice(ram);
+        self.resolution = resolution;
+        self.plane_mask = plane_mask;
+        let row_bytes = resolution.width() / 8;
+        for (rows, bytes) in self
+            .screen
+            .iter_mut()
+            .zip(screen.chunks_exact(SCREEN_SIZE / 8))
+        {
+            *rows = [0; HIRES_SCREEN_HEIGHT];
+            let height = resolution.height();
+            for (row, bytes) in rows[..height].iter_mut().zip(bytes.chunks_exact(row_bytes)) {
+                *row = bytes.iter().fold(0, |row, &byte| (row << 8) | byte as u128);
+            }
+        }
+        self.mark_screen_dirty();
+        self.quirks = quirks;
+        self.v_reg.copy_from_slice(v_reg);
+        self.i_reg = i_reg;
+        self.stack = stack;
+        self.sp = sp;
+        self.dt = dt;
+        self.st = st;
+        self.audio_pattern.copy_from_slice(audio_pattern);
+        self.audio_position = audio_position;
+        self.pitch = pitch;
+        self.playback_rate = pitch_to_rate(pitch);
+        for (i, pressed) in self.keys.iter_mut().enumerate() {
+            *pressed = keys & (1 << i) != 0;
+        }
+        if let Some((seed, rng_state)) = rng {
+            self.seed = seed;
+            self.rng.set_state(rng_state);
+        }
+        if let Some(extra) = extra {
+            self.metrics = extra.metrics;
+            // The audio sink hears about the sound the state has
+            #[cfg(feature = "alloc")]
+            match (self.sounding, extra.sounding) {
+                (false, true) => self.notify_audio_sink(SoundEdge::Started),
+                (true, false) => self.notify_audio_sink(SoundEdge::Stopped),
+                _ => {}
+            }
+            self.sounding = extra.sounding;
+            self.keys_polled = extra.keys_polled;
+            self.waited_for_key = extra.waited_for_key;
+            self.font.copy_from_slice(extra.font);
+            self.big_font.copy_from_slice(extra.big_font);
+            self.program_end = extra.program_end;
+            self.audio_loaded = extra.audio_loaded;
+        } else {
+            // Older states only have the pattern to go by
+            self.audio_loaded = self.audio_pattern != [0; AUDIO_PATTERN_SIZE];
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "compression")]
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    fn load_compressed_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut reader = Reader { data };
+        let length = reader.u32()? as usize;
+
+        let mut state = Vec::new();
+        rle::decompress(reader.data, length, &mut state).ok_or(StateError::Truncated)?;
+        if state.len() != length {
+            return Err(StateError::Truncated);
+        }
+
+        self.load_state(&state)
+    }
+}
+
+// Version 3 fields of a state being loaded
+struct Extra<'a> {
+    metrics: Metrics,
+    sounding: bool,
+    keys_polled: u16,
+    waited_for_key: bool,
+    font: &'a [u8],
+    big_font: &'a [u8],
+    program_end: usize,
+    audio_loaded: bool,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(StateError::Truncated);
+        }
+
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::InvalidValue),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, StateError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, StateError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+
+    for &byte in data {
+        a = (a + byte as u32) % MOD_ADLER;
+        b = (b + a) % MOD_ADLER;
+    }
+
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Waits for a key with the font switched, after a few frames
+    const PROGRAM: [u8; 6] = [0x00, 0xE0, 0xF0, 0x0A, 0x12, 0x02];
+
+    fn waiting_emu() -> Emu {
+        let mut emu = Emu::new();
+        emu.load(&PROGRAM).unwrap();
+        emu.font[0] = 0x55;
+        emu.big_font[0] = 0xAA;
+        emu.st = 10;
+        for _ in 0..3 {
+            emu.run_frame(10).unwrap();
+        }
+        emu
+    }
+
+    #[test]
+    fn version_3_keeps_frames_flags_fonts_and_program_end() {
+        let emu = waiting_emu();
+        let mut state = Vec::new();
+        emu.save_state(&mut state);
+
+        let mut loaded = Emu::new();
+        loaded.load_state(&state).unwrap();
+        assert_eq!(loaded.metrics, emu.metrics);
+        assert_eq!(loaded.metrics.frames, 3);
+        assert!(loaded.sounding);
+        assert_eq!(loaded.keys_polled, emu.keys_polled);
+        assert!(loaded.waited_for_key);
+        assert_eq!(loaded.font, emu.font);
+        assert_eq!(loaded.big_font, emu.big_font);
+        assert_eq!(loaded.program_end, START_ADDR as usize + PROGRAM.len());
+    }
+
+    #[test]
+    fn screens_survive_a_round_trip() {
+        for resolution in [Resolution::Low, Resolution::High] {
+            let mut emu = Emu::new();
+            emu.set_resolution(resolution);
+            let width = resolution.width();
+            emu.screen[0][0] = 1 << (width - 1);
+            emu.screen[1][resolution.height() - 1] = 0xA5;
+            let mut state = Vec::new();
+            emu.save_state(&mut state);
+
+            let mut loaded = Emu::new();
+            loaded.screen[0][40] = 1;
+            loaded.load_state(&state).unwrap();
+            assert_eq!(loaded.resolution, resolution);
+            assert_eq!(loaded.screen, emu.screen);
+        }
+    }
+
+    #[test]
+    fn version_2_states_still_load() {
+        let emu = waiting_emu();
+        let mut state = Vec::new();
+        emu.save_state(&mut state);
+
+        // Cut the version 3 fields and seal it again as version 2
+        let v3_size = 3 * 8 + 1 + 2 + 1 + FONTSET_SIZE + BIG_FONTSET_SIZE + 4 + 1;
+        let length = state.len() - HEADER_SIZE - CHECKSUM_SIZE - v3_size;
+        let mut payload = state[HEADER_SIZE..HEADER_SIZE + length].to_vec();
+        let mut v2 = Vec::from(*MAGIC);
+        v2.extend_from_slice(&2u16.to_be_bytes());
+        v2.extend_from_slice(&(length as u32).to_be_bytes());
+        let checksum = adler32(&payload);
+        v2.append(&mut payload);
+        v2.extend_from_slice(&checksum.to_be_bytes());
+
+        let mut loaded = Emu::new();
+        loaded.load_state(&v2).unwrap();
+        assert_eq!(loaded.pc, emu.pc);
+        assert_eq!(loaded.metrics.frames, 0);
+        assert_eq!(loaded.font, Emu::new().font);
+    }
+
+    #[test]
+    fn program_end_past_the_ram_is_rejected() {
+        let mut emu = waiting_emu();
+        emu.program_end = DEFAULT_RAM_SIZE + 1;
+        let mut state = Vec::new();
+        emu.save_state(&mut state);
+
+        let mut loaded = Emu::new();
+        assert_eq!(loaded.load_state(&state), Err(StateError::InvalidValue));
+        assert_eq!(loaded.program_end, Emu::new().program_end);
+    }
+}
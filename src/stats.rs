@@ -0,0 +1,115 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::*;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Stats {
+    opcodes_enabled: bool,
+    opcodes: BTreeMap<&'static str, u64>, // by Instruction::pattern()
+    heatmap: Vec<u32>,                    // by address, empty while disabled
+}
+
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    // Counting is off by default, turning it off keeps the counts so far
+    pub fn set_opcode_stats(&mut self, enabled: bool) {
+        self.stats.opcodes_enabled = enabled;
+    }
+
+    pub fn clear_opcode_stats(&mut self) {
+        self.stats.opcodes.clear();
+    }
+
+    // (pattern, count) for every kind executed at least once, by pattern
+    pub fn opcode_stats(&self) -> impl Iterator<Item = (&'static str, u64)> + '_ {
+        self.stats
+            .opcodes
+            .iter()
+            .map(|(&pattern, &count)| (pattern, count))
+    }
+
+    // One line per kind, most executed first:
+    //
+    //     7XNN           1520  38.0%
+    pub fn opcode_report(&self) -> String {
+        let total: u64 = self.stats.opcodes.values().sum();
+        let mut counts: Vec<_> = self.opcode_stats().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+        let mut report = String::new();
+        for (pattern, count) in counts {
+            let percent = count as f64 * 100.0 / total as f64;
+            let _ = writeln!(report, "{:<9} {:>9} {:>5.1}%", pattern, count, percent);
+        }
+        report
+    }
+
+    // Counting is off by default, turning it off drops the counts so far
+    pub fn set_heatmap(&mut self, enabled: bool) {
+        let heatmap = &mut self.stats.heatmap;
+        if !enabled {
+            *heatmap = Vec::new();
+        } else if heatmap.is_empty() {
+            *heatmap = vec![0; MAX_RAM_SIZE];
+        }
+    }
+
+    pub fn clear_heatmap(&mut self) {
+        self.stats.heatmap.fill(0);
+    }
+
+    // (address, times executed) for every address executed at least once
+    pub fn heatmap(&self) -> impl Iterator<Item = (u16, u32)> + '_ {
+        self.stats
+            .heatmap
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(addr, &count)| (addr as u16, count))
+    }
+
+    // `addr,count` lines after a header, addresses in hex
+    pub fn heatmap_csv(&self) -> String {
+        let mut csv = String::from("addr,count\n");
+        for (addr, count) in self.heatmap() {
+            let _ = writeln!(csv, "{:#05X},{}", addr, count);
+        }
+        csv
+    }
+
+    // An object mapping hex addresses to counts, e.g. {"0x200":1,"0x202":60}
+    pub fn heatmap_json(&self) -> String {
+        let mut json = String::from("{");
+        for (index, (addr, count)) in self.heatmap().enumerate() {
+            let separator = if index == 0 { "" } else { "," };
+            let _ = write!(json, "{}\"{:#05X}\":{}", separator, addr, count);
+        }
+        json.push('}');
+        json
+    }
+
+    // Linear disassembly of the loaded ROM with each line's count in front
+    pub fn heatmap_listing(&self) -> String {
+        let start = START_ADDR as usize;
+        let mut listing = String::new();
+
+        for line in disasm::disassemble(&self.ram[start..self.program_end], START_ADDR) {
+            let count = self.stats.heatmap.get(line.addr as usize).copied();
+            let _ = writeln!(listing, "{:>10}  {}", count.unwrap_or(0), line);
+        }
+        listing
+    }
+
+    // Called after executing the instruction at `pc`
+    pub(crate) fn record_stats(&mut self, pc: u16, instruction: Instruction) {
+        if self.stats.opcodes_enabled {
+            *self.stats.opcodes.entry(instruction.pattern()).or_default() += 1;
+        }
+        if let Some(count) = self.stats.heatmap.get_mut(pc as usize) {
+            *count = count.saturating_add(1);
+        }
+    }
+}
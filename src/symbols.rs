@@ -0,0 +1,123 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+// Label names for addresses, from the assembler or a symbol file, one
+// `0x200 main` pair per line. Either order is accepted when parsing and
+// `#`/`;` start comments. Displaying the table produces the same format.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    names: BTreeMap<u16, String>,
+    addresses: BTreeMap<String, u16>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SymbolError {
+    pub line: usize, // 1-based
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: expected an address and a name", self.line)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SymbolError {}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, SymbolError> {
+        let mut table = Self::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let code = line.split(['#', ';']).next().unwrap_or("");
+            let mut words = code.split_whitespace();
+            let (first, second) = match (words.next(), words.next(), words.next()) {
+                (None, ..) => continue,
+                (Some(first), Some(second), None) => (first, second),
+                _ => return Err(SymbolError { line: index + 1 }),
+            };
+
+            let (addr, name) = match (parse_address(first), parse_address(second)) {
+                (Some(addr), None) => (addr, second),
+                (None, Some(addr)) => (addr, first),
+                _ => return Err(SymbolError { line: index + 1 }),
+            };
+            table.insert(name, addr);
+        }
+
+        Ok(table)
+    }
+
+    // Replaces any existing symbol with the same name. When several names share
+    // an address the last one inserted is displayed.
+    pub fn insert(&mut self, name: &str, addr: u16) {
+        if let Some(old) = self.addresses.insert(name.to_string(), addr) {
+            if self.names.get(&old).map(String::as_str) == Some(name) {
+                self.names.remove(&old);
+            }
+        }
+        self.names.insert(addr, name.to_string());
+    }
+
+    pub fn name(&self, addr: u16) -> Option<&str> {
+        self.names.get(&addr).map(String::as_str)
+    }
+
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    // Symbols sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u16)> {
+        self.addresses
+            .iter()
+            .map(|(name, &addr)| (name.as_str(), addr))
+    }
+}
+
+// The label map of an assembled `Program`
+impl From<&BTreeMap<String, u16>> for SymbolTable {
+    fn from(symbols: &BTreeMap<String, u16>) -> Self {
+        let mut table = Self::new();
+        for (name, &addr) in symbols {
+            table.insert(name, addr);
+        }
+        table
+    }
+}
+
+impl fmt::Display for SymbolTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut by_addr: Vec<_> = self.iter().collect();
+        by_addr.sort_by_key(|&(name, addr)| (addr, name));
+
+        for (name, addr) in by_addr {
+            writeln!(f, "{:#06X} {}", addr, name)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_address(text: &str) -> Option<u16> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u16::from_str_radix(hex, 16).ok()
+    } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+        text.parse().ok()
+    } else {
+        None
+    }
+}
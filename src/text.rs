@@ -0,0 +1,60 @@
+use core::fmt;
+
+use crate::*;
+
+const HALF_BLOCKS: [char; 4] = [' ', '▀', '▄', '█']; // top, bottom pixel
+const BRAILLE_BLANK: u32 = 0x2800;
+
+// Braille dot bits by row of the cell, for the left and right column
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+// How render_text() packs pixels into characters
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextStyle {
+    #[default]
+    HalfBlock, // 1x2 pixels per character, 64x16 for the low resolution screen
+    Braille, // 2x4 pixels per character, 32x8
+}
+
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    // The screen as lines of Unicode characters for terminals, each ending
+    // with a newline. A pixel is lit if it is in either plane.
+    pub fn render_text(&self, out: &mut impl fmt::Write, style: TextStyle) -> fmt::Result {
+        let (width, height) = (self.resolution.width(), self.resolution.height());
+        let lit = |x: usize, y: usize| {
+            let row = self.screen[0][y] | self.screen[1][y];
+            (row >> (width - 1 - x)) & 1 != 0
+        };
+
+        match style {
+            TextStyle::HalfBlock => {
+                for y in (0..height).step_by(2) {
+                    for x in 0..width {
+                        let pixels = lit(x, y) as usize | (lit(x, y + 1) as usize) << 1;
+                        out.write_char(HALF_BLOCKS[pixels])?;
+                    }
+                    out.write_char('\n')?;
+                }
+            }
+            TextStyle::Braille => {
+                for y in (0..height).step_by(4) {
+                    for x in (0..width).step_by(2) {
+                        let mut dots = 0;
+                        for (row, bits) in BRAILLE_DOTS.iter().enumerate() {
+                            for (column, bit) in bits.iter().enumerate() {
+                                if lit(x + column, y + row) {
+                                    dots |= bit;
+                                }
+                            }
+                        }
+                        // The braille block is all valid characters
+                        out.write_char(char::from_u32(BRAILLE_BLANK | dots).unwrap_or(' '))?;
+                    }
+                    out.write_char('\n')?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use alloc::collections::VecDeque;
+use alloc::format;
+use core::fmt;
+
+use crate::*;
+
+// An executed instruction with the registers it left behind, formatted as
+//
+//     0x208: D01F  DRW V0, V1, 15    V=0C 08 00 ... 00 I=0x22A SP=0 DT=00 ST=00
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub instruction: Instruction,
+    pub v_reg: [u8; NUM_V_REGS],
+    pub i_reg: u16,
+    pub sp: u16,
+    pub dt: u8,
+    pub st: u8,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:#05X}: {:04X}  {:<16}  V=",
+            self.pc,
+            self.instruction.encode(),
+            format!("{}", self.instruction)
+        )?;
+        for (x, value) in self.v_reg.iter().enumerate() {
+            let separator = if x == 0 { "" } else { " " };
+            write!(f, "{}{:02X}", separator, value)?;
+        }
+        write!(
+            f,
+            " I={:#05X} SP={} DT={:02X} ST={:02X}",
+            self.i_reg, self.sp, self.dt, self.st
+        )
+    }
+}
+
+// Ring buffer of the most recently executed instructions
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Tracer {
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+}
+
+impl<F: FlagStorage, R: RandomSource> Emu<F, R> {
+    // How many instructions the trace keeps, 0 (the default) disables tracing
+    pub fn set_trace_capacity(&mut self, capacity: usize) {
+        let tracer = &mut self.tracer;
+        tracer.capacity = capacity;
+        while tracer.entries.len() > capacity {
+            tracer.entries.pop_front();
+        }
+    }
+
+    // Oldest first
+    pub fn trace(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.tracer.entries.iter()
+    }
+
+    // Removes and returns the trace, oldest first, for streaming it elsewhere
+    pub fn take_trace(&mut self) -> impl Iterator<Item = TraceEntry> + '_ {
+        self.tracer.entries.drain(..)
+    }
+
+    // Called after executing the instruction at `pc`
+    pub(crate) fn record_trace(&mut self, pc: u16, instruction: Instruction) {
+        if self.tracer.capacity == 0 {
+            return;
+        }
+
+        let entry = TraceEntry {
+            pc,
+            instruction,
+            v_reg: self.v_reg,
+            i_reg: self.i_reg,
+            sp: self.sp,
+            dt: self.dt,
+            st: self.st,
+        };
+
+        let tracer = &mut self.tracer;
+        if tracer.entries.len() == tracer.capacity {
+            tracer.entries.pop_front();
+        }
+        tracer.entries.push_back(entry);
+    }
+}
@@ -0,0 +1,101 @@
+// Captures the sound of a session for saving as a WAV file: 16-bit PCM, one
+// channel, at the rate the samples were rendered at.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+use std::vec::Vec;
+
+use crate::*;
+
+const HEADER_SIZE: u32 = 44;
+const BYTES_PER_SAMPLE: u32 = 2;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AudioRecorder {
+    sample_rate: u32,
+    samples: Vec<f32>,
+}
+
+impl AudioRecorder {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            samples: Vec::new(),
+        }
+    }
+
+    // Emu::render_audio() that keeps a copy of what it rendered
+    pub fn render_audio<F: FlagStorage, R: RandomSource>(
+        &mut self,
+        emu: &mut Emu<F, R>,
+        samples: &mut [f32],
+    ) {
+        emu.render_audio(samples, self.sample_rate);
+        self.record(samples);
+    }
+
+    // For samples rendered elsewhere, at the recorder's sample rate
+    pub fn record(&mut self, samples: &[f32]) {
+        self.samples.extend_from_slice(samples);
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.samples.len() as f64 / self.sample_rate.max(1) as f64)
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    // Samples outside -1.0..=1.0 are clipped. Fails with InvalidInput for
+    // recordings too long for a WAV file, about 13 hours at 44.1kHz.
+    pub fn write_wav(&self, out: impl Write) -> io::Result<()> {
+        let data_size = u32::try_from(self.samples.len())
+            .ok()
+            .and_then(|len| len.checked_mul(BYTES_PER_SAMPLE))
+            .filter(|size| *size <= u32::MAX - HEADER_SIZE)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "recording too long"))?;
+        let mut out = BufWriter::new(out);
+
+        out.write_all(b"RIFF")?;
+        out.write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        out.write_all(b"WAVE")?;
+
+        out.write_all(b"fmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?; // PCM
+        out.write_all(&1u16.to_le_bytes())?; // channels
+        out.write_all(&self.sample_rate.to_le_bytes())?;
+        out.write_all(
+            &self
+                .sample_rate
+                .saturating_mul(BYTES_PER_SAMPLE)
+                .to_le_bytes(),
+        )?;
+        out.write_all(&(BYTES_PER_SAMPLE as u16).to_le_bytes())?; // block size
+        out.write_all(&(BYTES_PER_SAMPLE as u16 * 8).to_le_bytes())?; // bits
+
+        out.write_all(b"data")?;
+        out.write_all(&data_size.to_le_bytes())?;
+        for sample in &self.samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            out.write_all(&value.to_le_bytes())?;
+        }
+
+        out.flush()
+    }
+
+    pub fn save_wav(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write_wav(File::create(path)?)
+    }
+}